@@ -0,0 +1,241 @@
+//! The Twitter API v1.1 via `egg_mode`
+use async_trait::async_trait;
+use egg_mode::{
+    cursor::{self, CursorIter},
+    direct,
+    list::{self, List, ListID},
+    tweet::{self, Tweet},
+    user::{self, TwitterUser},
+    Token,
+};
+use eyre::Result;
+use serde::de::DeserializeOwned;
+
+use super::{
+    conversation_id, map_error, Cursor, LookedUp, MessageEvent, Page, RelatedUser, Relation,
+    Timeline, TwitterApi,
+};
+use crate::crawler::DownloadInstruction;
+use crate::helpers::media_instruction;
+use crate::storage::{DirectMessage, ListKind, TweetId, UserId};
+
+/// The maximum page size of the timelines
+const TIMELINE_PAGE_SIZE: i32 = 200;
+const CURSOR_PAGE_SIZE: i32 = 100;
+const SEARCH_PAGE_SIZE: u32 = 100;
+const MESSAGE_PAGE_SIZE: u32 = 50;
+/// The error code of a tweet that doesn't exist (anymore)
+const NO_STATUS_FOUND: i32 = 144;
+
+#[derive(Debug)]
+pub struct EggModeApi {
+    token: Token,
+}
+
+impl EggModeApi {
+    pub fn new(token: Token) -> Self {
+        Self { token }
+    }
+}
+
+/// Load one page of a cursored endpoint
+async fn call_cursor<T>(mut iter: CursorIter<T>, cursor: Option<Cursor>) -> Result<Page<T::Item>>
+where
+    T: cursor::Cursor + DeserializeOwned,
+{
+    iter.next_cursor = match cursor {
+        Some(Cursor::Next(cursor)) => cursor,
+        _ => -1,
+    };
+    let response = iter.call().await.map_err(map_error)?;
+    let next_cursor = response.response.next_cursor_id();
+    Ok(Page {
+        next: (next_cursor != 0).then_some(Cursor::Next(next_cursor)),
+        rate_limit: Some(response.rate_limit_status),
+        items: response.response.into_inner(),
+    })
+}
+
+#[async_trait]
+impl TwitterApi for EggModeApi {
+    async fn timeline(&self, timeline: Timeline, cursor: Option<Cursor>) -> Result<Page<Tweet>> {
+        let timeline = match timeline {
+            Timeline::User(id) => tweet::user_timeline(id, true, true, &self.token),
+            Timeline::Mentions => tweet::mentions_timeline(&self.token),
+            Timeline::Likes(id) => tweet::liked_by(id, &self.token),
+            Timeline::Bookmarks => {
+                eyre::bail!("The Twitter API v1.1 has no bookmarks, please use the API v2")
+            }
+        }
+        .with_page_size(TIMELINE_PAGE_SIZE);
+        let max_id = match cursor {
+            Some(Cursor::OlderThan(id)) => Some(id.saturating_sub(1)),
+            _ => None,
+        };
+        let response = timeline.call(None, max_id).await.map_err(map_error)?;
+        Ok(Page {
+            next: response.response.last().map(|t| Cursor::OlderThan(t.id)),
+            rate_limit: Some(response.rate_limit_status),
+            items: response.response,
+        })
+    }
+
+    async fn user_ids(
+        &self,
+        relation: Relation,
+        user_id: UserId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<RelatedUser>> {
+        let iter = match relation {
+            Relation::Followers => user::followers_ids(user_id, &self.token),
+            Relation::Follows => user::friends_ids(user_id, &self.token),
+        };
+        let page = call_cursor(iter.with_page_size(CURSOR_PAGE_SIZE), cursor).await?;
+        Ok(Page {
+            items: page.items.into_iter().map(RelatedUser::id).collect(),
+            next: page.next,
+            rate_limit: page.rate_limit,
+        })
+    }
+
+    async fn user(&self, id: UserId) -> Result<TwitterUser> {
+        let response = user::show(id, &self.token).await.map_err(map_error)?;
+        Ok(response.response)
+    }
+
+    async fn users(&self, ids: &[UserId]) -> Result<Page<TwitterUser>> {
+        let response = user::lookup(ids.to_vec(), &self.token)
+            .await
+            .map_err(map_error)?;
+        Ok(Page {
+            items: response.response,
+            next: None,
+            rate_limit: Some(response.rate_limit_status),
+        })
+    }
+
+    async fn lists(
+        &self,
+        user_id: UserId,
+        kind: ListKind,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<List>> {
+        let iter = match kind {
+            ListKind::Created => list::ownerships(user_id, &self.token),
+            ListKind::Subscribed => list::subscriptions(user_id, &self.token),
+            ListKind::Member => list::memberships(user_id, &self.token),
+        };
+        call_cursor(iter.with_page_size(CURSOR_PAGE_SIZE), cursor).await
+    }
+
+    async fn list_members(
+        &self,
+        list_id: u64,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<TwitterUser>> {
+        let iter =
+            list::members(ListID::from_id(list_id), &self.token).with_page_size(CURSOR_PAGE_SIZE);
+        call_cursor(iter, cursor).await
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        since_id: TweetId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<Tweet>> {
+        let mut search = egg_mode::search::search(query.to_string())
+            .since_tweet(since_id)
+            .count(SEARCH_PAGE_SIZE);
+        if let Some(Cursor::OlderThan(id)) = cursor {
+            search = search.max_tweet(id.saturating_sub(1));
+        }
+        let response = search.call(&self.token).await.map_err(map_error)?;
+        let statuses = response.response.statuses;
+        Ok(Page {
+            next: statuses.last().map(|t| Cursor::OlderThan(t.id)),
+            rate_limit: Some(response.rate_limit_status),
+            items: statuses,
+        })
+    }
+
+    async fn lookup(&self, ids: &[TweetId]) -> Result<Page<(TweetId, LookedUp)>> {
+        let response = tweet::lookup_map(ids.to_vec(), &self.token)
+            .await
+            .map_err(map_error)?;
+        let mut rate_limit = response.rate_limit_status;
+        let mut items = Vec::new();
+        for (id, tweet) in response.response {
+            let looked_up = match tweet {
+                Some(tweet) => LookedUp::Found(Box::new(tweet)),
+                // The lookup leaves out deleted and protected tweets alike,
+                // only the error of a single tweet tells them apart
+                None => match tweet::show(id, &self.token).await {
+                    Ok(response) => {
+                        rate_limit = response.rate_limit_status;
+                        LookedUp::Found(Box::new(response.response))
+                    }
+                    Err(egg_mode::error::Error::TwitterError(_, errors))
+                        if errors.errors.iter().any(|e| e.code == NO_STATUS_FOUND) =>
+                    {
+                        LookedUp::Deleted
+                    }
+                    Err(egg_mode::error::Error::TwitterError(..)) => LookedUp::Unavailable,
+                    Err(e) => return Err(map_error(e)),
+                },
+            };
+            items.push((id, looked_up));
+        }
+        Ok(Page {
+            items,
+            next: None,
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn direct_messages(&self, cursor: Option<Cursor>) -> Result<Page<MessageEvent>> {
+        let mut timeline = direct::list(&self.token).with_page_size(MESSAGE_PAGE_SIZE);
+        if let Some(Cursor::Token(token)) = cursor {
+            timeline.next_cursor = Some(token);
+        }
+        let response = timeline.next_page().await.map_err(map_error)?;
+        let rate_limit = response.rate_limit_status;
+        let items = response
+            .response
+            .into_iter()
+            .map(MessageEvent::from)
+            .collect();
+        Ok(Page {
+            items,
+            next: timeline.next_cursor.map(Cursor::Token),
+            rate_limit: Some(rate_limit),
+        })
+    }
+}
+
+/// The API v1.1 only returns one-on-one conversations
+impl From<direct::DirectMessage> for MessageEvent {
+    fn from(message: direct::DirectMessage) -> Self {
+        let media = message
+            .attachment
+            .as_ref()
+            .and_then(media_instruction)
+            .and_then(|instruction| match instruction {
+                DownloadInstruction::Image(url) => Some(url),
+                DownloadInstruction::Movie(_, url) => Some(url),
+                _ => None,
+            });
+        MessageEvent {
+            conversation_id: conversation_id(message.sender_id, message.recipient_id),
+            participants: vec![message.sender_id, message.recipient_id],
+            message: DirectMessage {
+                id: message.id,
+                sender_id: message.sender_id,
+                recipient_id: Some(message.recipient_id),
+                created_at: message.created_at,
+                text: message.text,
+                media: media.into_iter().collect(),
+            },
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Serves recorded responses from a folder instead of calling Twitter.
+//! The files contain the JSON that TwitVault stores (e.g. the `tweets` of a
+//! data file), a missing file is an empty response:
+//!
+//! - `timeline-user-{id}.json`, `timeline-mentions.json`,
+//!   `timeline-likes-{id}.json` and `timeline-bookmarks.json`: tweets,
+//!   newest first
+//! - `followers-{id}.json` and `follows-{id}.json`: user ids
+//! - `users.json`: the profiles for the user lookups
+//! - `lists-{id}.json` (created), `lists-subscribed-{id}.json`,
+//!   `lists-member-{id}.json` and `list-members-{list id}.json`
+//! - `search.json`: tweets for the reply search
+//! - `direct-messages.json`: `MessageEvent`s, newest first
+//! - `account.json`: the `username`, `user_id` and `crawl_options` of the
+//!   crawled account, see `Config::for_fixtures`
+//! - `media/`: the files of the media downloads, named after the last part
+//!   of their url
+//!
+//! Tweets that are in none of the timelines or the search are reported as
+//! deleted by `lookup`.
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use egg_mode::{list::List, tweet::Tweet, user::TwitterUser};
+use eyre::{eyre, Result};
+use serde::de::DeserializeOwned;
+
+use super::{Cursor, LookedUp, MessageEvent, Page, RelatedUser, Relation, Timeline, TwitterApi};
+use crate::storage::{ListKind, TweetId, UserId};
+
+const TIMELINE_PAGE_SIZE: usize = 200;
+const CURSOR_PAGE_SIZE: usize = 100;
+
+#[derive(Debug)]
+pub struct FixtureApi {
+    folder: PathBuf,
+}
+
+impl FixtureApi {
+    pub fn new(folder: impl AsRef<Path>) -> Result<Self> {
+        let folder = folder.as_ref().to_path_buf();
+        if !folder.is_dir() {
+            eyre::bail!("Could not find the fixtures folder {}", folder.display())
+        }
+        Ok(Self { folder })
+    }
+
+    fn read<T: DeserializeOwned>(&self, name: &str) -> Result<Vec<T>> {
+        let path = self.folder.join(name);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let input = std::fs::read(&path)?;
+        serde_json::from_slice(&input).map_err(|e| eyre!("Invalid fixture {}: {e}", path.display()))
+    }
+
+    /// All tweets that can be looked up
+    fn known_tweets(&self) -> Result<Vec<Tweet>> {
+        let mut tweets = self.read("search.json")?;
+        for entry in std::fs::read_dir(&self.folder)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if name.starts_with("timeline-") && name.ends_with(".json") {
+                tweets.append(&mut self.read(&name)?);
+            }
+        }
+        Ok(tweets)
+    }
+}
+
+/// Pages through ids or profiles. The cursor is the offset of the next page
+fn offset_page<T>(mut items: Vec<T>, cursor: Option<Cursor>) -> Page<T> {
+    let start = match cursor {
+        Some(Cursor::Next(offset)) => usize::try_from(offset).unwrap_or_default(),
+        _ => 0,
+    }
+    .min(items.len());
+    let end = (start + CURSOR_PAGE_SIZE).min(items.len());
+    Page {
+        next: (end < items.len()).then_some(Cursor::Next(end as i64)),
+        items: items.drain(start..end).collect(),
+        rate_limit: None,
+    }
+}
+
+#[async_trait]
+impl TwitterApi for FixtureApi {
+    async fn timeline(&self, timeline: Timeline, cursor: Option<Cursor>) -> Result<Page<Tweet>> {
+        let name = match timeline {
+            Timeline::User(id) => format!("timeline-user-{id}.json"),
+            Timeline::Mentions => "timeline-mentions.json".to_string(),
+            Timeline::Likes(id) => format!("timeline-likes-{id}.json"),
+            Timeline::Bookmarks => "timeline-bookmarks.json".to_string(),
+        };
+        let older: Vec<Tweet> = self
+            .read::<Tweet>(&name)?
+            .into_iter()
+            .filter(|tweet| match cursor {
+                Some(Cursor::OlderThan(id)) => tweet.id < id,
+                _ => true,
+            })
+            .collect();
+        let has_more = older.len() > TIMELINE_PAGE_SIZE;
+        let items: Vec<Tweet> = older.into_iter().take(TIMELINE_PAGE_SIZE).collect();
+        Ok(Page {
+            next: items
+                .last()
+                .filter(|_| has_more)
+                .map(|tweet| Cursor::OlderThan(tweet.id)),
+            items,
+            rate_limit: None,
+        })
+    }
+
+    async fn user_ids(
+        &self,
+        relation: Relation,
+        user_id: UserId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<RelatedUser>> {
+        let name = match relation {
+            Relation::Followers => format!("followers-{user_id}.json"),
+            Relation::Follows => format!("follows-{user_id}.json"),
+        };
+        let page = offset_page(self.read(&name)?, cursor);
+        Ok(Page {
+            items: page.items.into_iter().map(RelatedUser::id).collect(),
+            next: page.next,
+            rate_limit: page.rate_limit,
+        })
+    }
+
+    async fn user(&self, id: UserId) -> Result<TwitterUser> {
+        self.read::<TwitterUser>("users.json")?
+            .into_iter()
+            .find(|user| user.id == id)
+            .ok_or_else(|| eyre!("Unknown user {id}"))
+    }
+
+    async fn users(&self, ids: &[UserId]) -> Result<Page<TwitterUser>> {
+        let mut users = self.read::<TwitterUser>("users.json")?;
+        users.retain(|user| ids.contains(&user.id));
+        Ok(Page {
+            items: users,
+            next: None,
+            rate_limit: None,
+        })
+    }
+
+    async fn lists(
+        &self,
+        user_id: UserId,
+        kind: ListKind,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<List>> {
+        let name = match kind {
+            ListKind::Created => format!("lists-{user_id}.json"),
+            ListKind::Subscribed => format!("lists-subscribed-{user_id}.json"),
+            ListKind::Member => format!("lists-member-{user_id}.json"),
+        };
+        Ok(offset_page(self.read(&name)?, cursor))
+    }
+
+    async fn list_members(
+        &self,
+        list_id: u64,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<TwitterUser>> {
+        Ok(offset_page(
+            self.read(&format!("list-members-{list_id}.json"))?,
+            cursor,
+        ))
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        since_id: TweetId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<Tweet>> {
+        // Only the `to:` query of the reply search is supported
+        let to = query.strip_prefix("to:");
+        let mut tweets = self.read::<Tweet>("search.json")?;
+        tweets.retain(|tweet| {
+            let addressed = match (to, &tweet.in_reply_to_screen_name) {
+                (None, _) => true,
+                (Some(to), Some(name)) => to.eq_ignore_ascii_case(name),
+                (Some(_), None) => false,
+            };
+            addressed && tweet.id > since_id
+        });
+        Ok(offset_page(tweets, cursor))
+    }
+
+    async fn lookup(&self, ids: &[TweetId]) -> Result<Page<(TweetId, LookedUp)>> {
+        let known = self.known_tweets()?;
+        let items = ids
+            .iter()
+            .map(|id| {
+                let looked_up = match known.iter().find(|tweet| tweet.id == *id) {
+                    Some(tweet) => LookedUp::Found(Box::new(tweet.clone())),
+                    None => LookedUp::Deleted,
+                };
+                (*id, looked_up)
+            })
+            .collect();
+        Ok(Page {
+            items,
+            next: None,
+            rate_limit: None,
+        })
+    }
+
+    async fn direct_messages(&self, cursor: Option<Cursor>) -> Result<Page<MessageEvent>> {
+        Ok(offset_page(self.read("direct-messages.json")?, cursor))
+    }
+}
@@ -0,0 +1,196 @@
+//! The Twitter API calls the crawler needs. `EggModeApi` talks to the
+//! Twitter API v1.1, `TwitterV2Api` to the API v2. `FixtureApi` serves
+//! recorded responses from a folder, so that a crawl or a sync can run offline.
+mod egg;
+mod fixtures;
+mod v2;
+
+use async_trait::async_trait;
+use egg_mode::{list::List, tweet::Tweet, user::TwitterUser, RateLimit};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PagingPosition;
+use crate::storage::{DirectMessage, ListKind, TweetId, UserId};
+
+pub use egg::EggModeApi;
+pub use fixtures::FixtureApi;
+pub use v2::TwitterV2Api;
+
+/// Where a paged request continues
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cursor {
+    /// Timelines continue with the tweets older than this id
+    OlderThan(TweetId),
+    /// Cursored endpoints (followers, lists, ...) continue at this cursor
+    Next(i64),
+    /// The API v2 continues every paged request with a pagination token
+    Token(String),
+}
+
+impl Cursor {
+    /// Continue a timeline at a stored paging position
+    pub fn older_than(position: PagingPosition) -> Self {
+        match position {
+            PagingPosition::Id(id) => Cursor::OlderThan(id),
+            PagingPosition::Token(token) => Cursor::Token(token),
+        }
+    }
+
+    /// Continue a cursored endpoint at a stored paging position
+    pub fn next(position: PagingPosition) -> Self {
+        match position {
+            PagingPosition::Id(cursor) => Cursor::Next(cursor as i64),
+            PagingPosition::Token(token) => Cursor::Token(token),
+        }
+    }
+
+    /// The value that is stored as the paging position
+    pub fn position(&self) -> Option<PagingPosition> {
+        match self {
+            Cursor::OlderThan(id) => Some(PagingPosition::Id(*id)),
+            Cursor::Next(cursor) => u64::try_from(*cursor).ok().map(PagingPosition::Id),
+            Cursor::Token(token) => Some(PagingPosition::Token(token.clone())),
+        }
+    }
+}
+
+/// One page of a paged request
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Where the next page starts. `None` after the last page
+    pub next: Option<Cursor>,
+    /// The rate limit after this request, if the API reports one
+    pub rate_limit: Option<RateLimit>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timeline {
+    /// The tweets of a user, including replies and retweets
+    User(UserId),
+    /// The mentions of the authenticated user
+    Mentions,
+    /// The tweets a user liked
+    Likes(UserId),
+    /// The bookmarks of the authenticated user
+    Bookmarks,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Followers,
+    /// The accounts a user follows
+    Follows,
+}
+
+/// The result of looking up a tweet
+#[derive(Debug)]
+pub enum LookedUp {
+    Found(Box<Tweet>),
+    /// The tweet doesn't exist anymore
+    Deleted,
+    /// The tweet exists, but can't be seen, e.g. because the author became
+    /// protected or was suspended
+    Unavailable,
+}
+
+impl LookedUp {
+    pub fn found(self) -> Option<Tweet> {
+        match self {
+            LookedUp::Found(tweet) => Some(*tweet),
+            _ => None,
+        }
+    }
+}
+
+/// A follower or follow
+#[derive(Debug)]
+pub struct RelatedUser {
+    pub id: UserId,
+    /// The API v2 returns the profile along with the id
+    pub profile: Option<TwitterUser>,
+}
+
+impl RelatedUser {
+    /// A user whose profile still has to be looked up
+    pub fn id(id: UserId) -> Self {
+        Self { id, profile: None }
+    }
+}
+
+/// A direct message and the conversation it belongs to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MessageEvent {
+    /// See `Conversation::id`
+    pub conversation_id: String,
+    /// The users taking part in the conversation, as far as the event knows them
+    pub participants: Vec<UserId>,
+    /// `media` contains the urls of the attachments, they still have to be downloaded
+    pub message: DirectMessage,
+}
+
+/// The id of a one-on-one conversation, the same as in a Twitter archive
+fn conversation_id(first: UserId, second: UserId) -> String {
+    format!("{}-{}", first.min(second), first.max(second))
+}
+
+/// The rate limit of a request was used up. Retry after `reset`
+/// (a unix timestamp)
+#[derive(Debug)]
+pub struct RateLimited {
+    pub reset: i32,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limit reached until {}", self.reset)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Rate limit errors become `RateLimited`, so that callers can wait and retry
+fn map_error(error: egg_mode::error::Error) -> eyre::Report {
+    match error {
+        egg_mode::error::Error::RateLimit(reset) => RateLimited { reset }.into(),
+        error => error.into(),
+    }
+}
+
+#[async_trait]
+pub trait TwitterApi: std::fmt::Debug + Send + Sync {
+    /// Up to 200 tweets of a timeline, newest first
+    async fn timeline(&self, timeline: Timeline, cursor: Option<Cursor>) -> Result<Page<Tweet>>;
+    /// The followers or follows of a user
+    async fn user_ids(
+        &self,
+        relation: Relation,
+        user_id: UserId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<RelatedUser>>;
+    async fn user(&self, id: UserId) -> Result<TwitterUser>;
+    /// Up to 100 users. Users that don't exist are left out
+    async fn users(&self, ids: &[UserId]) -> Result<Page<TwitterUser>>;
+    /// The lists a user owns, subscribed to or is a member of
+    async fn lists(
+        &self,
+        user_id: UserId,
+        kind: ListKind,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<List>>;
+    async fn list_members(&self, list_id: u64, cursor: Option<Cursor>)
+        -> Result<Page<TwitterUser>>;
+    /// Up to 100 tweets matching `query` that are newer than `since_id`, newest first
+    async fn search(
+        &self,
+        query: &str,
+        since_id: TweetId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<Tweet>>;
+    /// Up to 100 tweets, and why the ones that can't be loaded are missing
+    async fn lookup(&self, ids: &[TweetId]) -> Result<Page<(TweetId, LookedUp)>>;
+    /// The direct messages of the authenticated user, newest first.
+    /// Twitter only returns the messages of the last 30 days
+    async fn direct_messages(&self, cursor: Option<Cursor>) -> Result<Page<MessageEvent>>;
+}
@@ -0,0 +1,927 @@
+//! The Twitter API v2. It uses the same login as the API v1.1 and maps the
+//! v2 objects into the `egg_mode` types that TwitVault stores
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use egg_mode::{
+    entities::{
+        HashtagEntity, MediaEntity, MediaSize, MediaSizes, MediaType, MentionEntity, ResizeMode,
+        UrlEntity, VideoInfo, VideoVariant,
+    },
+    list::List,
+    raw::ParamList,
+    tweet::{ExtendedTweetEntities, Tweet, TweetEntities, TweetSource},
+    user::TwitterUser,
+    RateLimit, Token,
+};
+use eyre::{bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use tracing::warn;
+
+use super::{
+    conversation_id, map_error, Cursor, LookedUp, MessageEvent, Page, RelatedUser, Relation,
+    Timeline, TwitterApi,
+};
+use crate::helpers::{date_from_snowflake, placeholder_profile};
+use crate::storage::{DirectMessage, ListKind, TweetId, UserId};
+
+const API_URL: &str = "https://api.twitter.com/2";
+const TWEET_FIELDS: &str = "attachments,author_id,created_at,entities,in_reply_to_user_id,lang,note_tweet,possibly_sensitive,public_metrics,referenced_tweets,source";
+const TWEET_EXPANSIONS: &str = "attachments.media_keys,author_id,in_reply_to_user_id,referenced_tweets.id,referenced_tweets.id.author_id";
+const USER_FIELDS: &str =
+    "created_at,description,location,profile_image_url,protected,public_metrics,url,verified";
+const MEDIA_FIELDS: &str = "alt_text,duration_ms,height,preview_image_url,type,url,variants,width";
+const LIST_FIELDS: &str = "created_at,description,follower_count,member_count,owner_id";
+const DM_EVENT_FIELDS: &str =
+    "attachments,created_at,dm_conversation_id,event_type,id,participant_ids,sender_id,text";
+/// The page size of paged requests
+const PAGE_SIZE: usize = 100;
+/// The page size of followers and follows, which include their profiles
+const USERS_PAGE_SIZE: usize = 1000;
+/// How many days back the recent search reaches
+const SEARCH_DAYS: i64 = 7;
+/// The error type of objects that don't exist
+const NOT_FOUND_ERROR: &str = "https://api.twitter.com/2/problems/resource-not-found";
+
+#[derive(Debug)]
+pub struct TwitterV2Api {
+    token: Token,
+    /// The authenticated user, the mentions are requested for this user
+    user_id: UserId,
+}
+
+impl TwitterV2Api {
+    pub fn new(token: Token, user_id: UserId) -> Self {
+        Self { token, user_id }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: ParamList,
+    ) -> Result<(V2Response<T>, RateLimit)> {
+        let request =
+            egg_mode::raw::request_get(&format!("{API_URL}{path}"), &self.token, Some(&params));
+        let response = egg_mode::raw::response_json::<V2Response<T>>(request)
+            .await
+            .map_err(map_error)?;
+        Ok((response.response, response.rate_limit_status))
+    }
+}
+
+fn tweet_params() -> ParamList {
+    ParamList::new()
+        .add_param("tweet.fields", TWEET_FIELDS)
+        .add_param("expansions", TWEET_EXPANSIONS)
+        .add_param("user.fields", USER_FIELDS)
+        .add_param("media.fields", MEDIA_FIELDS)
+}
+
+fn user_params() -> ParamList {
+    ParamList::new().add_param("user.fields", USER_FIELDS)
+}
+
+/// Continue at the pagination token of the cursor, if it has one
+fn paged(params: ParamList, cursor: &Option<Cursor>, page_size: usize) -> ParamList {
+    let params = params.add_param("max_results", page_size.to_string());
+    match cursor {
+        Some(Cursor::Token(token)) => params.add_param("pagination_token", token.clone()),
+        _ => params,
+    }
+}
+
+/// Whether the request was rejected with `403 Forbidden`, e.g. because the
+/// endpoint needs a higher access level
+fn is_forbidden(error: &eyre::Report) -> bool {
+    matches!(
+        error.downcast_ref::<egg_mode::error::Error>(),
+        Some(egg_mode::error::Error::BadStatus(status)) if status.as_u16() == 403
+    )
+}
+
+fn ids_param(ids: &[u64]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[async_trait]
+impl TwitterApi for TwitterV2Api {
+    async fn timeline(&self, timeline: Timeline, cursor: Option<Cursor>) -> Result<Page<Tweet>> {
+        let path = match timeline {
+            Timeline::User(id) => format!("/users/{id}/tweets"),
+            Timeline::Mentions => format!("/users/{}/mentions", self.user_id),
+            Timeline::Likes(id) => format!("/users/{id}/liked_tweets"),
+            Timeline::Bookmarks => format!("/users/{}/bookmarks", self.user_id),
+        };
+        let mut params = paged(tweet_params(), &cursor, PAGE_SIZE);
+        // A paging position from the API v1.1. Likes and bookmarks can't continue there
+        let has_until = matches!(timeline, Timeline::User(_) | Timeline::Mentions);
+        if let (Some(Cursor::OlderThan(id)), true) = (&cursor, has_until) {
+            params = params.add_param("until_id", id.to_string());
+        }
+        let (response, rate_limit) = self.get::<Vec<V2Tweet>>(&path, params).await?;
+        Ok(Page {
+            next: response.next(),
+            items: response.tweets(),
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn user_ids(
+        &self,
+        relation: Relation,
+        user_id: UserId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<RelatedUser>> {
+        let path = match relation {
+            Relation::Followers => format!("/users/{user_id}/followers"),
+            Relation::Follows => format!("/users/{user_id}/following"),
+        };
+        let params = paged(user_params(), &cursor, USERS_PAGE_SIZE);
+        let (response, rate_limit) = self.get::<Vec<V2User>>(&path, params).await?;
+        Ok(Page {
+            next: response.next(),
+            items: response
+                .data
+                .unwrap_or_default()
+                .iter()
+                .map(|user| RelatedUser {
+                    id: user.id,
+                    profile: Some(user.to_user()),
+                })
+                .collect(),
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn user(&self, id: UserId) -> Result<TwitterUser> {
+        let (response, _) = self
+            .get::<V2User>(&format!("/users/{id}"), user_params())
+            .await?;
+        match response.data {
+            Some(user) => Ok(user.into()),
+            None => bail!("Could not load user {id}: {}", response.error_message()),
+        }
+    }
+
+    async fn users(&self, ids: &[UserId]) -> Result<Page<TwitterUser>> {
+        let params = user_params().add_param("ids", ids_param(ids));
+        let (response, rate_limit) = self.get::<Vec<V2User>>("/users", params).await?;
+        Ok(Page {
+            items: response
+                .data
+                .unwrap_or_default()
+                .into_iter()
+                .map(TwitterUser::from)
+                .collect(),
+            next: None,
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn lists(
+        &self,
+        user_id: UserId,
+        kind: ListKind,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<List>> {
+        let params = user_params()
+            .add_param("list.fields", LIST_FIELDS)
+            .add_param("expansions", "owner_id");
+        let params = paged(params, &cursor, PAGE_SIZE);
+        let path = match kind {
+            ListKind::Created => format!("/users/{user_id}/owned_lists"),
+            ListKind::Subscribed => format!("/users/{user_id}/followed_lists"),
+            ListKind::Member => format!("/users/{user_id}/list_memberships"),
+        };
+        let (response, rate_limit) = match self.get::<Vec<V2List>>(&path, params).await {
+            Ok(response) => response,
+            // Followed lists and memberships aren't available with every access level
+            Err(e) if kind != ListKind::Created && is_forbidden(&e) => {
+                warn!("No access to the {kind} lists: {e}");
+                return Ok(Page {
+                    items: Vec::new(),
+                    next: None,
+                    rate_limit: None,
+                });
+            }
+            Err(e) => return Err(e),
+        };
+        let lists = response
+            .data
+            .iter()
+            .flatten()
+            .map(|list| list.convert(&response.includes))
+            .collect();
+        Ok(Page {
+            next: response.next(),
+            items: lists,
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn list_members(
+        &self,
+        list_id: u64,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<TwitterUser>> {
+        let params = paged(user_params(), &cursor, PAGE_SIZE);
+        let path = format!("/lists/{list_id}/members");
+        let (response, rate_limit) = self.get::<Vec<V2User>>(&path, params).await?;
+        Ok(Page {
+            next: response.next(),
+            items: response
+                .data
+                .unwrap_or_default()
+                .into_iter()
+                .map(TwitterUser::from)
+                .collect(),
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        since_id: TweetId,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<Tweet>> {
+        let params = tweet_params().add_param("query", query.to_string());
+        // The recent search rejects a `since_id` older than its window
+        let window_start = Utc::now() - Duration::days(SEARCH_DAYS) + Duration::minutes(1);
+        let params = if date_from_snowflake(since_id) > window_start {
+            params.add_param("since_id", since_id.to_string())
+        } else {
+            params.add_param(
+                "start_time",
+                window_start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            )
+        };
+        let params = paged(params, &cursor, PAGE_SIZE);
+        let (response, rate_limit) = self
+            .get::<Vec<V2Tweet>>("/tweets/search/recent", params)
+            .await?;
+        Ok(Page {
+            next: response.next(),
+            items: response.tweets(),
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn lookup(&self, ids: &[TweetId]) -> Result<Page<(TweetId, LookedUp)>> {
+        let params = tweet_params().add_param("ids", ids_param(ids));
+        let (response, rate_limit) = self.get::<Vec<V2Tweet>>("/tweets", params).await?;
+        let tweets = response.tweets();
+        let items = ids
+            .iter()
+            .map(|id| {
+                let looked_up = match tweets.iter().find(|tweet| tweet.id == *id) {
+                    Some(tweet) => LookedUp::Found(Box::new(tweet.clone())),
+                    None => response.missing(*id),
+                };
+                (*id, looked_up)
+            })
+            .collect();
+        Ok(Page {
+            items,
+            next: None,
+            rate_limit: Some(rate_limit),
+        })
+    }
+
+    async fn direct_messages(&self, cursor: Option<Cursor>) -> Result<Page<MessageEvent>> {
+        let params = ParamList::new()
+            .add_param("dm_event.fields", DM_EVENT_FIELDS)
+            .add_param("event_types", "MessageCreate")
+            .add_param("expansions", "attachments.media_keys")
+            .add_param("media.fields", MEDIA_FIELDS);
+        let params = paged(params, &cursor, PAGE_SIZE);
+        let (response, rate_limit) = self.get::<Vec<V2DmEvent>>("/dm_events", params).await?;
+        let items = response
+            .data
+            .iter()
+            .flatten()
+            .filter_map(|event| event.convert(&response.includes))
+            .collect();
+        Ok(Page {
+            next: response.next(),
+            items,
+            rate_limit: Some(rate_limit),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Response<T> {
+    data: Option<T>,
+    #[serde(default)]
+    includes: Includes,
+    meta: Option<Meta>,
+    #[serde(default)]
+    errors: Vec<V2Error>,
+}
+
+impl<T> V2Response<T> {
+    fn next(&self) -> Option<Cursor> {
+        let token = self.meta.as_ref()?.next_token.clone()?;
+        Some(Cursor::Token(token))
+    }
+
+    /// Why a requested object is missing. Only a "Not Found Error" means
+    /// that it was deleted, an "Authorization Error" that it is protected
+    fn missing(&self, id: u64) -> LookedUp {
+        let id = id.to_string();
+        let not_found = self.errors.iter().any(|error| {
+            error.resource_id.as_deref() == Some(id.as_str())
+                && error.kind.as_deref() == Some(NOT_FOUND_ERROR)
+        });
+        match not_found {
+            true => LookedUp::Deleted,
+            false => LookedUp::Unavailable,
+        }
+    }
+
+    fn error_message(&self) -> String {
+        self.errors
+            .first()
+            .map(|error| error.detail.clone().unwrap_or_else(|| error.title.clone()))
+            .unwrap_or_else(|| "Empty response".to_string())
+    }
+}
+
+impl V2Response<Vec<V2Tweet>> {
+    fn tweets(&self) -> Vec<Tweet> {
+        self.data
+            .iter()
+            .flatten()
+            .map(|tweet| tweet.convert(&self.includes))
+            .collect()
+    }
+}
+
+/// The objects that the tweets or lists of a response refer to
+#[derive(Debug, Default, Deserialize)]
+struct Includes {
+    #[serde(default)]
+    users: Vec<V2User>,
+    #[serde(default)]
+    tweets: Vec<V2Tweet>,
+    #[serde(default)]
+    media: Vec<V2Media>,
+}
+
+impl Includes {
+    fn user(&self, id: UserId) -> Option<&V2User> {
+        self.users.iter().find(|user| user.id == id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Error {
+    #[serde(default)]
+    title: String,
+    detail: Option<String>,
+    /// The id of the object the error is about
+    resource_id: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Tweet {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: TweetId,
+    text: String,
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_optional_id")]
+    author_id: Option<UserId>,
+    #[serde(default, deserialize_with = "deserialize_optional_id")]
+    in_reply_to_user_id: Option<UserId>,
+    #[serde(default)]
+    referenced_tweets: Vec<Reference>,
+    #[serde(default)]
+    entities: V2Entities,
+    attachments: Option<Attachments>,
+    public_metrics: Option<TweetMetrics>,
+    lang: Option<String>,
+    source: Option<String>,
+    possibly_sensitive: Option<bool>,
+    /// The full text of tweets longer than 280 characters, `text` is cut off
+    note_tweet: Option<NoteTweet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NoteTweet {
+    text: String,
+    #[serde(default)]
+    entities: V2Entities,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reference {
+    /// `replied_to`, `quoted` or `retweeted`
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(deserialize_with = "deserialize_id")]
+    id: TweetId,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct V2Entities {
+    #[serde(default)]
+    hashtags: Vec<V2Hashtag>,
+    #[serde(default)]
+    mentions: Vec<V2Mention>,
+    #[serde(default)]
+    urls: Vec<V2Url>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Hashtag {
+    start: usize,
+    end: usize,
+    tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Mention {
+    start: usize,
+    end: usize,
+    username: String,
+    #[serde(default, deserialize_with = "deserialize_optional_id")]
+    id: Option<UserId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Url {
+    start: usize,
+    end: usize,
+    url: String,
+    expanded_url: Option<String>,
+    display_url: Option<String>,
+    /// Set if the link points to attached media
+    media_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Attachments {
+    #[serde(default)]
+    media_keys: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TweetMetrics {
+    #[serde(default)]
+    retweet_count: i32,
+    #[serde(default)]
+    like_count: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Media {
+    media_key: String,
+    /// `photo`, `video` or `animated_gif`
+    #[serde(rename = "type")]
+    kind: String,
+    url: Option<String>,
+    preview_image_url: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+    duration_ms: Option<i32>,
+    alt_text: Option<String>,
+    #[serde(default)]
+    variants: Vec<V2Variant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Variant {
+    bit_rate: Option<i32>,
+    content_type: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2DmEvent {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: u64,
+    #[serde(default)]
+    text: String,
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_optional_id")]
+    sender_id: Option<UserId>,
+    /// `{user id}-{user id}` for one-on-one conversations, a number for groups
+    dm_conversation_id: String,
+    #[serde(default)]
+    participant_ids: Vec<String>,
+    attachments: Option<Attachments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2User {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: UserId,
+    name: String,
+    username: String,
+    created_at: Option<DateTime<Utc>>,
+    description: Option<String>,
+    location: Option<String>,
+    url: Option<String>,
+    profile_image_url: Option<String>,
+    #[serde(default)]
+    protected: bool,
+    #[serde(default)]
+    verified: bool,
+    public_metrics: Option<UserMetrics>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserMetrics {
+    #[serde(default)]
+    followers_count: i32,
+    #[serde(default)]
+    following_count: i32,
+    #[serde(default)]
+    tweet_count: i32,
+    #[serde(default)]
+    listed_count: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2List {
+    #[serde(deserialize_with = "deserialize_id")]
+    id: u64,
+    name: String,
+    #[serde(default)]
+    description: String,
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    member_count: u64,
+    #[serde(default)]
+    follower_count: u64,
+    #[serde(default, deserialize_with = "deserialize_optional_id")]
+    owner_id: Option<UserId>,
+}
+
+impl V2Tweet {
+    fn convert(&self, includes: &Includes) -> Tweet {
+        let author = match self.author_id.and_then(|id| includes.user(id)) {
+            Some(user) => user.to_user(),
+            None => placeholder_profile(self.author_id.unwrap_or_default(), "Unknown Author", ""),
+        };
+        let referenced = |kind: &str| {
+            self.referenced_tweets
+                .iter()
+                .find(|reference| reference.kind == kind)
+                .map(|reference| reference.id)
+        };
+        let included = |id: Option<TweetId>| {
+            let tweet = includes.tweets.iter().find(|tweet| Some(tweet.id) == id)?;
+            Some(Box::new(tweet.convert(includes)))
+        };
+        let quoted_status_id = referenced("quoted");
+        let (hashtags, urls, user_mentions) = self.entities(includes);
+        let media: Vec<MediaEntity> = self
+            .attachments
+            .iter()
+            .flat_map(|attachments| attachments.media_keys.iter())
+            .filter_map(|key| includes.media.iter().find(|media| &media.media_key == key))
+            .filter_map(|media| self.media_entity(media))
+            .collect();
+        let metrics = self.public_metrics.as_ref();
+
+        Tweet {
+            coordinates: None,
+            created_at: self
+                .created_at
+                .unwrap_or_else(|| date_from_snowflake(self.id)),
+            current_user_retweet: None,
+            display_text_range: None,
+            entities: TweetEntities {
+                hashtags,
+                symbols: Vec::new(),
+                urls,
+                user_mentions,
+                media: (!media.is_empty()).then(|| media[..1].to_vec()),
+            },
+            extended_entities: (!media.is_empty()).then_some(ExtendedTweetEntities { media }),
+            favorite_count: metrics.map(|m| m.like_count).unwrap_or_default(),
+            favorited: None,
+            filter_level: None,
+            id: self.id,
+            in_reply_to_user_id: self.in_reply_to_user_id,
+            in_reply_to_screen_name: self
+                .in_reply_to_user_id
+                .and_then(|id| includes.user(id))
+                .map(|user| user.username.clone()),
+            in_reply_to_status_id: referenced("replied_to"),
+            lang: self.lang.clone(),
+            place: None,
+            possibly_sensitive: self.possibly_sensitive,
+            quoted_status_id,
+            quoted_status: included(quoted_status_id),
+            retweet_count: metrics.map(|m| m.retweet_count).unwrap_or_default(),
+            retweeted: None,
+            retweeted_status: included(referenced("retweeted")),
+            source: self.source.clone().map(|name| TweetSource {
+                name,
+                url: String::new(),
+            }),
+            text: self.full_text().0.to_string(),
+            truncated: false,
+            user: Some(Box::new(author)),
+            withheld_copyright: false,
+            withheld_in_countries: None,
+            withheld_scope: None,
+        }
+    }
+
+    /// The text and its entities, from the note of long tweets
+    fn full_text(&self) -> (&str, &V2Entities) {
+        match &self.note_tweet {
+            Some(note) => (&note.text, &note.entities),
+            None => (&self.text, &self.entities),
+        }
+    }
+
+    fn entities(
+        &self,
+        includes: &Includes,
+    ) -> (Vec<HashtagEntity>, Vec<UrlEntity>, Vec<MentionEntity>) {
+        let (text, entities) = self.full_text();
+        let hashtags = entities
+            .hashtags
+            .iter()
+            .map(|hashtag| HashtagEntity {
+                range: byte_range(text, hashtag.start, hashtag.end),
+                text: hashtag.tag.clone(),
+            })
+            .collect();
+        // Links to media are part of the media entities
+        let urls = entities
+            .urls
+            .iter()
+            .filter(|url| url.media_key.is_none())
+            .map(|url| UrlEntity {
+                display_url: url.display_url.clone().unwrap_or_else(|| url.url.clone()),
+                expanded_url: url.expanded_url.clone(),
+                range: byte_range(text, url.start, url.end),
+                url: url.url.clone(),
+            })
+            .collect();
+        let mentions = entities
+            .mentions
+            .iter()
+            .map(|mention| MentionEntity {
+                id: mention.id.unwrap_or_default(),
+                range: byte_range(text, mention.start, mention.end),
+                name: mention
+                    .id
+                    .and_then(|id| includes.user(id))
+                    .map(|user| user.name.clone())
+                    .unwrap_or_else(|| mention.username.clone()),
+                screen_name: mention.username.clone(),
+            })
+            .collect();
+        (hashtags, urls, mentions)
+    }
+
+    fn media_entity(&self, media: &V2Media) -> Option<MediaEntity> {
+        let media_type = match media.kind.as_str() {
+            "photo" => MediaType::Photo,
+            "video" => MediaType::Video,
+            "animated_gif" => MediaType::Gif,
+            _ => return None,
+        };
+        // Videos only have a preview image
+        let image = media.url.clone().or(media.preview_image_url.clone())?;
+        let (text, entities) = self.full_text();
+        let link = entities
+            .urls
+            .iter()
+            .find(|url| url.media_key.as_ref() == Some(&media.media_key));
+        let (width, height) = (
+            media.width.unwrap_or_default(),
+            media.height.unwrap_or_default(),
+        );
+        let size = MediaSize {
+            w: width,
+            h: height,
+            resize: ResizeMode::Fit,
+        };
+        let video_info = (media_type != MediaType::Photo).then(|| VideoInfo {
+            aspect_ratio: (width, height),
+            duration_millis: media.duration_ms,
+            variants: media
+                .variants
+                .iter()
+                .filter_map(|variant| {
+                    Some(VideoVariant {
+                        bitrate: variant.bit_rate,
+                        content_type: variant.content_type.parse().ok()?,
+                        url: variant.url.clone(),
+                    })
+                })
+                .collect(),
+        });
+        Some(MediaEntity {
+            display_url: link
+                .and_then(|url| url.display_url.clone())
+                .unwrap_or_default(),
+            expanded_url: link
+                .and_then(|url| url.expanded_url.clone())
+                .unwrap_or_default(),
+            // The media key is `{media category}_{media id}`
+            id: media
+                .media_key
+                .rsplit('_')
+                .next()
+                .and_then(|id| id.parse().ok())
+                .unwrap_or_default(),
+            range: link
+                .map(|url| byte_range(text, url.start, url.end))
+                .unwrap_or_default(),
+            media_url: image.replacen("https://", "http://", 1),
+            media_url_https: image,
+            sizes: MediaSizes {
+                thumb: size,
+                small: size,
+                medium: size,
+                large: size,
+            },
+            source_status_id: None,
+            media_type,
+            url: link.map(|url| url.url.clone()).unwrap_or_default(),
+            video_info,
+            ext_alt_text: media.alt_text.clone(),
+        })
+    }
+}
+
+impl V2DmEvent {
+    /// Only the events that created a message have a sender
+    fn convert(&self, includes: &Includes) -> Option<MessageEvent> {
+        let sender_id = self.sender_id?;
+        let pair = self
+            .dm_conversation_id
+            .split_once('-')
+            .and_then(|(first, second)| Some((first.parse().ok()?, second.parse().ok()?)));
+        let mut participants: Vec<UserId> = self
+            .participant_ids
+            .iter()
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        participants.push(sender_id);
+        let recipient_id =
+            pair.map(|(first, second)| if first == sender_id { second } else { first });
+        participants.extend(recipient_id);
+        let media = self
+            .attachments
+            .iter()
+            .flat_map(|attachments| attachments.media_keys.iter())
+            .filter_map(|key| includes.media.iter().find(|media| &media.media_key == key))
+            .filter_map(V2Media::download_url)
+            .collect();
+        Some(MessageEvent {
+            conversation_id: match pair {
+                Some((first, second)) => conversation_id(first, second),
+                None => self.dm_conversation_id.clone(),
+            },
+            participants,
+            message: DirectMessage {
+                id: self.id,
+                sender_id,
+                recipient_id,
+                created_at: self
+                    .created_at
+                    .unwrap_or_else(|| date_from_snowflake(self.id)),
+                text: self.text.clone(),
+                media,
+            },
+        })
+    }
+}
+
+impl V2Media {
+    /// The image, or the mp4 version of a video with the highest bitrate
+    fn download_url(&self) -> Option<String> {
+        if self.kind == "photo" {
+            return self.url.clone();
+        }
+        self.variants
+            .iter()
+            .filter(|variant| variant.content_type == "video/mp4")
+            .max_by_key(|variant| variant.bit_rate)
+            .map(|variant| variant.url.clone())
+    }
+}
+
+impl V2User {
+    fn to_user(&self) -> TwitterUser {
+        let mut user = placeholder_profile(self.id, &self.name, &self.username);
+        if let Some(created_at) = self.created_at {
+            user.created_at = created_at;
+        }
+        user.description = self.description.clone().filter(|e| !e.is_empty());
+        user.location = self.location.clone().filter(|e| !e.is_empty());
+        user.url = self.url.clone().filter(|e| !e.is_empty());
+        user.protected = self.protected;
+        user.verified = self.verified;
+        if let Some(metrics) = &self.public_metrics {
+            user.followers_count = metrics.followers_count;
+            user.friends_count = metrics.following_count;
+            user.statuses_count = metrics.tweet_count;
+            user.listed_count = metrics.listed_count;
+        }
+        if let Some(avatar) = &self.profile_image_url {
+            user.default_profile_image = false;
+            user.profile_image_url = avatar.replacen("https://", "http://", 1);
+            user.profile_image_url_https = avatar.clone();
+        }
+        user
+    }
+}
+
+impl From<V2User> for TwitterUser {
+    fn from(value: V2User) -> Self {
+        value.to_user()
+    }
+}
+
+impl V2List {
+    fn convert(&self, includes: &Includes) -> List {
+        let owner_id = self.owner_id.unwrap_or_default();
+        let owner = match includes.user(owner_id) {
+            Some(user) => user.to_user(),
+            None => placeholder_profile(owner_id, "Unknown Author", ""),
+        };
+        // The API v2 has no slugs, but lists without a slug are placeholders
+        let mut slug = self
+            .name
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        if slug.is_empty() {
+            slug = self.id.to_string();
+        }
+        // Without the owner's screen name, the list is linked by its id
+        let (full_name, uri) = match owner.screen_name.is_empty() {
+            true => (self.name.clone(), format!("/i/lists/{}", self.id)),
+            false => (
+                format!("@{}/{slug}", owner.screen_name),
+                format!("/{}/lists/{slug}", owner.screen_name),
+            ),
+        };
+        List {
+            name: self.name.clone(),
+            full_name,
+            uri,
+            slug,
+            id: self.id,
+            subscriber_count: self.follower_count,
+            member_count: self.member_count,
+            description: self.description.clone(),
+            created_at: self
+                .created_at
+                .unwrap_or_else(|| date_from_snowflake(self.id)),
+            user: owner,
+        }
+    }
+}
+
+/// The API v2 counts entity positions in characters, the stored entities in bytes
+fn byte_range(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let byte = |position: usize| {
+        text.char_indices()
+            .nth(position)
+            .map(|(index, _)| index)
+            .unwrap_or(text.len())
+    };
+    (byte(start), byte(end))
+}
+
+/// The API v2 returns ids as strings
+fn deserialize_id<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let id = String::deserialize(deserializer)?;
+    id.parse().map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_id<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(id) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None)
+    };
+    id.parse().map(Some).map_err(serde::de::Error::custom)
+}
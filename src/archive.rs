@@ -0,0 +1,292 @@
+//! Access to the files of an official Twitter archive. The archive can
+//! either be an unzipped folder or one or more zip files (Twitter splits
+//! large archives into `...-part1.zip`, `...-part2.zip`, etc).
+//! Zip entries are read on demand, nothing is extracted up front.
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use eyre::{bail, eyre, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::info;
+use zip::ZipArchive;
+
+const ARCHIVE_DATA_FOLDER: &str = "data";
+/// Describes the archive, e.g. when it was created
+const ARCHIVE_MANIFEST_FILE: &str = "manifest.js";
+
+pub struct Archive {
+    source: Source,
+}
+
+enum Source {
+    Folder(PathBuf),
+    Zip {
+        parts: Vec<ZipArchive<File>>,
+        /// Maps the path relative to the `data` folder to the
+        /// part containing it and the full name within that part
+        entries: HashMap<String, (usize, String)>,
+    },
+}
+
+impl Archive {
+    /// Open a folder or one or more zip files
+    pub fn open(paths: &[PathBuf]) -> Result<Self> {
+        match paths {
+            [] => bail!("No archive path given"),
+            [folder] if folder.is_dir() => Ok(Archive {
+                source: Source::Folder(folder.join(ARCHIVE_DATA_FOLDER)),
+            }),
+            _ => {
+                let mut parts = Vec::new();
+                let mut entries = HashMap::new();
+                for (index, path) in paths.iter().enumerate() {
+                    if path.is_dir() {
+                        bail!(
+                            "{} is a folder. Folders can't be combined with other parts",
+                            path.display()
+                        );
+                    }
+                    let part = ZipArchive::new(File::open(path)?)?;
+                    for name in part.file_names() {
+                        if let Some(relative) = data_relative_name(name) {
+                            entries.insert(relative.to_string(), (index, name.to_string()));
+                        }
+                    }
+                    info!("Opened {} with {} entries", path.display(), part.len());
+                    parts.push(part);
+                }
+                Ok(Archive {
+                    source: Source::Zip { parts, entries },
+                })
+            }
+        }
+    }
+
+    /// The archive data files are javascript files which assign the data
+    /// to a variable: `window.YTD.tweets.part0 = [...]`. Large archives split
+    /// a data file into `tweets.js`, `tweets-part1.js`, `tweets-part2.js`, etc.
+    /// This returns the entries of all parts. Not every archive contains
+    /// every file, so missing files are `None`
+    pub fn read_data_file<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<Vec<T>>> {
+        let parts = self.data_file_parts(name);
+        if parts.is_empty() {
+            info!("No {name} in archive");
+            return Ok(None);
+        }
+        let mut entries = Vec::new();
+        for part in parts {
+            let mut reader = BufReader::new(self.open_entry(&part)?);
+            let mut decoded =
+                read_entries(&mut reader).map_err(|e| eyre!("Could not read {part}: {e}"))?;
+            entries.append(&mut decoded);
+        }
+        Ok(Some(entries))
+    }
+
+    /// When Twitter created the archive. Older archives have no manifest
+    pub fn generation_date(&mut self) -> Option<DateTime<Utc>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Manifest {
+            archive_info: ArchiveInfo,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ArchiveInfo {
+            generation_date: DateTime<Utc>,
+        }
+        let mut input = String::new();
+        let mut entry = self.open_entry(ARCHIVE_MANIFEST_FILE).ok()?;
+        entry.read_to_string(&mut input).ok()?;
+        // `window.__THAR_CONFIG = {...}`
+        let (_, json) = input.split_once('=')?;
+        let manifest: Manifest = serde_json::from_str(json.trim().trim_end_matches(';')).ok()?;
+        Some(manifest.archive_info.generation_date)
+    }
+
+    /// Copy `file_name` from the media `folder` (e.g. `tweets_media`) into `output`.
+    /// Returns false if the archive doesn't contain the file
+    pub fn copy_media(
+        &mut self,
+        folder: &str,
+        file_name: &str,
+        output: &mut impl Write,
+    ) -> Result<bool> {
+        let name = format!("{folder}/{file_name}");
+        match &mut self.source {
+            Source::Folder(data_folder) => {
+                let path = data_folder.join(&name);
+                if !path.exists() {
+                    return Ok(false);
+                }
+                std::io::copy(&mut File::open(path)?, output)?;
+            }
+            Source::Zip { parts, entries } => {
+                let Some((index, full_name)) = entries.get(&name) else {
+                    return Ok(false)
+                };
+                let mut entry = parts[*index].by_name(full_name)?;
+                std::io::copy(&mut entry, output)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// `name` and its numbered parts (`tweets-part1.js`, ...) in order
+    fn data_file_parts(&self, name: &str) -> Vec<String> {
+        let Some(stem) = name.strip_suffix(".js") else {
+            return Vec::new()
+        };
+        let names: Vec<String> = match &self.source {
+            Source::Folder(data_folder) => std::fs::read_dir(data_folder)
+                .map(|files| {
+                    files
+                        .filter_map(|file| file.ok())
+                        .map(|file| file.file_name().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Source::Zip { entries, .. } => entries.keys().cloned().collect(),
+        };
+        let mut parts: Vec<(usize, String)> = names
+            .into_iter()
+            .filter_map(|file| {
+                if file == name {
+                    return Some((0, file));
+                }
+                let number = file
+                    .strip_prefix(stem)?
+                    .strip_prefix("-part")?
+                    .strip_suffix(".js")?
+                    .parse()
+                    .ok()?;
+                Some((number, file))
+            })
+            .collect();
+        parts.sort();
+        parts.into_iter().map(|(_, file)| file).collect()
+    }
+
+    /// A file in the `data` folder. The archive has to contain it
+    fn open_entry(&mut self, name: &str) -> Result<Box<dyn Read + '_>> {
+        match &mut self.source {
+            Source::Folder(data_folder) => Ok(Box::new(File::open(data_folder.join(name))?)),
+            Source::Zip { parts, entries } => {
+                let Some((index, full_name)) = entries.get(name) else {
+                    bail!("No {name} in archive")
+                };
+                Ok(Box::new(parts[*index].by_name(full_name)?))
+            }
+        }
+    }
+}
+
+/// Skip the variable assignment in front of the JSON array and decode the
+/// array while it is read
+fn read_entries<T: DeserializeOwned>(reader: &mut impl BufRead) -> Result<Vec<T>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            bail!("The file does not contain any data")
+        }
+        match buffer.iter().position(|c| *c == b'[') {
+            Some(start) => {
+                reader.consume(start);
+                break;
+            }
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    }
+    // Anything after the array (e.g. a `;`) is ignored
+    let mut arrays = serde_json::Deserializer::from_reader(reader).into_iter::<Vec<T>>();
+    match arrays.next() {
+        Some(entries) => Ok(entries?),
+        None => bail!("The file does not contain any data"),
+    }
+}
+
+/// Zip entries are either `data/tweets.js` or, if the archive was
+/// re-zipped, `some-folder/data/tweets.js`.
+fn data_relative_name(name: &str) -> Option<&str> {
+    let marker = format!("{ARCHIVE_DATA_FOLDER}/");
+    if let Some(relative) = name.strip_prefix(&marker) {
+        return Some(relative);
+    }
+    let position = name.find(&format!("/{marker}"))?;
+    Some(&name[(position + marker.len() + 1)..])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn data_file_parts_are_in_order() {
+        let names = [
+            "tweets-part10.js",
+            "tweets-part2.js",
+            "tweets.js",
+            "tweets-part1.js",
+            "tweets-partx.js",
+            "tweet-headers.js",
+            "deleted-tweets.js",
+            "tweets_media/tweets-part3.js",
+        ];
+        let entries = names
+            .iter()
+            .map(|name| (name.to_string(), (0, format!("data/{name}"))))
+            .collect();
+        let archive = Archive {
+            source: Source::Zip {
+                parts: Vec::new(),
+                entries,
+            },
+        };
+        let expected = [
+            "tweets.js",
+            "tweets-part1.js",
+            "tweets-part2.js",
+            "tweets-part10.js",
+        ];
+        assert_eq!(archive.data_file_parts("tweets.js"), expected);
+        assert!(archive.data_file_parts("like.js").is_empty());
+        assert!(archive.data_file_parts("tweets").is_empty());
+    }
+
+    #[test]
+    fn read_entries_skips_the_assignment() {
+        let input = "window.YTD.tweets.part0 = [{\"id\": \"1\"}, {\"id\": \"2\"}];\n";
+        // The prefix spans several reads of the buffer
+        let mut reader = BufReader::with_capacity(4, Cursor::new(input));
+        let entries: Vec<Value> = read_entries(&mut reader).unwrap();
+        let expected = serde_json::json!([{"id": "1"}, {"id": "2"}]);
+        assert_eq!(Value::from(entries), expected);
+
+        let mut reader = BufReader::with_capacity(4, Cursor::new("window.YTD.tweets.part0 = "));
+        assert!(read_entries::<Value>(&mut reader).is_err());
+    }
+
+    #[test]
+    fn data_relative_names_of_rezipped_archives() {
+        assert_eq!(data_relative_name("data/tweets.js"), Some("tweets.js"));
+        assert_eq!(
+            data_relative_name("twitter-2022-12-01/data/tweets_media/1-a.jpg"),
+            Some("tweets_media/1-a.jpg")
+        );
+        assert_eq!(data_relative_name("a/b/data/tweets.js"), Some("tweets.js"));
+        assert_eq!(data_relative_name("metadata/tweets.js"), None);
+        assert_eq!(data_relative_name("Your archive.html"), None);
+    }
+}
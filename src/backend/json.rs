@@ -0,0 +1,202 @@
+//! Stores all data in one `_data.json` file. The file can be compressed
+//! with zstd, which is detected when it is read
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use eyre::{eyre, Result};
+use tracing::warn;
+
+use super::{migrations, BackendKind, NewerSchemaError, StorageBackend};
+use crate::crypto::{self, Vault};
+use crate::storage::Data;
+
+const FILE_ROOT: &str = "_data.json";
+/// The data is written here first and then renamed to `FILE_ROOT`
+const FILE_ROOT_TEMP: &str = "_data.json.tmp";
+/// A data file that could not be read is moved here
+const FILE_ROOT_CORRUPT: &str = "_data.json.corrupt";
+/// How many previous versions of `FILE_ROOT` are kept (`_data.json.1`, ...)
+const DATA_GENERATIONS: usize = 3;
+/// Every zstd frame starts with this
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// zstd's default level. Higher levels are much slower for little gain
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug)]
+pub struct JsonBackend {
+    root_folder: PathBuf,
+    data_path: PathBuf,
+    /// If the storage is encrypted
+    vault: Option<Vault>,
+    /// Whether the data file is compressed. Updated by `load`, so that
+    /// saving keeps the format of the file
+    compressed: AtomicBool,
+}
+
+impl JsonBackend {
+    pub fn new(root_folder: &Path, vault: Option<Vault>) -> Self {
+        Self {
+            root_folder: root_folder.to_path_buf(),
+            data_path: root_folder.join(FILE_ROOT),
+            vault,
+            compressed: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether there is a data file or one of its previous generations
+    pub fn exists(root_folder: &Path) -> bool {
+        let data_path = root_folder.join(FILE_ROOT);
+        data_path.exists()
+            || (1..=DATA_GENERATIONS)
+                .any(|generation| generation_path(&data_path, generation).exists())
+    }
+
+    /// Compress the data file from the next save on
+    pub fn with_compression(self, compressed: bool) -> Self {
+        self.compressed.store(compressed, Ordering::Relaxed);
+        self
+    }
+
+    fn encode(&self, value: &serde_json::Value, output: impl Write) -> Result<()> {
+        if self.compressed() {
+            let mut encoder = zstd::Encoder::new(output, COMPRESSION_LEVEL)?;
+            serde_json::to_writer(&mut encoder, value)?;
+            encoder.finish()?;
+        } else {
+            serde_json::to_writer(output, value)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the previous generations and a corrupt data file, e.g. because
+    /// they aren't encrypted
+    pub fn remove_old_data(root_folder: &Path) -> Result<()> {
+        let data_path = root_folder.join(FILE_ROOT);
+        let old_files = (1..=DATA_GENERATIONS)
+            .map(|generation| generation_path(&data_path, generation))
+            .chain(std::iter::once(root_folder.join(FILE_ROOT_CORRUPT)));
+        for path in old_files {
+            if path.exists() {
+                crypto::remove_file_securely(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageBackend for JsonBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Json
+    }
+
+    /// If the data file can't be read (e.g. because of a crash while writing),
+    /// the newest previous generation that can be read is used instead.
+    fn load(&self) -> Result<Data> {
+        let data_path = &self.data_path;
+        let candidates = std::iter::once(data_path.clone())
+            .chain((1..=DATA_GENERATIONS).map(|generation| generation_path(data_path, generation)));
+        let mut last_error = None;
+        for candidate in candidates {
+            if !candidate.exists() {
+                continue;
+            }
+            match read_data(&candidate, self.vault.as_ref()) {
+                Ok((data, compressed)) => {
+                    self.compressed.store(compressed, Ordering::Relaxed);
+                    if &candidate != data_path {
+                        warn!(
+                            "Could not read {}, using {} instead",
+                            data_path.display(),
+                            candidate.display()
+                        );
+                        // Keep the broken file around, but out of the rotation
+                        if data_path.exists() {
+                            std::fs::rename(data_path, self.root_folder.join(FILE_ROOT_CORRUPT))?;
+                        }
+                    }
+                    return Ok(data);
+                }
+                // An older generation would silently lose the newer data
+                Err(e) if e.downcast_ref::<NewerSchemaError>().is_some() => return Err(e),
+                Err(e) => {
+                    warn!("Could not read {}: {e:?}", candidate.display());
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| eyre!("No data found at {}", data_path.display())))
+    }
+
+    // Blocking write. The data is written to a temporary file which atomically
+    // replaces the data file once it is complete. The previous data file is
+    // kept as a generation.
+    fn save(&self, data: &Data) -> Result<()> {
+        use std::io::BufWriter;
+        let value = super::to_value(data)?;
+        let temp_path = self.root_folder.join(FILE_ROOT_TEMP);
+        let mut writer = BufWriter::new(std::fs::File::create(&temp_path)?);
+        match self.vault.as_ref() {
+            Some(vault) => {
+                let mut plaintext = Vec::new();
+                self.encode(&value, &mut plaintext)?;
+                writer.write_all(&vault.encrypt(&plaintext)?)?
+            }
+            None => self.encode(&value, &mut writer)?,
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        rotate_generations(&self.data_path)?;
+        std::fs::rename(&temp_path, &self.data_path)?;
+
+        // Make sure the rename itself is persisted
+        #[cfg(unix)]
+        std::fs::File::open(&self.root_folder)?.sync_all()?;
+
+        Ok(())
+    }
+
+    fn compressed(&self) -> bool {
+        self.compressed.load(Ordering::Relaxed)
+    }
+}
+
+/// The data and whether the file was compressed
+fn read_data(path: &Path, vault: Option<&Vault>) -> Result<(Data, bool)> {
+    let mut input = crypto::read_file(path, vault)?;
+    let compressed = input.starts_with(ZSTD_MAGIC);
+    if compressed {
+        input = zstd::decode_all(input.as_slice())?;
+    }
+    let value = serde_json::from_slice(&input)?;
+    Ok((migrations::migrate(value)?, compressed))
+}
+
+/// `_data.json.1` is the newest previous generation
+fn generation_path(data_path: &Path, generation: usize) -> PathBuf {
+    let mut name = data_path.as_os_str().to_owned();
+    name.push(format!(".{generation}"));
+    PathBuf::from(name)
+}
+
+/// Move every generation one step back, dropping the oldest one.
+/// The current data file becomes the first generation, but stays in place
+/// so that there is always a data file, even if the save is interrupted
+fn rotate_generations(data_path: &Path) -> Result<()> {
+    for generation in (1..DATA_GENERATIONS).rev() {
+        let source = generation_path(data_path, generation);
+        if source.exists() {
+            std::fs::rename(&source, generation_path(data_path, generation + 1))?;
+        }
+    }
+    if data_path.exists() {
+        let first = generation_path(data_path, 1);
+        // Not every file system supports hard links
+        if std::fs::hard_link(data_path, &first).is_err() {
+            std::fs::copy(data_path, &first)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! `Data` is stored with a schema version. Data written by older versions is
+//! upgraded one version at a time before it is deserialized.
+use eyre::{bail, Result};
+use serde_json::{Map, Value};
+use tracing::info;
+
+use super::users;
+use crate::storage::Data;
+
+/// The schema version written by this version of TwitVault
+pub const SCHEMA_VERSION: u64 = 8;
+
+/// Data written before the schema was versioned has no version field
+const FIELD_VERSION: &str = "schema_version";
+
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades the data from version `n` to version `n + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+];
+
+/// The data was written by a newer version of TwitVault. Reading it would
+/// silently drop fields we don't know about, and the next save would lose them.
+#[derive(Debug)]
+pub struct NewerSchemaError {
+    pub found: u64,
+}
+
+impl std::fmt::Display for NewerSchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The data was written by a newer version of TwitVault (schema version {}, this version supports up to {SCHEMA_VERSION}). Please update TwitVault",
+            self.found
+        )
+    }
+}
+
+impl std::error::Error for NewerSchemaError {}
+
+/// Upgrade the JSON representation of `Data` to `SCHEMA_VERSION` and deserialize it.
+/// The authors of the tweets are put back into the tweets first, see `users`
+pub fn migrate(mut value: Value) -> Result<Data> {
+    let Value::Object(fields) = &mut value else {
+        bail!("The data is not a JSON object")
+    };
+    let version = match fields.get(FIELD_VERSION) {
+        None => 0,
+        Some(version) => {
+            let Some(version) = version.as_u64() else {
+                bail!("Invalid schema version {version}")
+            };
+            version
+        }
+    };
+    if version > SCHEMA_VERSION {
+        return Err(NewerSchemaError { found: version }.into());
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Migrating data from schema version {from} to {}", from + 1);
+        migration(fields)?;
+        fields.insert(FIELD_VERSION.to_string(), Value::from(from as u64 + 1));
+    }
+    users::rehydrate(fields);
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Version 0 is everything written before the schema was versioned.
+/// Depending on how old it is, `likes`, `conversations`, `tweet_meta` and
+/// the kind of each list might be missing
+fn migrate_v0_to_v1(fields: &mut Map<String, Value>) -> Result<()> {
+    for key in ["likes", "conversations"] {
+        fields
+            .entry(key)
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+    fields
+        .entry("tweet_meta")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Some(Value::Array(lists)) = fields.get_mut("lists") {
+        for list in lists.iter_mut().filter_map(Value::as_object_mut) {
+            // Lists crawled before this existed are all our own lists
+            list.entry("kind").or_insert_with(|| Value::from("Created"));
+        }
+    }
+    Ok(())
+}
+
+/// Version 1 stored only the file name for each media url. The size and the
+/// hash of these files are recorded by `verify`, which also renames them
+/// to their hash
+fn migrate_v1_to_v2(fields: &mut Map<String, Value>) -> Result<()> {
+    let Some(Value::Object(media)) = fields.get_mut("media") else {
+        return Ok(())
+    };
+    for entry in media.values_mut() {
+        let Value::String(file) = entry else {
+            continue
+        };
+        let extension = file.rsplit_once('.').map(|(_, e)| e).unwrap_or_default();
+        *entry = serde_json::json!({
+            "file": file,
+            "size": null,
+            "mime": crate::media::mime_for_extension(extension),
+            "sha256": null,
+        });
+    }
+    Ok(())
+}
+
+/// Version 3 stores the author of a tweet only once in `profiles`. Tweets
+/// written before still embed their author, which is read as it is, so
+/// nothing has to change. Older versions can't read the stripped tweets
+fn migrate_v2_to_v3(_fields: &mut Map<String, Value>) -> Result<()> {
+    Ok(())
+}
+
+/// Version 4 records when a tweet was seen on Twitter and whether it was
+/// deleted with TwitVault. Nothing is known about that for older tweets
+fn migrate_v3_to_v4(fields: &mut Map<String, Value>) -> Result<()> {
+    let Some(Value::Object(tweet_meta)) = fields.get_mut("tweet_meta") else {
+        return Ok(())
+    };
+    for meta in tweet_meta.values_mut().filter_map(Value::as_object_mut) {
+        meta.entry("deleted_via_twitvault")
+            .or_insert(Value::Bool(false));
+        meta.entry("first_seen").or_insert(Value::Null);
+        meta.entry("last_seen").or_insert(Value::Null);
+    }
+    Ok(())
+}
+
+/// Version 5 adds the bookmarks of the user
+fn migrate_v4_to_v5(fields: &mut Map<String, Value>) -> Result<()> {
+    fields
+        .entry("bookmarks")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    Ok(())
+}
+
+/// Version 6 adds the threads of the thread crawler
+fn migrate_v5_to_v6(fields: &mut Map<String, Value>) -> Result<()> {
+    fields
+        .entry("threads")
+        .or_insert_with(|| Value::Object(Map::new()));
+    Ok(())
+}
+
+/// Version 7 stores an empty screen name for placeholder profiles. Before,
+/// it was the path of the Twitter link to the profile
+fn migrate_v6_to_v7(fields: &mut Map<String, Value>) -> Result<()> {
+    let Some(Value::Object(profiles)) = fields.get_mut("profiles") else {
+        return Ok(())
+    };
+    for profile in profiles.values_mut().filter_map(Value::as_object_mut) {
+        let is_link = profile
+            .get("screen_name")
+            .and_then(Value::as_str)
+            .map_or(false, |name| name.starts_with("intent/user?user_id="));
+        if is_link {
+            profile.insert("screen_name".to_string(), Value::from(""));
+        }
+    }
+    Ok(())
+}
+
+/// Version 8 records when a tweet became unavailable without being deleted.
+/// Older checks marked these tweets as deleted, which can't be undone here
+fn migrate_v7_to_v8(fields: &mut Map<String, Value>) -> Result<()> {
+    let Some(Value::Object(tweet_meta)) = fields.get_mut("tweet_meta") else {
+        return Ok(())
+    };
+    for meta in tweet_meta.values_mut().filter_map(Value::as_object_mut) {
+        meta.entry("unavailable_since").or_insert(Value::Null);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ListKind;
+
+    /// `_data.json` as it was written before the schema was versioned
+    fn baseline_data() -> Value {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/migrations/v0/_data.json"
+        );
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn migrates_baseline_data() {
+        let data = migrate(baseline_data()).unwrap();
+        assert_eq!(data.schema_version, SCHEMA_VERSION);
+        assert_eq!(data.profile.id, 12345);
+        assert_eq!(data.tweets.len(), 2);
+        assert!(data.tweets.iter().all(|t| t.user.is_some()));
+        assert!(data.likes.is_empty() && data.bookmarks.is_empty());
+        assert!(data.conversations.is_empty() && data.threads.is_empty());
+        assert_eq!(data.lists[0].kind, ListKind::Created);
+        assert_eq!(data.lists[0].members, [777]);
+        assert_eq!(data.profiles[&777].screen_name, "");
+        assert_eq!(data.profiles[&12345].screen_name, "tester");
+
+        let entry = &data.media["https://pbs.twimg.com/profile_images/12345/tester.png"];
+        assert_eq!(entry.file, "tester.png");
+        assert_eq!(entry.mime.as_deref(), Some("image/png"));
+        assert!(entry.size.is_none() && entry.sha256.is_none());
+    }
+
+    #[test]
+    fn completes_the_tweet_meta_of_version_3() {
+        let mut value = baseline_data();
+        migrate_v0_to_v1(value.as_object_mut().unwrap()).unwrap();
+        migrate_v1_to_v2(value.as_object_mut().unwrap()).unwrap();
+        value[FIELD_VERSION] = Value::from(3);
+        value["tweet_meta"] = serde_json::json!({
+            "1500000000000000000": { "deleted_at": "2022-12-06T05:31:41Z" }
+        });
+
+        let data = migrate(value).unwrap();
+        let meta = &data.tweet_meta[&1500000000000000000];
+        assert!(meta.deleted_at.is_some());
+        assert!(!meta.deleted_via_twitvault);
+        assert!(meta.first_seen.is_none() && meta.unavailable_since.is_none());
+    }
+
+    #[test]
+    fn refuses_newer_schema_versions() {
+        let mut value = baseline_data();
+        value[FIELD_VERSION] = Value::from(SCHEMA_VERSION + 1);
+        let error = migrate(value).unwrap_err();
+        let error = error.downcast_ref::<NewerSchemaError>().unwrap();
+        assert_eq!(error.found, SCHEMA_VERSION + 1);
+    }
+}
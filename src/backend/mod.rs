@@ -0,0 +1,70 @@
+//! The different ways of persisting `Data`. The whole `Data` is always kept
+//! in memory, a backend only loads and saves it. The JSON backend rewrites
+//! the whole file on every save, the SQLite backend only the rows that changed.
+mod json;
+mod migrations;
+mod sqlite;
+mod users;
+
+use std::{path::Path, sync::Arc};
+
+use eyre::Result;
+use serde_json::Value;
+
+use crate::crypto::Vault;
+use crate::storage::Data;
+
+pub use json::JsonBackend;
+pub use migrations::{NewerSchemaError, SCHEMA_VERSION};
+pub use sqlite::SqliteBackend;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    Json,
+    Sqlite,
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendKind::Json => f.write_str("JSON"),
+            BackendKind::Sqlite => f.write_str("SQLite"),
+        }
+    }
+}
+
+pub trait StorageBackend: std::fmt::Debug + Send + Sync {
+    fn kind(&self) -> BackendKind;
+    fn load(&self) -> Result<Data>;
+    fn save(&self, data: &Data) -> Result<()>;
+    /// Whether the data is stored compressed
+    fn compressed(&self) -> bool {
+        false
+    }
+}
+
+/// Whether the storage folder contains data of any backend, even if it
+/// can't be read
+pub fn exists(root_folder: &Path) -> bool {
+    SqliteBackend::exists(root_folder) || JsonBackend::exists(root_folder)
+}
+
+/// If the storage folder contains a SQLite database, it is used.
+/// Otherwise, the data is stored as JSON. Only JSON can be encrypted
+pub fn detect(root_folder: &Path, vault: Option<Vault>) -> Arc<dyn StorageBackend> {
+    if SqliteBackend::exists(root_folder) {
+        Arc::new(SqliteBackend::new(root_folder))
+    } else {
+        Arc::new(JsonBackend::new(root_folder, vault))
+    }
+}
+
+/// The JSON representation of `Data` that the backends store. The authors
+/// of the tweets are only stored once, see `users`
+fn to_value(data: &Data) -> Result<Value> {
+    let mut value = serde_json::to_value(data)?;
+    if let Value::Object(fields) = &mut value {
+        users::strip(fields);
+    }
+    Ok(value)
+}
@@ -0,0 +1,750 @@
+//! Stores the data in an embedded SQLite database (`_data.sqlite`).
+//! Tweets, profiles, follows, lists and media get one row per entry, and so
+//! do threads, conversations and tweet meta (in `entries`). All other
+//! fields of `Data` (e.g. the profile of the owner) are stored as a whole in `meta`.
+//!
+//! A save only writes the rows that changed since the last save. The backend
+//! remembers a hash of every row it knows to be in the database for that.
+//! Positions count backwards from the end of a collection, so that tweets
+//! that are added at the front (e.g. by a sync) don't move the others.
+//!
+//! Rows are identified by the id of their entry, so an entry that appears
+//! twice in a collection (e.g. the same tweet twice in the likes) is only
+//! stored once.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use eyre::{bail, eyre, Result};
+use rusqlite::{params, Connection, Transaction};
+use serde_json::{Map, Value};
+use tracing::{debug, warn};
+
+use super::{migrations, BackendKind, StorageBackend};
+use crate::storage::Data;
+
+const FILE_DATABASE: &str = "_data.sqlite";
+/// A new database is written here first and then renamed to `FILE_DATABASE`
+const FILE_DATABASE_TEMP: &str = "_data.sqlite.tmp";
+
+/// The tweet collections in `Data` that are stored in the `tweets` table
+const TWEET_COLLECTIONS: [&str; 4] = ["tweets", "mentions", "likes", "bookmarks"];
+/// Responses are stored in the `tweets` table with the id of the tweet they respond to
+const RESPONSES: &str = "responses";
+/// The user id collections in `Data` that are stored in the `follows` table
+const FOLLOW_KINDS: [&str; 2] = ["followers", "follows"];
+/// The maps in `Data` that are stored in the `entries` table by their key
+const KEYED_COLLECTIONS: [&str; 2] = ["threads", "tweet_meta"];
+/// The conversations are stored in the `entries` table by their id, in order
+const CONVERSATIONS: &str = "conversations";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tweets (
+    collection TEXT NOT NULL,
+    parent_id INTEGER,
+    position INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tweets_by_collection ON tweets (collection, parent_id, position);
+CREATE INDEX IF NOT EXISTS tweets_by_id ON tweets (collection, id);
+CREATE TABLE IF NOT EXISTS profiles (
+    id INTEGER PRIMARY KEY,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS follows (
+    kind TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    user_id INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS follows_by_user ON follows (kind, user_id);
+CREATE TABLE IF NOT EXISTS lists (
+    position INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS lists_by_id ON lists (id);
+CREATE TABLE IF NOT EXISTS media (
+    url TEXT PRIMARY KEY,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS entries (
+    collection TEXT NOT NULL,
+    key TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    json TEXT NOT NULL,
+    PRIMARY KEY (collection, key)
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    json TEXT NOT NULL
+);
+";
+
+/// Identifies a row of one of the tables across saves
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum RowKey {
+    Tweet {
+        collection: &'static str,
+        parent_id: Option<i64>,
+        id: i64,
+    },
+    Profile(i64),
+    Follow {
+        kind: &'static str,
+        user_id: i64,
+    },
+    List(i64),
+    Media(String),
+    Entry {
+        collection: &'static str,
+        key: String,
+    },
+    Meta(String),
+}
+
+/// The content of a row. `position` is only used by the ordered tables
+struct Row {
+    position: i64,
+    json: String,
+}
+
+impl Row {
+    fn new(position: i64, value: &Value) -> Self {
+        Self {
+            position,
+            json: value.to_string(),
+        }
+    }
+
+    fn from_columns(position: i64, json: String) -> Self {
+        Self { position, json }
+    }
+
+    fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.position.hash(&mut hasher);
+        self.json.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// The hashes of the rows in the database
+type Snapshot = HashMap<RowKey, u64>;
+
+#[derive(Debug)]
+pub struct SqliteBackend {
+    database_path: PathBuf,
+    /// What the database contains, read on the first save.
+    /// `None` if it is unknown, e.g. after a failed save
+    snapshot: Mutex<Option<Snapshot>>,
+}
+
+impl SqliteBackend {
+    pub fn new(root_folder: &Path) -> Self {
+        Self {
+            database_path: root_folder.join(FILE_DATABASE),
+            snapshot: Mutex::new(None),
+        }
+    }
+
+    pub fn exists(root_folder: &Path) -> bool {
+        root_folder.join(FILE_DATABASE).exists()
+    }
+
+    /// Create a new database with `data`. The database only replaces an existing
+    /// one once it is complete, so an interrupted migration leaves nothing behind.
+    pub fn create(root_folder: &Path, data: &Data) -> Result<Self> {
+        let temp_path = root_folder.join(FILE_DATABASE_TEMP);
+        if temp_path.exists() {
+            std::fs::remove_file(&temp_path)?;
+        }
+        let mut connection = connect(&temp_path)?;
+        let mut snapshot = Snapshot::new();
+        write_data(&mut connection, data, &mut snapshot)?;
+        connection.close().map_err(|(_, e)| e)?;
+
+        let backend = Self {
+            database_path: root_folder.join(FILE_DATABASE),
+            snapshot: Mutex::new(Some(snapshot)),
+        };
+        std::fs::rename(&temp_path, &backend.database_path)?;
+        Ok(backend)
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Sqlite
+    }
+
+    fn load(&self) -> Result<Data> {
+        let connection = connect(&self.database_path)?;
+        let value = read_value(&connection)?;
+        migrations::migrate(value)
+    }
+
+    fn save(&self, data: &Data) -> Result<()> {
+        let mut connection = connect(&self.database_path)?;
+        let mut known = self.snapshot.lock().map_err(|_| eyre!("Poisoned lock"))?;
+        let mut snapshot = match known.take() {
+            Some(snapshot) => snapshot,
+            None => read_snapshot(&connection)?,
+        };
+        write_data(&mut connection, data, &mut snapshot)?;
+        *known = Some(snapshot);
+        Ok(())
+    }
+}
+
+fn connect(path: &Path) -> Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(connection)
+}
+
+/// Assemble the JSON representation of `Data` from the tables
+fn read_value(connection: &Connection) -> Result<Value> {
+    let mut fields = Map::new();
+
+    let mut select = connection.prepare("SELECT key, json FROM meta")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let json: String = row.get(1)?;
+        fields.insert(row.get(0)?, serde_json::from_str(&json)?);
+    }
+
+    let mut select = connection.prepare(
+        "SELECT json FROM tweets WHERE collection = ?1 AND parent_id IS NULL ORDER BY position",
+    )?;
+    for collection in TWEET_COLLECTIONS {
+        let mut tweets = Vec::new();
+        let mut rows = select.query([collection])?;
+        while let Some(row) = rows.next()? {
+            let json: String = row.get(0)?;
+            tweets.push(serde_json::from_str(&json)?);
+        }
+        fields.insert(collection.to_string(), Value::Array(tweets));
+    }
+
+    let mut responses = Map::new();
+    let mut select = connection
+        .prepare("SELECT parent_id, json FROM tweets WHERE collection = ?1 ORDER BY position")?;
+    let mut rows = select.query([RESPONSES])?;
+    while let Some(row) = rows.next()? {
+        let parent_id: i64 = row.get(0)?;
+        let json: String = row.get(1)?;
+        let entry = responses
+            .entry((parent_id as u64).to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(tweets) = entry {
+            tweets.push(serde_json::from_str(&json)?);
+        }
+    }
+    fields.insert(RESPONSES.to_string(), Value::Object(responses));
+
+    let mut profiles = Map::new();
+    let mut select = connection.prepare("SELECT id, json FROM profiles")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let json: String = row.get(1)?;
+        profiles.insert((id as u64).to_string(), serde_json::from_str(&json)?);
+    }
+    fields.insert("profiles".to_string(), Value::Object(profiles));
+
+    let mut select =
+        connection.prepare("SELECT user_id FROM follows WHERE kind = ?1 ORDER BY position")?;
+    for kind in FOLLOW_KINDS {
+        let mut ids = Vec::new();
+        let mut rows = select.query([kind])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            ids.push(Value::from(id as u64));
+        }
+        fields.insert(kind.to_string(), Value::Array(ids));
+    }
+
+    let mut lists = Vec::new();
+    let mut select = connection.prepare("SELECT json FROM lists ORDER BY position")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let json: String = row.get(0)?;
+        lists.push(serde_json::from_str(&json)?);
+    }
+    fields.insert("lists".to_string(), Value::Array(lists));
+
+    let mut media = Map::new();
+    let mut select = connection.prepare("SELECT url, json FROM media")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let json: String = row.get(1)?;
+        media.insert(row.get(0)?, serde_json::from_str(&json)?);
+    }
+    fields.insert("media".to_string(), Value::Object(media));
+
+    // Databases of earlier versions have these in `meta`, until the next save
+    let mut select = connection
+        .prepare("SELECT key, json FROM entries WHERE collection = ?1 ORDER BY position")?;
+    for collection in KEYED_COLLECTIONS {
+        let mut entries = Map::new();
+        let mut rows = select.query([collection])?;
+        while let Some(row) = rows.next()? {
+            let json: String = row.get(1)?;
+            entries.insert(row.get(0)?, serde_json::from_str(&json)?);
+        }
+        if !entries.is_empty() || !fields.contains_key(collection) {
+            fields.insert(collection.to_string(), Value::Object(entries));
+        }
+    }
+    let mut conversations = Vec::new();
+    let mut rows = select.query([CONVERSATIONS])?;
+    while let Some(row) = rows.next()? {
+        let json: String = row.get(1)?;
+        conversations.push(serde_json::from_str(&json)?);
+    }
+    if !conversations.is_empty() || !fields.contains_key(CONVERSATIONS) {
+        fields.insert(CONVERSATIONS.to_string(), Value::Array(conversations));
+    }
+
+    Ok(Value::Object(fields))
+}
+
+/// Write the rows of `data` that differ from `snapshot` in one transaction,
+/// and delete the rows that are gone. `snapshot` is updated to the new content
+fn write_data(connection: &mut Connection, data: &Data, snapshot: &mut Snapshot) -> Result<()> {
+    let Value::Object(fields) = super::to_value(data)? else {
+        bail!("Data is not serialized as an object")
+    };
+    let transaction = connection.transaction()?;
+    let mut written = Snapshot::with_capacity(snapshot.len());
+    let mut changed = 0;
+    for_each_row(fields, |key, row| {
+        let hash = row.hash();
+        // A duplicate would overwrite the row of the first one
+        if written.contains_key(&key) {
+            warn!("Not storing the duplicate {key:?}");
+            return Ok(());
+        }
+        if snapshot.get(&key) != Some(&hash) {
+            write_row(&transaction, &key, &row)?;
+            changed += 1;
+        }
+        written.insert(key, hash);
+        Ok(())
+    })?;
+    let mut deleted = 0;
+    for key in snapshot.keys().filter(|key| !written.contains_key(key)) {
+        delete_row(&transaction, key)?;
+        deleted += 1;
+    }
+    transaction.commit()?;
+    debug!("Saved {changed} changed rows, deleted {deleted} rows");
+    *snapshot = written;
+    Ok(())
+}
+
+/// Split the JSON representation of `Data` into the rows of the tables
+fn for_each_row(
+    mut fields: Map<String, Value>,
+    mut action: impl FnMut(RowKey, Row) -> Result<()>,
+) -> Result<()> {
+    for collection in TWEET_COLLECTIONS {
+        let tweets = take_array(&mut fields, collection);
+        for (position, tweet) in with_positions(&tweets) {
+            let key = RowKey::Tweet {
+                collection,
+                parent_id: None,
+                id: id_of(tweet),
+            };
+            action(key, Row::new(position, tweet))?;
+        }
+    }
+    for (parent_id, tweets) in take_object(&mut fields, RESPONSES) {
+        let parent_id = parent_id.parse::<u64>()? as i64;
+        let Value::Array(tweets) = tweets else {
+            continue
+        };
+        for (position, tweet) in with_positions(&tweets) {
+            let key = RowKey::Tweet {
+                collection: RESPONSES,
+                parent_id: Some(parent_id),
+                id: id_of(tweet),
+            };
+            action(key, Row::new(position, tweet))?;
+        }
+    }
+
+    for (id, profile) in take_object(&mut fields, "profiles") {
+        let key = RowKey::Profile(id.parse::<u64>()? as i64);
+        action(key, Row::new(0, &profile))?;
+    }
+
+    for kind in FOLLOW_KINDS {
+        let ids = take_array(&mut fields, kind);
+        for (position, id) in with_positions(&ids) {
+            let user_id = id.as_u64().unwrap_or_default() as i64;
+            let row = Row {
+                position,
+                json: String::new(),
+            };
+            action(RowKey::Follow { kind, user_id }, row)?;
+        }
+    }
+
+    let lists = take_array(&mut fields, "lists");
+    for (position, list) in with_positions(&lists) {
+        let id = list.get("list").map(id_of).unwrap_or_default();
+        action(RowKey::List(id), Row::new(position, list))?;
+    }
+
+    for (url, file) in take_object(&mut fields, "media") {
+        action(RowKey::Media(url), Row::new(0, &file))?;
+    }
+
+    for collection in KEYED_COLLECTIONS {
+        for (key, value) in take_object(&mut fields, collection) {
+            action(RowKey::Entry { collection, key }, Row::new(0, &value))?;
+        }
+    }
+    let conversations = take_array(&mut fields, CONVERSATIONS);
+    for (position, conversation) in with_positions(&conversations) {
+        let key = conversation
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let key = RowKey::Entry {
+            collection: CONVERSATIONS,
+            key,
+        };
+        action(key, Row::new(position, conversation))?;
+    }
+
+    // Everything else, e.g. the profile of the owner
+    for (key, value) in fields {
+        action(RowKey::Meta(key), Row::new(0, &value))?;
+    }
+
+    Ok(())
+}
+
+fn write_row(transaction: &Transaction, key: &RowKey, row: &Row) -> Result<()> {
+    let position = row.position;
+    let json = row.json.as_str();
+    let updated = match key {
+        RowKey::Tweet {
+            collection,
+            parent_id,
+            id,
+        } => transaction
+            .prepare_cached(
+                "UPDATE tweets SET position = ?4, json = ?5
+                 WHERE collection = ?1 AND parent_id IS ?2 AND id = ?3",
+            )?
+            .execute(params![collection, parent_id, id, position, json])?,
+        RowKey::Follow { kind, user_id } => transaction
+            .prepare_cached("UPDATE follows SET position = ?3 WHERE kind = ?1 AND user_id = ?2")?
+            .execute(params![kind, user_id, position])?,
+        RowKey::List(id) => transaction
+            .prepare_cached("UPDATE lists SET position = ?2, json = ?3 WHERE id = ?1")?
+            .execute(params![id, position, json])?,
+        // The other tables have a primary key
+        RowKey::Profile(id) => transaction
+            .prepare_cached("INSERT OR REPLACE INTO profiles (id, json) VALUES (?1, ?2)")?
+            .execute(params![id, json])?,
+        RowKey::Media(url) => transaction
+            .prepare_cached("INSERT OR REPLACE INTO media (url, json) VALUES (?1, ?2)")?
+            .execute(params![url, json])?,
+        RowKey::Entry { collection, key } => transaction
+            .prepare_cached(
+                "INSERT OR REPLACE INTO entries (collection, key, position, json)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute(params![collection, key, position, json])?,
+        RowKey::Meta(name) => transaction
+            .prepare_cached("INSERT OR REPLACE INTO meta (key, json) VALUES (?1, ?2)")?
+            .execute(params![name, json])?,
+    };
+    if updated > 0 {
+        return Ok(());
+    }
+    match key {
+        RowKey::Tweet {
+            collection,
+            parent_id,
+            id,
+        } => transaction
+            .prepare_cached(
+                "INSERT INTO tweets (collection, parent_id, id, position, json)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![collection, parent_id, id, position, json])?,
+        RowKey::Follow { kind, user_id } => transaction
+            .prepare_cached("INSERT INTO follows (kind, position, user_id) VALUES (?1, ?2, ?3)")?
+            .execute(params![kind, position, user_id])?,
+        RowKey::List(id) => transaction
+            .prepare_cached("INSERT INTO lists (position, id, json) VALUES (?1, ?2, ?3)")?
+            .execute(params![position, id, json])?,
+        // Already inserted by `INSERT OR REPLACE`
+        RowKey::Profile(_) | RowKey::Media(_) | RowKey::Entry { .. } | RowKey::Meta(_) => 0,
+    };
+    Ok(())
+}
+
+fn delete_row(transaction: &Transaction, key: &RowKey) -> Result<()> {
+    match key {
+        RowKey::Tweet {
+            collection,
+            parent_id,
+            id,
+        } => transaction
+            .prepare_cached(
+                "DELETE FROM tweets WHERE collection = ?1 AND parent_id IS ?2 AND id = ?3",
+            )?
+            .execute(params![collection, parent_id, id])?,
+        RowKey::Profile(id) => transaction
+            .prepare_cached("DELETE FROM profiles WHERE id = ?1")?
+            .execute(params![id])?,
+        RowKey::Follow { kind, user_id } => transaction
+            .prepare_cached("DELETE FROM follows WHERE kind = ?1 AND user_id = ?2")?
+            .execute(params![kind, user_id])?,
+        RowKey::List(id) => transaction
+            .prepare_cached("DELETE FROM lists WHERE id = ?1")?
+            .execute(params![id])?,
+        RowKey::Media(url) => transaction
+            .prepare_cached("DELETE FROM media WHERE url = ?1")?
+            .execute(params![url])?,
+        RowKey::Entry { collection, key } => transaction
+            .prepare_cached("DELETE FROM entries WHERE collection = ?1 AND key = ?2")?
+            .execute(params![collection, key])?,
+        RowKey::Meta(name) => transaction
+            .prepare_cached("DELETE FROM meta WHERE key = ?1")?
+            .execute(params![name])?,
+    };
+    Ok(())
+}
+
+/// The hashes of all rows that are in the database
+fn read_snapshot(connection: &Connection) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+
+    let mut select =
+        connection.prepare("SELECT collection, parent_id, id, position, json FROM tweets")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let collection: String = row.get(0)?;
+        // Rows of collections we don't know are deleted by the next save
+        let mut known = TWEET_COLLECTIONS.into_iter().chain([RESPONSES]);
+        let Some(collection) = known.find(|known| *known == collection) else {
+            continue
+        };
+        let key = RowKey::Tweet {
+            collection,
+            parent_id: row.get(1)?,
+            id: row.get(2)?,
+        };
+        snapshot.insert(key, Row::from_columns(row.get(3)?, row.get(4)?).hash());
+    }
+
+    let mut select = connection.prepare("SELECT id, json FROM profiles")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash = Row::from_columns(0, row.get(1)?).hash();
+        snapshot.insert(RowKey::Profile(row.get(0)?), hash);
+    }
+
+    let mut select = connection.prepare("SELECT kind, user_id, position FROM follows")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let kind: String = row.get(0)?;
+        let Some(kind) = FOLLOW_KINDS.into_iter().find(|known| *known == kind) else {
+            continue
+        };
+        let user_id = row.get(1)?;
+        let hash = Row::from_columns(row.get(2)?, String::new()).hash();
+        snapshot.insert(RowKey::Follow { kind, user_id }, hash);
+    }
+
+    let mut select = connection.prepare("SELECT id, position, json FROM lists")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash = Row::from_columns(row.get(1)?, row.get(2)?).hash();
+        snapshot.insert(RowKey::List(row.get(0)?), hash);
+    }
+
+    let mut select = connection.prepare("SELECT url, json FROM media")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash = Row::from_columns(0, row.get(1)?).hash();
+        snapshot.insert(RowKey::Media(row.get(0)?), hash);
+    }
+
+    let mut select = connection.prepare("SELECT collection, key, position, json FROM entries")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let collection: String = row.get(0)?;
+        let mut known = KEYED_COLLECTIONS.into_iter().chain([CONVERSATIONS]);
+        let Some(collection) = known.find(|known| *known == collection) else {
+            continue
+        };
+        let key = RowKey::Entry {
+            collection,
+            key: row.get(1)?,
+        };
+        snapshot.insert(key, Row::from_columns(row.get(2)?, row.get(3)?).hash());
+    }
+
+    let mut select = connection.prepare("SELECT key, json FROM meta")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash = Row::from_columns(0, row.get(1)?).hash();
+        snapshot.insert(RowKey::Meta(row.get(0)?), hash);
+    }
+
+    Ok(snapshot)
+}
+
+/// The values with their positions. The last value has the position -1,
+/// so the positions of the values don't change if values are added in front
+fn with_positions(values: &[Value]) -> impl Iterator<Item = (i64, &Value)> {
+    let count = values.len() as i64;
+    values
+        .iter()
+        .enumerate()
+        .map(move |(index, value)| (index as i64 - count, value))
+}
+
+fn id_of(value: &Value) -> i64 {
+    value.get("id").and_then(Value::as_u64).unwrap_or_default() as i64
+}
+
+fn take_array(fields: &mut Map<String, Value>, key: &str) -> Vec<Value> {
+    match fields.remove(key) {
+        Some(Value::Array(values)) => values,
+        _ => Vec::new(),
+    }
+}
+
+fn take_object(fields: &mut Map<String, Value>, key: &str) -> Map<String, Value> {
+    match fields.remove(key) {
+        Some(Value::Object(values)) => values,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use egg_mode::tweet::Tweet;
+
+    use super::*;
+    use crate::helpers::placeholder_profile;
+    use crate::storage::{Conversation, DirectMessage, Storage, Thread, TweetId, TweetMeta};
+
+    /// A tweet of the crawl fixtures with another id
+    fn tweet(id: TweetId) -> Tweet {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/crawl/timeline-user-12345.json"
+        );
+        let tweets: Vec<Value> = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        let mut tweet = tweets[0].clone();
+        tweet["id"] = id.into();
+        serde_json::from_value(tweet).unwrap()
+    }
+
+    fn conversation(id: &str, messages: u64) -> Conversation {
+        Conversation {
+            id: id.to_string(),
+            name: None,
+            participants: vec![1, 2],
+            messages: (0..messages)
+                .map(|id| DirectMessage {
+                    id,
+                    sender_id: 1,
+                    recipient_id: Some(2),
+                    created_at: Utc::now(),
+                    text: format!("Message {id}"),
+                    media: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn sample_data(root: &Path) -> Data {
+        let profile = placeholder_profile(1, "Tester", "tester");
+        let storage = Storage::new(profile, root, None).unwrap();
+        let mut data = storage.data().clone();
+        data.tweets = vec![tweet(3), tweet(2), tweet(1)];
+        data.likes = vec![tweet(10)];
+        data.followers = vec![2, 3];
+        data.threads.insert(1, Thread::new(tweet(1)));
+        data.tweet_meta.insert(
+            2,
+            TweetMeta {
+                first_seen: Some(Utc::now()),
+                ..TweetMeta::default()
+            },
+        );
+        data.conversations = vec![conversation("1-2", 2), conversation("1-3", 1)];
+        // Loading puts the authors of the tweets into the profiles
+        let author = tweet(1).user.unwrap();
+        data.profiles.insert(author.id, *author);
+        data
+    }
+
+    fn assert_same(loaded: &Data, expected: &Data) {
+        assert_eq!(
+            serde_json::to_value(loaded).unwrap(),
+            serde_json::to_value(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn data_round_trips() {
+        let folder = tempfile::tempdir().unwrap();
+        let data = sample_data(&folder.path().join("storage"));
+        let backend = SqliteBackend::create(folder.path(), &data).unwrap();
+        assert_same(&backend.load().unwrap(), &data);
+        assert_same(&SqliteBackend::new(folder.path()).load().unwrap(), &data);
+
+        let connection = connect(&folder.path().join(FILE_DATABASE)).unwrap();
+        let count = |sql: &str| -> i64 { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM entries"), 4);
+        let blobs =
+            "SELECT COUNT(*) FROM meta WHERE key IN ('threads', 'tweet_meta', 'conversations')";
+        assert_eq!(count(blobs), 0);
+    }
+
+    #[test]
+    fn saves_updates_and_deletions() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut data = sample_data(&folder.path().join("storage"));
+        let backend = SqliteBackend::create(folder.path(), &data).unwrap();
+
+        // Through the backend that created the database
+        data.tweets.insert(0, tweet(4));
+        data.tweets.remove(2);
+        data.likes[0].favorite_count = 5;
+        data.followers.pop();
+        data.threads.get_mut(&1).unwrap().insert(tweet(5));
+        data.tweet_meta.get_mut(&2).unwrap().deleted_at = Some(Utc::now());
+        data.conversations[0] = conversation("1-2", 3);
+        data.conversations.pop();
+        backend.save(&data).unwrap();
+        assert_same(&backend.load().unwrap(), &data);
+
+        // Through a backend that reads what's in the database first
+        let backend = SqliteBackend::new(folder.path());
+        data.tweets.truncate(1);
+        data.threads.clear();
+        data.tweet_meta.insert(4, TweetMeta::default());
+        data.conversations.insert(0, conversation("1-4", 1));
+        backend.save(&data).unwrap();
+        assert_same(&backend.load().unwrap(), &data);
+        assert_same(&SqliteBackend::new(folder.path()).load().unwrap(), &data);
+    }
+}
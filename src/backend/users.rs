@@ -0,0 +1,220 @@
+//! Every tweet embeds the full profile of its author, so the same profile is
+//! repeated in thousands of tweets. When saving, these profiles are moved
+//! into `profiles` and the tweets only keep the id of the author. When
+//! loading, the profiles are put back into the tweets.
+//!
+//! A tweet that is read again carries the profile from `profiles`, not the
+//! profile it was crawled with. `profiles` is also filled by the profile
+//! lookups of the crawler, which are usually more recent than the tweets.
+//! So the profile from the newest tweet of an author is only used if
+//! `profiles` has no profile (or only a placeholder) for the author.
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::helpers::placeholder_profile;
+
+/// The collections of `Data` that contain lists of tweets
+const TWEET_COLLECTIONS: [&str; 4] = ["tweets", "mentions", "likes", "bookmarks"];
+/// Responses are a map from a tweet id to a list of tweets
+const RESPONSES: &str = "responses";
+/// Threads are a map from a tweet id to a thread with a list of tweets
+const THREADS: &str = "threads";
+const PROFILES: &str = "profiles";
+/// Tweets can contain the tweet they quote or retweet
+const NESTED_TWEETS: [&str; 2] = ["quoted_status", "retweeted_status"];
+/// Tweets with an unknown author (e.g. imported likes) keep their placeholder
+const UNKNOWN_AUTHOR: u64 = 0;
+
+/// Replace the author of every tweet with the id of the author. The
+/// profiles from the tweets are moved into `profiles` if it doesn't have them
+pub fn strip(fields: &mut Map<String, Value>) {
+    let mut profiles = match fields.remove(PROFILES) {
+        Some(Value::Object(profiles)) => profiles,
+        _ => Map::new(),
+    };
+    let mut embedded = HashMap::new();
+    for tweet in tweets_mut(fields) {
+        strip_tweet(tweet, &mut embedded);
+    }
+    for (key, (_, user)) in embedded {
+        // Same rule as `helpers::is_placeholder_profile`: only a placeholder
+        // (e.g. from an archive import) is replaced by a real profile
+        let replace = match profiles.get(&key) {
+            None => true,
+            Some(existing) => is_placeholder(existing) && !is_placeholder(&user),
+        };
+        if replace {
+            profiles.insert(key, user);
+        }
+    }
+    fields.insert(PROFILES.to_string(), Value::Object(profiles));
+}
+
+/// Put the profiles from `profiles` back into the tweets. Tweets that
+/// still embed their author (e.g. written before stripping) are kept as they are
+pub fn rehydrate(fields: &mut Map<String, Value>) {
+    let mut profiles = match fields.remove(PROFILES) {
+        Some(Value::Object(profiles)) => profiles,
+        _ => Map::new(),
+    };
+    // Written by earlier versions, which stripped the unknown author as well
+    profiles.remove(&UNKNOWN_AUTHOR.to_string());
+    for tweet in tweets_mut(fields) {
+        rehydrate_tweet(tweet, &mut profiles);
+    }
+    fields.insert(PROFILES.to_string(), Value::Object(profiles));
+}
+
+fn tweets_mut(fields: &mut Map<String, Value>) -> impl Iterator<Item = &mut Value> {
+    fields
+        .iter_mut()
+        .filter(|(key, _)| {
+            TWEET_COLLECTIONS.contains(&key.as_str())
+                || [RESPONSES, THREADS].contains(&key.as_str())
+        })
+        .flat_map(|(key, value)| match (key.as_str(), value) {
+            (RESPONSES, Value::Object(responses)) => responses
+                .values_mut()
+                .filter_map(Value::as_array_mut)
+                .flat_map(|tweets| tweets.iter_mut())
+                .collect::<Vec<_>>(),
+            (THREADS, Value::Object(threads)) => threads
+                .values_mut()
+                .filter_map(|thread| thread.get_mut("tweets"))
+                .filter_map(Value::as_array_mut)
+                .flat_map(|tweets| tweets.iter_mut())
+                .collect(),
+            (_, Value::Array(tweets)) => tweets.iter_mut().collect(),
+            _ => Vec::new(),
+        })
+}
+
+/// `embedded` keeps the profile of the newest tweet of each author, by
+/// the id of the author
+fn strip_tweet(tweet: &mut Value, embedded: &mut HashMap<String, (u64, Value)>) {
+    let Some(tweet) = tweet.as_object_mut() else {
+        return
+    };
+    for key in NESTED_TWEETS {
+        if let Some(nested) = tweet.get_mut(key) {
+            strip_tweet(nested, embedded);
+        }
+    }
+    let tweet_id = tweet.get("id").and_then(Value::as_u64).unwrap_or_default();
+    let Some(id) = tweet
+        .get("user")
+        .and_then(|user| user.get("id"))
+        .and_then(Value::as_u64)
+        .filter(|id| *id != UNKNOWN_AUTHOR) else {
+        return
+    };
+    let Some(Value::Object(user)) = tweet.insert("user".to_string(), Value::from(id)) else {
+        return
+    };
+    let user = Value::Object(user);
+    let key = id.to_string();
+    // The collections are visited in no particular order, the tweet ids decide
+    let replace = match embedded.get(&key) {
+        None => true,
+        Some((newest, known)) => match (is_placeholder(known), is_placeholder(&user)) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => tweet_id > *newest,
+        },
+    };
+    if replace {
+        embedded.insert(key, (tweet_id, user));
+    }
+}
+
+fn rehydrate_tweet(tweet: &mut Value, profiles: &mut Map<String, Value>) {
+    let Some(tweet) = tweet.as_object_mut() else {
+        return
+    };
+    for key in NESTED_TWEETS {
+        if let Some(nested) = tweet.get_mut(key) {
+            rehydrate_tweet(nested, profiles);
+        }
+    }
+    let Some(id) = tweet.get("user").and_then(Value::as_u64) else {
+        return
+    };
+    if id == UNKNOWN_AUTHOR {
+        let placeholder = placeholder_profile(id, "Unknown Author", "");
+        let user = serde_json::to_value(placeholder).expect("Expect a profile to serialize");
+        tweet.insert("user".to_string(), user);
+        return;
+    }
+    // A placeholder keeps the author, a sync can look the profile up again
+    let user = profiles.entry(id.to_string()).or_insert_with(|| {
+        warn!("Missing profile {id} of a tweet");
+        let placeholder = placeholder_profile(id, &format!("User {id}"), "");
+        serde_json::to_value(placeholder).expect("Expect a profile to serialize")
+    });
+    tweet.insert("user".to_string(), user.clone());
+}
+
+fn is_placeholder(profile: &Value) -> bool {
+    profile
+        .get("profile_image_url_https")
+        .and_then(Value::as_str)
+        .map_or(true, str::is_empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn profile(name: &str, image: &str) -> Value {
+        json!({ "id": 7, "name": name, "profile_image_url_https": image })
+    }
+
+    #[test]
+    fn strip_keeps_looked_up_profiles() {
+        let mut fields = json!({
+            "tweets": [{ "id": 2, "user": profile("Tweeted", "a.jpg") }],
+            "likes": [{ "id": 1, "user": profile("Older", "b.jpg") }],
+            "profiles": { "7": profile("Looked up", "c.jpg") },
+        });
+        let fields = fields.as_object_mut().unwrap();
+        strip(fields);
+        assert_eq!(fields["profiles"]["7"]["name"], "Looked up");
+        assert_eq!(fields["tweets"][0]["user"], 7);
+    }
+
+    #[test]
+    fn strip_replaces_placeholders() {
+        let mut fields = json!({
+            "tweets": [{ "id": 2, "user": profile("Tweeted", "a.jpg") }],
+            "likes": [{ "id": 1, "user": profile("Older", "b.jpg") }],
+            "profiles": { "7": profile("Placeholder", "") },
+        });
+        let fields = fields.as_object_mut().unwrap();
+        strip(fields);
+        assert_eq!(fields["profiles"]["7"]["name"], "Tweeted");
+    }
+
+    #[test]
+    fn unknown_authors_stay_in_their_tweets() {
+        let mut fields = json!({
+            "likes": [{ "id": 1, "user": { "id": 0, "screen_name": "" } }],
+            "profiles": {},
+        });
+        let fields = fields.as_object_mut().unwrap();
+        strip(fields);
+        assert_eq!(fields["profiles"], json!({}));
+        assert_eq!(fields["likes"][0]["user"]["id"], 0);
+
+        // Earlier versions stored the unknown author with `i` as its screen name
+        fields.insert("likes".to_string(), json!([{ "id": 1, "user": 0 }]));
+        let legacy = json!({ "0": { "id": 0, "screen_name": "i" } });
+        fields.insert("profiles".to_string(), legacy);
+        rehydrate(fields);
+        assert_eq!(fields["profiles"], json!({}));
+        assert_eq!(fields["likes"][0]["user"]["screen_name"], "");
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::{
     collections::HashMap,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{Arc, Mutex},
 };
@@ -13,11 +13,49 @@ use eyre::{bail, Result};
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
+use crate::api::{EggModeApi, FixtureApi, TwitterApi, TwitterV2Api};
+use crate::crawler::MediaSource;
+use crate::crypto::{self, Vault};
+
 const ARCHIVE_PATH: &str = "archive";
 const SETTINGS_FILE: &str = "twitter_settings.json";
 const PAGING_FILE: &str = "paging_positions.json";
+/// The account in a fixtures folder
+const FIXTURES_ACCOUNT_FILE: &str = "account.json";
+/// The media files in a fixtures folder
+const FIXTURES_MEDIA_FOLDER: &str = "media";
+
+type PagingPositions = HashMap<String, PagingPosition>;
+
+/// Where a crawl continues. The API v1.1 pages with tweet ids and cursors,
+/// the API v2 with pagination tokens
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PagingPosition {
+    Id(u64),
+    Token(String),
+}
+
+/// The Twitter API the crawler uses
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    V2,
+}
 
-type PagingPositions = HashMap<String, u64>;
+impl FromStr for ApiVersion {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "v1" | "1.1" => Ok(ApiVersion::V1),
+            "v2" | "2" => Ok(ApiVersion::V2),
+            _ => bail!("Unknown API version {s}, use v1 or v2"),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -27,11 +65,17 @@ pub struct Config {
     pub is_sync: bool,
     pub token: egg_mode::Token,
     pub config_data: ConfigData,
+    /// The API the crawler uses
+    api: Arc<dyn TwitterApi>,
     /// Remember the paging positions for the different endpoints,
     /// so that restarting the crawler will continue where it left off.
     paging_positions: Arc<Mutex<PagingPositions>>,
     /// If this is a config for a custom path
     custom_path: Option<PathBuf>,
+    /// If the storage folder is encrypted
+    vault: Option<Vault>,
+    /// The recorded responses the API serves, see `Config::for_fixtures`
+    fixtures: Option<PathBuf>,
 }
 
 impl PartialEq for Config {
@@ -82,14 +126,73 @@ impl Config {
     pub fn set_crawl_options(&mut self, options: &CrawlOptions) {
         self.config_data.crawl_options = options.clone();
     }
+
+    pub fn api(&self) -> &dyn TwitterApi {
+        self.api.as_ref()
+    }
+
+    /// Where the crawler downloads media from
+    pub fn media_source(&self) -> MediaSource {
+        match &self.fixtures {
+            Some(folder) => MediaSource::Fixtures(folder.join(FIXTURES_MEDIA_FOLDER)),
+            None => MediaSource::Twitter,
+        }
+    }
+
+    pub fn api_version(&self) -> ApiVersion {
+        self.config_data.api_version
+    }
+
+    /// Crawl with `version` from now on
+    pub fn set_api_version(&mut self, version: ApiVersion) -> Result<()> {
+        self.config_data.api_version = version;
+        self.api = api_for(&self.token, &self.config_data);
+        self.config_data
+            .write(self.custom_path.clone(), self.vault.as_ref())
+    }
+
+    pub fn vault(&self) -> Option<&Vault> {
+        self.vault.as_ref()
+    }
+
+    /// Encrypt the settings file and the paging positions with `vault` from now on
+    pub fn encrypt(&mut self, vault: Vault) -> Result<()> {
+        self.config_data
+            .write(self.custom_path.clone(), Some(&vault))?;
+        Self::encrypt_files(self.custom_path.clone(), &vault)?;
+        self.vault = Some(vault);
+        Ok(())
+    }
+
+    /// Encrypt the settings files of a storage folder, also if they couldn't
+    /// be opened. Older versions stored the settings next to the storage
+    /// folder, they are moved into it
+    pub fn encrypt_files(custom_path: Option<PathBuf>, vault: &Vault) -> Result<()> {
+        let config_path = Config::config_path(custom_path.clone());
+        let old_path = Config::storage_path(custom_path.clone())
+            .parent()
+            .map(|parent| parent.join(SETTINGS_FILE));
+        if let Some(old_path) = old_path.filter(|path| path.exists()) {
+            if !config_path.exists() {
+                std::fs::copy(&old_path, &config_path)?;
+            }
+            crypto::remove_file_securely(&old_path)?;
+        }
+        for path in [config_path, Config::paging_path(custom_path)] {
+            if path.exists() {
+                crypto::encrypt_file(&path, vault)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Config {
-    pub fn paging_position(&self, key: &str) -> Option<u64> {
-        self.paging_positions.lock().ok()?.get(key).copied()
+    pub fn paging_position(&self, key: &str) -> Option<PagingPosition> {
+        self.paging_positions.lock().ok()?.get(key).cloned()
     }
 
-    pub fn set_paging_position(&self, key: &str, value: Option<u64>) {
+    pub fn set_paging_position(&self, key: &str, value: Option<PagingPosition>) {
         let Ok(mut lock) = self.paging_positions.lock() else { return };
         if let Some(value) = value {
             lock.insert(key.to_string(), value);
@@ -97,12 +200,12 @@ impl Config {
             lock.remove(key);
         }
         let paging_path = Config::paging_path(self.custom_path.clone());
-        let Ok(f) = std::fs::File::create(paging_path.clone()) else {
-            warn!("Could not create / save {}", &paging_path.display());
-            return
-        };
-        if let Err(e) = serde_json::to_writer(f, &(*lock)) {
-            warn!("Could not serialize {}: {e:?}", &paging_path.display());
+        let output = serde_json::to_vec(&(*lock))
+            .map_err(eyre::Report::from)
+            .and_then(|output| crypto::encrypt_if_needed(output, self.vault.as_ref()));
+        let written = output.and_then(|output| Ok(std::fs::write(&paging_path, output)?));
+        if let Err(e) = written {
+            warn!("Could not save {}: {e:?}", &paging_path.display());
         }
     }
 }
@@ -116,7 +219,7 @@ impl Config {
 
         egg_mode::KeyPair::new(consumer_key, consumer_secret)
     }
-    pub fn open(custom_path: Option<PathBuf>) -> Result<Self> {
+    pub fn open(custom_path: Option<PathBuf>, vault: Option<Vault>) -> Result<Self> {
         let con_token = Self::keypair();
 
         let (token, config_data, paging_positions) = {
@@ -139,13 +242,9 @@ impl Config {
                     )
                 }
             }
-            let fp = std::fs::File::open(path)?;
-            let config_data: ConfigData = serde_json::from_reader(fp)?;
-            let paging_path = Self::paging_path(custom_path.clone());
-            let paging_positions: PagingPositions = std::fs::File::open(paging_path)
-                .map_err(|e| eyre::eyre!("{e:?}"))
-                .and_then(|e| serde_json::from_reader(e).map_err(|e| eyre::eyre!("{e:?}")))
-                .unwrap_or_default();
+            let input = crypto::read_file(&path, vault.as_ref())?;
+            let config_data: ConfigData = serde_json::from_slice(&input)?;
+            let paging_positions = read_paging_positions(custom_path.clone(), vault.as_ref());
 
             let access_token =
                 egg_mode::KeyPair::new(config_data.key.clone(), config_data.secret.clone());
@@ -158,11 +257,54 @@ impl Config {
         };
 
         Ok(Config {
+            api: api_for(&token, &config_data),
             token,
             config_data,
             paging_positions: Arc::new(Mutex::new(paging_positions)),
             is_sync: false,
             custom_path,
+            vault,
+            fixtures: None,
+        })
+    }
+
+    /// A config that crawls the recorded responses in `fixtures` instead of
+    /// Twitter. The account comes from the `account.json` in `fixtures`.
+    /// Nothing is written to the settings file
+    pub fn for_fixtures(
+        custom_path: Option<PathBuf>,
+        vault: Option<Vault>,
+        fixtures: &Path,
+    ) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Account {
+            username: String,
+            user_id: u64,
+            #[serde(default)]
+            crawl_options: CrawlOptions,
+        }
+        let api = FixtureApi::new(fixtures)?;
+        let account_path = fixtures.join(FIXTURES_ACCOUNT_FILE);
+        let input = std::fs::read(&account_path)
+            .map_err(|e| eyre::eyre!("Could not read {}: {e}", account_path.display()))?;
+        let account: Account = serde_json::from_slice(&input)?;
+        let paging_positions = read_paging_positions(custom_path.clone(), vault.as_ref());
+        Ok(Config {
+            api: Arc::new(api),
+            token: egg_mode::Token::Bearer(String::new()),
+            config_data: ConfigData {
+                username: account.username,
+                user_id: account.user_id,
+                key: String::new(),
+                secret: String::new(),
+                crawl_options: account.crawl_options,
+                api_version: ApiVersion::default(),
+            },
+            paging_positions: Arc::new(Mutex::new(paging_positions)),
+            is_sync: false,
+            custom_path,
+            vault,
+            fixtures: Some(fixtures.to_path_buf()),
         })
     }
 
@@ -172,17 +314,17 @@ impl Config {
             .map(|_| ())?)
     }
 
-    pub async fn load(custom_path: Option<PathBuf>) -> Result<Self> {
-        let a1 = Config::load_inner(custom_path.clone()).await;
+    pub async fn load(custom_path: Option<PathBuf>, vault: Option<Vault>) -> Result<Self> {
+        let a1 = Config::load_inner(custom_path.clone(), vault.clone()).await;
         if let Ok(conf) = a1 {
             return Ok(conf);
         }
 
-        Config::load_inner(custom_path).await
+        Config::load_inner(custom_path, vault).await
     }
 
-    async fn load_inner(custom_path: Option<PathBuf>) -> Result<Self> {
-        if let Ok(config) = Self::open(custom_path.clone()) {
+    async fn load_inner(custom_path: Option<PathBuf>, vault: Option<Vault>) -> Result<Self> {
+        if let Ok(config) = Self::open(custom_path.clone(), vault.clone()) {
             if let Err(err) = config.verify().await {
                 println!("We've hit an error using your old tokens: {:?}", err);
                 println!("We'll have to reauthenticate before continuing.");
@@ -196,7 +338,7 @@ impl Config {
             Ok(config)
         } else {
             println!("Request Token");
-            let request_data = RequestData::request(custom_path.clone()).await?;
+            let request_data = RequestData::request(custom_path.clone(), vault.clone()).await?;
 
             println!(
                 "Go to the following URL, sign in, and paste the PIN here and hit enter / return"
@@ -222,6 +364,9 @@ impl Config {
                     ("Lists", &mut options.lists),
                     ("Media", &mut options.media),
                     ("Likes", &mut options.likes),
+                    ("Bookmarks", &mut options.bookmarks),
+                    ("Direct Messages", &mut options.direct_messages),
+                    ("Threads", &mut options.threads),
                 ];
                 for (idx, (name, _)) in items.iter().enumerate() {
                     println!("[{}]: {name}", idx + 1);
@@ -251,7 +396,9 @@ impl Config {
                 break;
             }
 
-            config.config_data.write(custom_path.clone())?;
+            config
+                .config_data
+                .write(custom_path.clone(), vault.as_ref())?;
 
             Ok(config)
         }
@@ -264,10 +411,11 @@ pub struct RequestData {
     pub authorize_url: String,
     user_pin: String,
     custom_path: Option<PathBuf>,
+    vault: Option<Vault>,
 }
 
 impl RequestData {
-    pub async fn request(custom_path: Option<PathBuf>) -> Result<Self> {
+    pub async fn request(custom_path: Option<PathBuf>, vault: Option<Vault>) -> Result<Self> {
         let con_token = Config::keypair();
         let request_token = egg_mode::auth::request_token(&con_token, "oob").await?;
         let authorize_url = egg_mode::auth::authorize_url(&request_token);
@@ -277,6 +425,7 @@ impl RequestData {
             authorize_url,
             user_pin: String::new(),
             custom_path,
+            vault,
         })
     }
 
@@ -295,18 +444,22 @@ impl RequestData {
                 key: access_token.key.to_string(),
                 secret: access_token.secret.to_string(),
                 crawl_options: Default::default(),
+                api_version: Default::default(),
             },
             _ => bail!("Invalid Token Type {token:?}"),
         };
 
-        config_data.write(self.custom_path.clone())?;
+        config_data.write(self.custom_path.clone(), self.vault.as_ref())?;
 
         Ok(Config {
+            api: api_for(&token, &config_data),
             token,
             config_data,
             paging_positions: Default::default(),
             is_sync: false,
             custom_path: self.custom_path.clone(),
+            vault: self.vault.clone(),
+            fixtures: None,
         })
     }
 }
@@ -319,6 +472,8 @@ pub struct ConfigData {
     secret: String,
     #[serde(default)]
     crawl_options: CrawlOptions,
+    #[serde(default)]
+    api_version: ApiVersion,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -342,6 +497,29 @@ pub struct CrawlOptions {
     /// Download the liked tweets and profiles for a user
     #[serde(default)]
     pub likes: bool,
+    /// Download the bookmarks of the authenticated user. Only the
+    /// Twitter API v2 has them
+    #[serde(default)]
+    pub bookmarks: bool,
+    /// Download the direct messages of the authenticated user
+    /// (only the last 30 days) and their media
+    #[serde(default)]
+    pub direct_messages: bool,
+    /// Reconstruct the threads of the tweets and mentions that are replies
+    /// or got replies, see `crawler::fetch_threads`
+    #[serde(default)]
+    pub threads: bool,
+    /// How many levels of replies below a tweet are collected for its thread
+    #[serde(default = "default_thread_depth")]
+    pub thread_depth: usize,
+    /// When syncing, look up all known tweets to find the ones
+    /// that were deleted on Twitter
+    #[serde(default)]
+    pub check_deleted: bool,
+}
+
+fn default_thread_depth() -> usize {
+    2
 }
 
 impl CrawlOptions {
@@ -356,6 +534,11 @@ impl CrawlOptions {
             lists: false,
             media: false,
             likes: false,
+            bookmarks: false,
+            direct_messages: false,
+            threads: false,
+            thread_depth: default_thread_depth(),
+            check_deleted: false,
         }
     }
 
@@ -378,19 +561,45 @@ impl Default for CrawlOptions {
             lists: false,
             media: true,
             likes: true,
+            bookmarks: false,
+            direct_messages: false,
+            threads: false,
+            thread_depth: default_thread_depth(),
+            check_deleted: false,
         }
     }
 }
 
 impl ConfigData {
-    fn write(&self, custom_path: Option<PathBuf>) -> Result<()> {
+    fn write(&self, custom_path: Option<PathBuf>, vault: Option<&Vault>) -> Result<()> {
         let path = Config::config_path(custom_path);
-        let f = std::fs::File::create(&path)?;
-        serde_json::to_writer(f, &self)?;
+        let output = crypto::encrypt_if_needed(serde_json::to_vec(&self)?, vault)?;
+        std::fs::write(path, output)?;
         Ok(())
     }
 }
 
+/// The stored paging positions. Without them, the crawl starts from the beginning
+fn read_paging_positions(custom_path: Option<PathBuf>, vault: Option<&Vault>) -> PagingPositions {
+    let path = Config::paging_path(custom_path);
+    if !path.exists() {
+        return PagingPositions::default();
+    }
+    crypto::read_file(&path, vault)
+        .and_then(|input| Ok(serde_json::from_slice(&input)?))
+        .unwrap_or_else(|e| {
+            warn!("Could not read {}: {e:?}", path.display());
+            PagingPositions::default()
+        })
+}
+
+fn api_for(token: &egg_mode::Token, config_data: &ConfigData) -> Arc<dyn TwitterApi> {
+    match config_data.api_version {
+        ApiVersion::V1 => Arc::new(EggModeApi::new(token.clone())),
+        ApiVersion::V2 => Arc::new(TwitterV2Api::new(token.clone(), config_data.user_id)),
+    }
+}
+
 fn data_directory() -> PathBuf {
     use directories_next::ProjectDirs;
     if let Some(proj_dirs) = ProjectDirs::from("com", "StyleMac", "TwitVault") {
@@ -1,18 +1,20 @@
-use crate::storage::{List, Storage};
-use crate::types::Message;
-use egg_mode::{
-    cursor,
-    list::{self, ListID},
-    tweet::{self, Tweet},
-    user::{self, TwitterUser},
-    RateLimit,
+use crate::api::{Cursor, LookedUp, RateLimited, Relation, Timeline};
+use crate::helpers::{
+    append_unknown_ids, insert_placeholder_profiles, is_placeholder_list, is_placeholder_profile,
 };
+use crate::storage::{Conversation, Data, List, ListKind, MessageId, Storage, Thread, TweetId};
+use crate::types::Message;
+use chrono::Utc;
+use egg_mode::{list, tweet::Tweet, user::TwitterUser, RateLimit};
 use reqwest::Client;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hasher;
 use std::io::Write;
 use std::time::SystemTime;
-use std::{collections::HashSet, path::PathBuf, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
 use tokio::sync::{
     mpsc::{channel, Sender},
     Mutex,
@@ -20,10 +22,15 @@ use tokio::sync::{
 use tokio::task::JoinHandle;
 use tracing::{info, trace, warn};
 
-use eyre::{bail, Result};
+use eyre::{bail, eyre, Result};
 
 use crate::config::Config;
 
+/// The maximum number of tweets per lookup request
+const LOOKUP_BATCH_SIZE: usize = 100;
+/// The maximum number of profiles per lookup request
+const PROFILE_BATCH_SIZE: usize = 100;
+
 /// Internal messaging between the different threads
 #[derive(Debug)]
 pub enum DownloadInstruction {
@@ -33,19 +40,29 @@ pub enum DownloadInstruction {
     Movie(mime::Mime, String),
     /// Download the media of a profile
     ProfileMedia(String),
+    /// Download the media of a direct message. Twitter only serves it
+    /// to requests that are signed with the token
+    MessageMedia(egg_mode::Token, String),
     /// Tells the thread to close as all the crawling finished
     Done,
 }
 
+/// Where the download instructions get the media from
+#[derive(Clone, Debug)]
+pub enum MediaSource {
+    Twitter,
+    /// A folder with recorded media, named after the last part of their url
+    Fixtures(PathBuf),
+}
+
 pub async fn crawl_new_storage(
     config: Config,
     message_sender: Sender<Message>,
     user_id: u64,
 ) -> Result<()> {
     let storage_path = config.actual_storage_path();
-    let Ok(user_container) = egg_mode::user::lookup([user_id], &config.token).await else { bail!("Could not find user") };
-    let Some(user) = user_container.response.first() else { bail!("Empty User Response") };
-    let mut storage = Storage::new(user.clone(), storage_path)?;
+    let Ok(user) = config.api().user(user_id).await else { bail!("Could not find user") };
+    let mut storage = Storage::new(user.clone(), storage_path, config.vault().cloned())?;
     storage.with_data(|d| {
         d.profiles.insert(user.id, user.clone());
     });
@@ -78,6 +95,7 @@ pub async fn crawl_into_storage(
 
 pub fn create_instruction_handler(
     should_download_media: bool,
+    source: MediaSource,
     shared_storage: Arc<Mutex<Storage>>,
 ) -> (JoinHandle<()>, Sender<DownloadInstruction>) {
     let (instruction_sender, mut instruction_receiver) = channel(4096);
@@ -90,7 +108,9 @@ pub fn create_instruction_handler(
             if !should_download_media {
                 continue;
             }
-            if let Err(e) = handle_instruction(&client, instruction, shared_storage.clone()).await {
+            let handled =
+                handle_instruction(&client, &source, instruction, shared_storage.clone()).await;
+            if let Err(e) = handled {
                 warn!("Download Error {e:?}");
             }
         }
@@ -98,7 +118,7 @@ pub fn create_instruction_handler(
     (instruction_task, instruction_sender)
 }
 
-async fn msg(msg: impl AsRef<str>, sender: &Sender<Message>) {
+pub async fn msg(msg: impl AsRef<str>, sender: &Sender<Message>) {
     if let Err(e) = sender
         .send(Message::Loading(msg.as_ref().to_string()))
         .await
@@ -121,8 +141,11 @@ async fn fetch(
         }
     }
 
-    let (instruction_task, instruction_sender) =
-        create_instruction_handler(config.crawl_options().media, shared_storage.clone());
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        config.crawl_options().media,
+        config.media_source(),
+        shared_storage.clone(),
+    );
 
     fetch_single_profile(
         user_id,
@@ -133,15 +156,17 @@ async fn fetch(
     .await?;
 
     if config.crawl_options().tweets {
-        fetch_user_tweets(
-            user_id,
+        let result = fetch_timeline(
+            Timeline::User(user_id),
+            "user_tweets",
             shared_storage.clone(),
             config,
             instruction_sender.clone(),
             sender.clone(),
         )
-        .await?;
+        .await;
         save_data(&shared_storage).await;
+        result?;
     }
 
     // If we're not crawling for the authenticated user
@@ -150,20 +175,85 @@ async fn fetch(
         if config.user_id() != user_id {
             info!("Can't crawl mentions for custom-user");
         } else {
-            fetch_user_mentions(
+            let result = fetch_timeline(
+                Timeline::Mentions,
+                "user_mentions",
                 shared_storage.clone(),
                 config,
                 instruction_sender.clone(),
                 sender.clone(),
             )
-            .await?;
+            .await;
             save_data(&shared_storage).await;
+            result?;
         }
     }
 
     if config.crawl_options().likes {
-        fetch_user_likes(
-            user_id,
+        let result = fetch_timeline(
+            Timeline::Likes(user_id),
+            "user_likes",
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        save_data(&shared_storage).await;
+        result?;
+
+        fill_imported_likes(
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await?;
+        save_data(&shared_storage).await;
+    }
+
+    // Bookmarks are private, only the authenticated user can crawl them
+    if config.crawl_options().bookmarks {
+        if config.user_id() != user_id {
+            info!("Can't crawl bookmarks for custom-user");
+        } else {
+            let result = fetch_timeline(
+                Timeline::Bookmarks,
+                "user_bookmarks",
+                shared_storage.clone(),
+                config,
+                instruction_sender.clone(),
+                sender.clone(),
+            )
+            .await;
+            save_data(&shared_storage).await;
+            // E.g. the API v1.1 has no bookmarks. The rest can still be crawled
+            if let Err(e) = result {
+                warn!("Could not download the bookmarks: {e:?}");
+                msg(format!("Could not download the bookmarks: {e}"), &sender).await;
+            }
+        }
+    }
+
+    // Only the authenticated user can read their direct messages
+    if config.crawl_options().direct_messages {
+        if config.user_id() != user_id {
+            info!("Can't crawl direct messages for custom-user");
+        } else {
+            fetch_direct_messages(
+                shared_storage.clone(),
+                config,
+                instruction_sender.clone(),
+                sender.clone(),
+            )
+            .await?;
+            save_data(&shared_storage).await;
+        }
+    }
+
+    // Threads start at the tweets and mentions, so they have to be crawled first
+    if config.crawl_options().threads {
+        fetch_threads(
             shared_storage.clone(),
             config,
             instruction_sender.clone(),
@@ -173,6 +263,12 @@ async fn fetch(
         save_data(&shared_storage).await;
     }
 
+    // Only known tweets can be checked, so this is part of a sync
+    if config.is_sync && config.crawl_options().check_deleted {
+        check_deleted_tweets(shared_storage.clone(), config, sender.clone()).await?;
+        save_data(&shared_storage).await;
+    }
+
     if config.crawl_options().followers {
         fetch_user_followers(
             user_id,
@@ -217,42 +313,70 @@ async fn fetch(
     instruction_sender.send(DownloadInstruction::Done).await?;
     instruction_task.await?;
 
-    let storage = shared_storage.lock_owned().await.clone();
+    let mut storage = shared_storage.lock_owned().await.clone();
+    storage.data_mut().rebuild_index();
     sender.send(Message::Finished(storage)).await?;
 
     Ok(())
 }
 
-async fn fetch_user_tweets(
-    id: u64,
+/// Crawl a timeline into its collection, newest first. `key` is where the
+/// paging position is kept. A sync stops at the first known tweet
+async fn fetch_timeline(
+    timeline: Timeline,
+    key: &str,
     shared_storage: Arc<Mutex<Storage>>,
     config: &Config,
     sender: Sender<DownloadInstruction>,
     message_sender: Sender<Message>,
 ) -> Result<()> {
-    let label = "User Tweets";
+    let label = match timeline {
+        Timeline::User(_) => "User Tweets",
+        Timeline::Mentions => "User Mentions",
+        Timeline::Likes(_) => "User Likes",
+        Timeline::Bookmarks => "User Bookmarks",
+    };
     msg(label, &message_sender).await;
-    let mut timeline = tweet::user_timeline(id, true, true, &config.token).with_page_size(200);
-
-    let mut first_page = config.paging_position("user_tweets");
+    let mut cursor = config.paging_position(key).map(Cursor::older_than);
 
-    let first_id = shared_storage.lock().await.data().tweets.first().cloned();
+    // Not only the newest one, imported likes can be in front of the crawled ones
+    let known_ids: HashSet<TweetId> = {
+        let mut storage = shared_storage.lock().await;
+        timeline_tweets(storage.data_mut(), timeline)
+            .iter()
+            .map(|e| e.id)
+            .collect()
+    };
     let is_sync = config.is_sync;
 
     let mut collected = Vec::new();
 
-    'outer: loop {
-        tracing::info!("Downloading Tweets before {:?}", timeline.min_id);
-        let (next_timeline, mut feed) = timeline.older(first_page).await?;
-        first_page = None;
-        if feed.response.is_empty() {
-            break;
+    let result = loop {
+        tracing::info!("Downloading {label} before {:?}", cursor);
+        let mut page = match config.api().timeline(timeline, cursor.clone()).await {
+            Ok(page) => page,
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(limited) => {
+                    msg(format!("Rate limit for {label} reached"), &message_sender).await;
+                    sleep_until(limited.reset).await;
+                    continue;
+                }
+                // The pages so far are kept, the paging position continues after them
+                None => break Err(e),
+            },
+        };
+        if page.items.is_empty() {
+            break Ok(());
         }
-        for tweet in feed.response.iter() {
-            // In this case, we know the tweet and we stop loading further
-            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
-                break 'outer;
-            }
+        // In this case, we know the tweet and we stop loading further
+        let known = page
+            .items
+            .iter()
+            .position(|tweet| is_sync && known_ids.contains(&tweet.id));
+        if let Some(known) = known {
+            page.items.truncate(known);
+        }
+        for tweet in page.items.iter() {
             inspect_tweet(
                 tweet,
                 shared_storage.clone(),
@@ -262,121 +386,419 @@ async fn fetch_user_tweets(
             )
             .await?;
         }
-        collected.append(&mut feed.response);
+        collected.append(&mut page.items);
+        if known.is_some() {
+            break Ok(());
+        }
 
-        handle_rate_limit(&feed.rate_limit_status, "User Feed", message_sender.clone()).await;
-        timeline = next_timeline;
-        config.set_paging_position("user_tweets", timeline.min_id);
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, label, message_sender.clone()).await;
+        }
+        cursor = page.next;
+        config.set_paging_position(key, cursor.as_ref().and_then(Cursor::position));
 
         msg(format!("{label}: {}", collected.len()), &message_sender).await;
-    }
+        if cursor.is_none() {
+            break Ok(());
+        }
+    };
 
     let mut s = shared_storage.lock().await;
+    s.data_mut().mark_seen(collected.iter().map(|t| t.id));
+    let tweets = timeline_tweets(s.data_mut(), timeline);
     if is_sync {
-        s.data_mut().tweets.splice(0..0, collected);
+        tweets.splice(0..0, collected);
     } else {
-        s.data_mut().tweets.append(&mut collected);
+        tweets.append(&mut collected);
+    }
+
+    if result.is_ok() {
+        config.set_paging_position(key, None);
     }
 
-    config.set_paging_position("user_tweets", None);
+    result
+}
 
-    Ok(())
+/// The collection the tweets of a timeline are stored in
+fn timeline_tweets(data: &mut Data, timeline: Timeline) -> &mut Vec<Tweet> {
+    match timeline {
+        Timeline::User(_) => &mut data.tweets,
+        Timeline::Mentions => &mut data.mentions,
+        Timeline::Likes(_) => &mut data.likes,
+        Timeline::Bookmarks => &mut data.bookmarks,
+    }
 }
 
-async fn fetch_user_mentions(
+/// Messages are added to the conversations that are already known,
+/// e.g. from a Twitter archive. A sync stops at the first known message
+async fn fetch_direct_messages(
     shared_storage: Arc<Mutex<Storage>>,
     config: &Config,
     sender: Sender<DownloadInstruction>,
     message_sender: Sender<Message>,
 ) -> Result<()> {
-    let label = "User Mentions";
+    let label = "Direct Messages";
     msg(label, &message_sender).await;
-    let mut timeline = tweet::mentions_timeline(&config.token).with_page_size(200);
-
-    let mut first_page = config.paging_position("user_mentions");
+    let mut cursor = config.paging_position("direct_messages").map(Cursor::next);
 
-    let first_id = shared_storage.lock().await.data().mentions.first().cloned();
+    let known_ids: HashSet<MessageId> = shared_storage
+        .lock()
+        .await
+        .data()
+        .conversations
+        .iter()
+        .flat_map(|c| c.messages.iter().map(|m| m.id))
+        .collect();
     let is_sync = config.is_sync;
 
     let mut collected = Vec::new();
 
-    'outer: loop {
-        tracing::info!("Downloading Mentions before {:?}", timeline.min_id);
-        let (next_timeline, mut feed) = timeline.older(first_page).await?;
-        first_page = None;
-        if feed.response.is_empty() {
+    loop {
+        tracing::info!("Downloading Direct Messages at {:?}", cursor);
+        let mut page = config.api().direct_messages(cursor.clone()).await?;
+        if page.items.is_empty() {
             break;
         }
-        for tweet in feed.response.iter() {
-            // In this case, we know the tweet and we stop loading further
-            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
-                break 'outer;
+        let known = page
+            .items
+            .iter()
+            .position(|event| is_sync && known_ids.contains(&event.message.id));
+        if let Some(known) = known {
+            page.items.truncate(known);
+        }
+        let mut participants = Vec::new();
+        for event in page.items.iter() {
+            append_unknown_ids(&mut participants, &event.participants);
+            for url in event.message.media.iter() {
+                sender
+                    .send(DownloadInstruction::MessageMedia(
+                        config.token.clone(),
+                        url.clone(),
+                    ))
+                    .await?;
             }
-            inspect_tweet(
-                tweet,
-                shared_storage.clone(),
-                config,
-                &sender,
-                &message_sender,
-            )
-            .await?;
         }
-        collected.append(&mut feed.response);
+        // Known participants (e.g. the owner) aren't looked up again
+        let participants: Vec<u64> = {
+            let storage = shared_storage.lock().await;
+            let profiles = &storage.data().profiles;
+            participants
+                .into_iter()
+                .filter(|id| profiles.get(id).map_or(true, is_placeholder_profile))
+                .collect()
+        };
+        for ids in participants.chunks(PROFILE_BATCH_SIZE) {
+            fetch_multiple_profiles_data(ids, shared_storage.clone(), config, sender.clone())
+                .await?;
+        }
+        collected.append(&mut page.items);
+        if known.is_some() {
+            break;
+        }
 
-        handle_rate_limit(
-            &feed.rate_limit_status,
-            "User Mentions",
-            message_sender.clone(),
-        )
-        .await;
-        timeline = next_timeline;
-        config.set_paging_position("user_mentions", timeline.min_id);
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, "Direct Messages", message_sender.clone()).await;
+        }
+        cursor = page.next;
+        config.set_paging_position(
+            "direct_messages",
+            cursor.as_ref().and_then(Cursor::position),
+        );
 
         msg(format!("{label}: {}", collected.len()), &message_sender).await;
+        if cursor.is_none() {
+            break;
+        }
     }
 
     let mut s = shared_storage.lock().await;
-    if is_sync {
-        s.data_mut().mentions.splice(0..0, collected);
-    } else {
-        s.data_mut().mentions.append(&mut collected);
+    let data = s.data_mut();
+    for event in collected {
+        let position = match data
+            .conversations
+            .iter()
+            .position(|c| c.id == event.conversation_id)
+        {
+            Some(n) => n,
+            None => {
+                data.conversations.push(Conversation {
+                    id: event.conversation_id.clone(),
+                    name: None,
+                    participants: Vec::new(),
+                    messages: Vec::new(),
+                });
+                data.conversations.len() - 1
+            }
+        };
+        // Participants that couldn't be loaded (e.g. suspended accounts)
+        insert_placeholder_profiles(&mut data.profiles, &event.participants);
+        let conversation = &mut data.conversations[position];
+        append_unknown_ids(&mut conversation.participants, &event.participants);
+        if !conversation
+            .messages
+            .iter()
+            .any(|m| m.id == event.message.id)
+        {
+            conversation.messages.push(event.message);
+        }
+    }
+    for conversation in data.conversations.iter_mut() {
+        conversation.messages.sort_by(|a, b| b.id.cmp(&a.id));
     }
 
-    config.set_paging_position("user_mentions", None);
+    config.set_paging_position("direct_messages", None);
 
     Ok(())
 }
 
-async fn fetch_user_likes(
-    id: u64,
+/// Reconstruct the conversations of the tweets and mentions that are replies
+/// or got replies. The replies are followed up to the first tweet of the
+/// conversation, and `thread_depth` levels of replies are collected below
+/// the tweet. Tweets that are part of a known thread are skipped, so a sync
+/// only crawls the threads of new tweets
+async fn fetch_threads(
     shared_storage: Arc<Mutex<Storage>>,
     config: &Config,
     sender: Sender<DownloadInstruction>,
     message_sender: Sender<Message>,
 ) -> Result<()> {
-    let label = "User Likes";
+    let label = "Threads";
     msg(label, &message_sender).await;
-    let mut timeline = tweet::liked_by(id, &config.token).with_page_size(200);
 
-    let mut first_page = config.paging_position("user_likes");
+    let candidates: Vec<Tweet> = {
+        let mut storage = shared_storage.lock().await;
+        // The other collections were crawled into the data, threads only
+        // look tweets up through the index
+        storage.data_mut().rebuild_index();
+        let data = storage.data();
+        data.tweets
+            .iter()
+            .chain(data.mentions.iter())
+            .filter(|t| t.in_reply_to_status_id.is_some() || data.responses.contains_key(&t.id))
+            .filter(|t| data.thread(t.id).is_none())
+            .cloned()
+            .collect()
+    };
 
-    let first_id = shared_storage.lock().await.data().likes.first().cloned();
-    let is_sync = config.is_sync;
+    let mut crawled = 0;
+    for (checked, tweet) in candidates.into_iter().enumerate() {
+        // The thread of an earlier tweet might contain this one
+        let known = shared_storage
+            .lock()
+            .await
+            .data()
+            .thread(tweet.id)
+            .is_some();
+        if known {
+            continue;
+        }
+        let thread = fetch_thread(tweet, &shared_storage, config, &sender, &message_sender).await;
+        shared_storage.lock().await.data_mut().insert_thread(thread);
+        crawled += 1;
+        msg(
+            format!("{label}: {crawled} crawled, {} checked", checked + 1),
+            &message_sender,
+        )
+        .await;
+    }
 
-    let mut collected = Vec::new();
+    Ok(())
+}
 
-    'outer: loop {
-        tracing::info!("Downloading Likes before {:?}", timeline.min_id);
-        let (next_timeline, mut feed) = timeline.older(first_page).await?;
-        first_page = None;
-        if feed.response.is_empty() {
+/// The thread of a single tweet, see `fetch_threads`. Rate limits are waited
+/// out, other errors end the crawl of the thread but keep what was found so far
+async fn fetch_thread(
+    tweet: Tweet,
+    storage: &Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &Sender<DownloadInstruction>,
+    message_sender: &Sender<Message>,
+) -> Thread {
+    let mut thread = Thread::new(tweet.clone());
+
+    // Upwards, one tweet at a time
+    let mut parent_id = tweet.in_reply_to_status_id;
+    while let Some(id) = parent_id {
+        let known = storage.lock().await.data().any_tweet(id).cloned();
+        let parent = match known {
+            Some(parent) => parent,
+            None => {
+                let page = match config.api().lookup(&[id]).await {
+                    Ok(page) => page,
+                    Err(e) => match e.downcast_ref::<RateLimited>() {
+                        Some(limited) => {
+                            msg("Rate limit for Thread Lookup reached", message_sender).await;
+                            sleep_until(limited.reset).await;
+                            continue;
+                        }
+                        None => {
+                            warn!("Could not look up tweet {id}: {e:?}");
+                            break;
+                        }
+                    },
+                };
+                if let Some(limit) = &page.rate_limit {
+                    handle_rate_limit(limit, "Thread Lookup", message_sender.clone()).await;
+                }
+                // Deleted or protected, the thread starts below it
+                let looked_up = page.items.into_iter().next();
+                let Some(parent) = looked_up.and_then(|(_, e)| e.found()) else {
+                    break
+                };
+                let inspected = inspect_inner_tweet(&parent, config, storage, sender.clone()).await;
+                if let Err(e) = inspected {
+                    warn!("Could not inspect tweet {}: {e:?}", parent.id);
+                }
+                parent
+            }
+        };
+        parent_id = parent.in_reply_to_status_id;
+        if !thread.insert(parent) {
             break;
         }
-        for tweet in feed.response.iter() {
-            // In this case, we know the tweet and we stop loading further
-            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
-                break 'outer;
+    }
+
+    // Downwards, one level of replies at a time
+    let mut level = vec![tweet];
+    for _ in 0..config.crawl_options().thread_depth {
+        let mut next_level = Vec::new();
+        for parent in level.iter() {
+            let mut replies = storage
+                .lock()
+                .await
+                .data()
+                .responses
+                .get(&parent.id)
+                .cloned()
+                .unwrap_or_default();
+            replies.append(&mut search_replies(parent, config, message_sender).await);
+            for reply in replies {
+                if thread.contains(reply.id) {
+                    continue;
+                }
+                if let Err(e) = inspect_inner_tweet(&reply, config, storage, sender.clone()).await {
+                    warn!("Could not inspect tweet {}: {e:?}", reply.id);
+                }
+                thread.insert(reply.clone());
+                next_level.push(reply);
             }
+        }
+        if next_level.is_empty() {
+            break;
+        }
+        level = next_level;
+    }
+
+    thread
+}
+
+/// The replies to a tweet, page by page. Twitter's search only finds the replies of the last 7 days.
+/// If a page fails, the replies of the earlier pages are kept
+async fn search_replies(
+    tweet: &Tweet,
+    config: &Config,
+    message_sender: &Sender<Message>,
+) -> Vec<Tweet> {
+    let Some(author) = &tweet.user else {
+        return Vec::new()
+    };
+    let query = format!("to:{}", author.screen_name);
+    let mut replies = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = match config.api().search(&query, tweet.id, cursor.clone()).await {
+            Ok(page) => page,
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(limited) => {
+                    msg("Rate limit for Thread Replies reached", message_sender).await;
+                    sleep_until(limited.reset).await;
+                    continue;
+                }
+                None => {
+                    warn!("Could not search the replies to tweet {}: {e:?}", tweet.id);
+                    break;
+                }
+            },
+        };
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, "Thread Replies", message_sender.clone()).await;
+        }
+        if page.items.is_empty() {
+            break;
+        }
+        replies.extend(
+            page.items
+                .into_iter()
+                .filter(|reply| reply.in_reply_to_status_id == Some(tweet.id)),
+        );
+        match page.next {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    replies
+}
+
+/// Likes from a Twitter archive only have the id and the text, their
+/// author is a placeholder. They are replaced with the looked up tweets
+async fn fill_imported_likes(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: Sender<DownloadInstruction>,
+    message_sender: Sender<Message>,
+) -> Result<()> {
+    let ids: Vec<TweetId> = {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        data.likes
+            .iter()
+            .filter(|tweet| tweet.user.as_deref().map_or(true, is_placeholder_profile))
+            .filter(|tweet| {
+                let meta = data.tweet_meta.get(&tweet.id);
+                meta.and_then(|meta| meta.deleted_at).is_none()
+            })
+            .map(|tweet| tweet.id)
+            .collect()
+    };
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let label = "Imported Likes";
+    msg(label, &message_sender).await;
+
+    let mut filled = 0;
+    for batch in ids.chunks(LOOKUP_BATCH_SIZE) {
+        let looked_up = loop {
+            match config.api().lookup(batch).await {
+                Ok(page) => break Ok(page),
+                Err(e) => match e.downcast_ref::<RateLimited>() {
+                    Some(limited) => {
+                        msg(format!("Rate limit for {label} reached"), &message_sender).await;
+                        sleep_until(limited.reset).await;
+                    }
+                    None => break Err(e),
+                },
+            }
+        };
+        // The likes that were filled so far are kept
+        let page = match looked_up {
+            Ok(page) => page,
+            Err(e) => {
+                warn!("Could not look up the imported likes: {e:?}");
+                break;
+            }
+        };
+        let now = Utc::now();
+        let mut found = Vec::new();
+        let mut deleted = Vec::new();
+        for (id, looked_up) in page.items {
+            match looked_up {
+                LookedUp::Found(tweet) => found.push(*tweet),
+                LookedUp::Deleted => deleted.push(id),
+                LookedUp::Unavailable => {}
+            }
+        }
+        for tweet in found.iter() {
             inspect_tweet(
                 tweet,
                 shared_storage.clone(),
@@ -386,29 +808,122 @@ async fn fetch_user_likes(
             )
             .await?;
         }
-        collected.append(&mut feed.response);
+        {
+            let mut storage = shared_storage.lock().await;
+            let data = storage.data_mut();
+            for id in deleted {
+                data.mark_deleted(id, now, false);
+            }
+            data.mark_seen(found.iter().map(|tweet| tweet.id));
+            let positions: HashMap<TweetId, usize> = data
+                .likes
+                .iter()
+                .enumerate()
+                .map(|(position, like)| (like.id, position))
+                .collect();
+            for tweet in found {
+                if let Some(position) = positions.get(&tweet.id) {
+                    data.likes[*position] = tweet;
+                    filled += 1;
+                }
+            }
+        }
 
-        handle_rate_limit(
-            &feed.rate_limit_status,
-            "User Likes",
-            message_sender.clone(),
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, label, message_sender.clone()).await;
+        }
+        msg(
+            format!("{label}: {filled} / {}", ids.len()),
+            &message_sender,
         )
         .await;
-        timeline = next_timeline;
-        config.set_paging_position("user_likes", timeline.min_id);
-
-        msg(format!("{label}: {}", collected.len()), &message_sender).await;
     }
+    Ok(())
+}
 
-    let mut s = shared_storage.lock().await;
-    if is_sync {
-        s.data_mut().likes.splice(0..0, collected);
-    } else {
-        s.data_mut().likes.append(&mut collected);
-    }
+/// Look up the known tweets in batches. The ones that don't exist anymore
+/// are marked as deleted. Tweets of accounts that became protected or were
+/// suspended are only marked as unavailable, they are checked again next time
+async fn check_deleted_tweets(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    message_sender: Sender<Message>,
+) -> Result<()> {
+    let label = "Checking for deleted Tweets";
+    msg(label, &message_sender).await;
 
-    config.set_paging_position("user_mentions", None);
+    let ids: Vec<TweetId> = {
+        let mut storage = shared_storage.lock().await;
+        // The UI might have deleted tweets since the storage was opened
+        storage.apply_deletions()?;
+        let data = storage.data();
+        let mut ids: Vec<TweetId> = data
+            .tweets
+            .iter()
+            .chain(data.mentions.iter())
+            .chain(data.likes.iter())
+            .chain(data.bookmarks.iter())
+            .map(|tweet| tweet.id)
+            .filter(|id| {
+                let meta = data.tweet_meta.get(id);
+                meta.and_then(|meta| meta.deleted_at).is_none()
+            })
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    };
+
+    let mut checked = 0;
+    let mut deleted = 0;
+    let mut unavailable = 0;
+    for batch in ids.chunks(LOOKUP_BATCH_SIZE) {
+        let page = loop {
+            match config.api().lookup(batch).await {
+                Ok(page) => break page,
+                Err(e) => match e.downcast_ref::<RateLimited>() {
+                    Some(limited) => {
+                        msg("Rate limit for Tweet Lookup reached", &message_sender).await;
+                        sleep_until(limited.reset).await;
+                    }
+                    None => return Err(e),
+                },
+            }
+        };
+        let now = Utc::now();
+        {
+            let mut storage = shared_storage.lock().await;
+            let data = storage.data_mut();
+            for (id, looked_up) in page.items.iter() {
+                match looked_up {
+                    LookedUp::Found(_) => data.mark_seen([*id]),
+                    LookedUp::Deleted => {
+                        data.mark_deleted(*id, now, false);
+                        deleted += 1;
+                    }
+                    LookedUp::Unavailable => {
+                        data.mark_unavailable(*id, now);
+                        unavailable += 1;
+                    }
+                }
+            }
+        }
+        checked += batch.len();
 
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, "Tweet Lookup", message_sender.clone()).await;
+        }
+        msg(
+            format!(
+                "{label}: {checked} / {}, {deleted} deleted, {unavailable} unavailable",
+                ids.len()
+            ),
+            &message_sender,
+        )
+        .await;
+    }
+
+    info!("{deleted} of {checked} tweets were deleted on Twitter, {unavailable} are unavailable");
     Ok(())
 }
 
@@ -421,8 +936,8 @@ async fn fetch_user_followers(
 ) -> Result<()> {
     let followers = { shared_storage.lock().await.data().followers.clone() };
     let ids = fetch_profiles_ids(
-        "Followers",
-        user::followers_ids(id, &config.token).with_page_size(100),
+        Relation::Followers,
+        id,
         shared_storage.clone(),
         config,
         sender,
@@ -443,8 +958,8 @@ async fn fetch_user_follows(
 ) -> Result<()> {
     let follows = { shared_storage.lock().await.data().follows.clone() };
     let ids = fetch_profiles_ids(
-        "Follows",
-        user::friends_ids(id, &config.token).with_page_size(100),
+        Relation::Follows,
+        id,
         shared_storage.clone(),
         config,
         sender,
@@ -459,46 +974,59 @@ async fn fetch_user_follows(
 // Helpers
 
 async fn fetch_profiles_ids(
-    kind: &'static str,
-    mut cursor: cursor::CursorIter<cursor::IDCursor>,
+    relation: Relation,
+    user_id: u64,
     shared_storage: Arc<Mutex<Storage>>,
     config: &Config,
     sender: Sender<DownloadInstruction>,
     mut ids: Vec<u64>,
     message_sender: Sender<Message>,
 ) -> Result<Vec<u64>> {
+    let kind = match relation {
+        Relation::Followers => "Followers",
+        Relation::Follows => "Follows",
+    };
     msg(kind, &message_sender).await;
-    cursor.next_cursor = config.paging_position(kind).map(|e| e as i64).unwrap_or(-1);
+    let mut cursor = config.paging_position(kind).map(Cursor::next);
 
     let is_sync = config.is_sync;
 
     loop {
-        if cursor.next_cursor == 0 {
-            break;
-        }
-        info!("Downloading {kind} before {}", cursor.next_cursor);
-        let called = cursor.call();
-        let resp = match called.await {
+        info!("Downloading {kind} before {:?}", cursor);
+        let page = match config.api().user_ids(relation, user_id, cursor.clone()).await {
             Ok(n) => n,
-            Err(e) => match e {
-                egg_mode::error::Error::RateLimit(limit) => {
-                    msg("Rate limit for {kind} reached", &message_sender).await;
-                    sleep_until(limit).await;
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(limited) => {
+                    msg(format!("Rate limit for {kind} reached"), &message_sender).await;
+                    sleep_until(limited.reset).await;
                     continue;
                 }
-                _ => {
+                None => {
                     warn!("Profile Ids Error {e:?}");
                     continue;
                 }
             },
         };
 
-        let new_ids = resp.response.ids.clone();
-
-        if new_ids.is_empty() {
+        if page.items.is_empty() {
             break;
         }
 
+        let new_ids: Vec<u64> = page.items.iter().map(|user| user.id).collect();
+        let profiles: Vec<TwitterUser> = page
+            .items
+            .into_iter()
+            .filter_map(|user| user.profile)
+            .collect();
+        for profile in profiles.iter() {
+            inspect_profile(profile, sender.clone()).await?;
+        }
+        shared_storage.lock().await.with_data(move |data| {
+            for profile in &profiles {
+                data.profiles.insert(profile.id, profile.clone());
+            }
+        });
+
         let mut unknown_new: Vec<_> = new_ids
             .iter()
             .filter(|s| !ids.contains(s))
@@ -506,8 +1034,11 @@ async fn fetch_profiles_ids(
             .collect();
         let unknown_new_len = unknown_new.len();
 
-        fetch_multiple_profiles_data(&unknown_new, shared_storage.clone(), config, sender.clone())
-            .await?;
+        // Known ids can still have placeholder profiles from an archive import
+        for batch in new_ids.chunks(LOOKUP_BATCH_SIZE) {
+            fetch_multiple_profiles_data(batch, shared_storage.clone(), config, sender.clone())
+                .await?;
+        }
 
         if is_sync {
             ids.splice(0..0, unknown_new);
@@ -522,13 +1053,35 @@ async fn fetch_profiles_ids(
             break;
         }
 
-        handle_rate_limit(&resp.rate_limit_status, kind, message_sender.clone()).await;
-        cursor.next_cursor = resp.response.next_cursor;
-        config.set_paging_position(kind, u64::try_from(cursor.next_cursor).ok());
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, kind, message_sender.clone()).await;
+        }
+        cursor = page.next;
+        config.set_paging_position(kind, cursor.as_ref().and_then(Cursor::position));
+        if cursor.is_none() {
+            break;
+        }
     }
 
     config.set_paging_position(kind, None);
 
+    // A sync stops at the first known page, so the placeholder profiles of
+    // known ids (e.g. from an archive import) are looked up separately
+    if is_sync {
+        let placeholders: Vec<u64> = {
+            let storage = shared_storage.lock().await;
+            let profiles = &storage.data().profiles;
+            ids.iter()
+                .filter(|id| profiles.get(id).map_or(true, is_placeholder_profile))
+                .copied()
+                .collect()
+        };
+        for batch in placeholders.chunks(LOOKUP_BATCH_SIZE) {
+            fetch_multiple_profiles_data(batch, shared_storage.clone(), config, sender.clone())
+                .await?;
+        }
+    }
+
     Ok(ids)
 }
 
@@ -538,33 +1091,39 @@ async fn fetch_multiple_profiles_data(
     config: &Config,
     sender: Sender<DownloadInstruction>,
 ) -> Result<()> {
-    // only get profiles we haven't gotten yet
+    // only get profiles we haven't gotten yet (placeholders from an
+    // archive import count as unknown)
     let known_ids: HashSet<u64> = shared_storage
         .lock()
         .await
         .data()
         .profiles
-        .keys()
-        .copied()
+        .values()
+        .filter(|p| !is_placeholder_profile(p))
+        .map(|p| p.id)
         .collect();
     let filtered: Vec<_> = ids
         .iter()
         .filter(|id| !known_ids.contains(id))
         .copied()
         .collect();
+    if filtered.is_empty() {
+        return Ok(());
+    }
     info!("Downloading {} profiles", filtered.len());
-    let profiles = user::lookup(filtered, &config.token).await?;
+    let profiles = config.api().users(&filtered).await?.items;
     for profile in profiles.iter() {
         inspect_profile(profile, sender.clone()).await?;
     }
     shared_storage.lock().await.with_data(move |data| {
-        for profile in &profiles.response {
+        for profile in &profiles {
             data.profiles.insert(profile.id, profile.clone());
         }
     });
     Ok(())
 }
 
+/// The lists the user created, subscribed to and is a member of
 async fn fetch_lists(
     id: u64,
     shared_storage: Arc<Mutex<Storage>>,
@@ -572,32 +1131,56 @@ async fn fetch_lists(
     sender: Sender<DownloadInstruction>,
     message_sender: Sender<Message>,
 ) -> Result<()> {
-    let label = "Lists";
-    msg(label, &message_sender).await;
-    let mut cursor = list::ownerships(id, &config.token).with_page_size(100);
-    cursor.next_cursor = config
-        .paging_position("lists")
-        .map(|e| e as i64)
-        .unwrap_or(-1);
-    loop {
-        let called = cursor.call();
+    for kind in [ListKind::Created, ListKind::Subscribed, ListKind::Member] {
+        fetch_lists_of_kind(
+            id,
+            kind,
+            shared_storage.clone(),
+            config,
+            sender.clone(),
+            message_sender.clone(),
+        )
+        .await?;
+    }
+    Ok(())
+}
 
-        let resp = match called.await {
+async fn fetch_lists_of_kind(
+    id: u64,
+    kind: ListKind,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: Sender<DownloadInstruction>,
+    message_sender: Sender<Message>,
+) -> Result<()> {
+    let label = format!("Lists ({kind})");
+    msg(&label, &message_sender).await;
+    // Created lists keep the key from before the other kinds were crawled
+    let paging_key = match kind {
+        ListKind::Created => "lists",
+        ListKind::Subscribed => "lists-subscribed",
+        ListKind::Member => "lists-member",
+    };
+    let mut cursor = config.paging_position(paging_key).map(Cursor::next);
+    loop {
+        let page = match config.api().lists(id, kind, cursor.clone()).await {
             Ok(n) => n,
-            Err(e) => match e {
-                egg_mode::error::Error::RateLimit(limit) => {
-                    msg("Rate limit for Lists reached", &message_sender).await;
-                    sleep_until(limit).await;
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(limited) => {
+                    msg(format!("Rate limit for {label} reached"), &message_sender).await;
+                    sleep_until(limited.reset).await;
                     continue;
                 }
-                _ => {
+                // E.g. no access to this kind of lists. The other kinds can still be crawled
+                None => {
                     warn!("Lists Error {e:?}");
-                    continue;
+                    msg(format!("Could not download the {label}: {e}"), &message_sender).await;
+                    return Ok(());
                 }
             },
         };
 
-        let lists = resp.response.lists;
+        let lists = page.items;
 
         if lists.is_empty() {
             break;
@@ -612,6 +1195,7 @@ async fn fetch_lists(
             .await;
             fetch_list_members(
                 list,
+                kind,
                 shared_storage.clone(),
                 config,
                 sender.clone(),
@@ -620,17 +1204,23 @@ async fn fetch_lists(
             .await?;
         }
 
-        handle_rate_limit(&resp.rate_limit_status, "Lists", message_sender.clone()).await;
-        cursor.next_cursor = resp.response.next_cursor;
-        config.set_paging_position("lists", u64::try_from(cursor.next_cursor).ok());
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, "Lists", message_sender.clone()).await;
+        }
+        cursor = page.next;
+        config.set_paging_position(paging_key, cursor.as_ref().and_then(Cursor::position));
+        if cursor.is_none() {
+            break;
+        }
     }
 
-    config.set_paging_position("lists", None);
+    config.set_paging_position(paging_key, None);
     Ok(())
 }
 
 async fn fetch_list_members(
     list: list::List,
+    kind: ListKind,
     shared_storage: Arc<Mutex<Storage>>,
     config: &Config,
     sender: Sender<DownloadInstruction>,
@@ -639,7 +1229,11 @@ async fn fetch_list_members(
     // Lists are not really synced, they're just not downloaded if they already exists
     if config.is_sync {
         let s = shared_storage.lock().await;
-        if s.data().lists.iter().any(|e| e.list.id == list.id) {
+        if s.data()
+            .lists
+            .iter()
+            .any(|e| e.list.id == list.id && !is_placeholder_list(&e.list))
+        {
             info!(
                 "Ignoring list {} because it was already downloaded",
                 &list.name
@@ -648,39 +1242,41 @@ async fn fetch_list_members(
         }
     }
 
-    let list_id = ListID::from_id(list.id);
-    let mut cursor = list::members(list_id, &config.token).with_page_size(100);
     let paging_key = format!("list-{}", list.id);
-    cursor.next_cursor = config
+    let mut cursor = config
         .paging_position(&paging_key)
-        .map(|e| e as i64)
-        .unwrap_or(-1);
+        .map(Cursor::next);
     let mut member_ids = Vec::new();
     loop {
-        let called = cursor.call();
-        let resp = match called.await {
+        let page = match config.api().list_members(list.id, cursor.clone()).await {
             Ok(n) => n,
-            Err(e) => match e {
-                egg_mode::error::Error::RateLimit(limit) => {
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(limited) => {
                     msg("Rate limit for Lists Members reached", &message_sender).await;
-                    sleep_until(limit).await;
+                    sleep_until(limited.reset).await;
                     continue;
                 }
-                _ => {
+                // E.g. the list was deleted or its owner suspended. A known list is kept
+                None => {
                     warn!("Lists Members Error {e:?}");
-                    continue;
+                    msg(
+                        format!("Could not download the members of {}: {e}", list.full_name),
+                        &message_sender,
+                    )
+                    .await;
+                    return Ok(());
                 }
             },
         };
 
-        if resp.users.is_empty() {
+        if page.items.is_empty() {
             break;
         }
 
         let mut storage = shared_storage.lock().await;
 
-        info!("Processing {} members", resp.users.len());
-        for member in &resp.users {
+        info!("Processing {} members", page.items.len());
+        for member in &page.items {
             if let Err(e) = inspect_profile(member, sender.clone()).await {
                 warn!("Could not inspect profile {e:?}");
             }
@@ -691,22 +1287,27 @@ async fn fetch_list_members(
                 .insert(member.id, member.clone());
         }
 
-        handle_rate_limit(
-            &resp.rate_limit_status,
-            "List Members",
-            message_sender.clone(),
-        )
-        .await;
-        cursor.next_cursor = resp.response.next_cursor;
-        config.set_paging_position(&paging_key, u64::try_from(cursor.next_cursor).ok());
+        if let Some(limit) = &page.rate_limit {
+            handle_rate_limit(limit, "List Members", message_sender.clone()).await;
+        }
+        cursor = page.next;
+        config.set_paging_position(&paging_key, cursor.as_ref().and_then(Cursor::position));
+        if cursor.is_none() {
+            break;
+        }
     }
 
     config.set_paging_position(&paging_key, None);
 
-    shared_storage.lock().await.data_mut().lists.push(List {
+    // Replace the placeholder from an archive import, if there is one
+    let mut storage = shared_storage.lock().await;
+    let lists = &mut storage.data_mut().lists;
+    lists.retain(|e| !(e.list.id == list.id && (is_placeholder_list(&e.list) || e.kind == kind)));
+    lists.push(List {
         name: list.name.clone(),
         list,
         members: member_ids,
+        kind,
     });
 
     Ok(())
@@ -723,12 +1324,14 @@ async fn fetch_single_profile(
         .await
         .data()
         .profiles
-        .contains_key(&id)
+        .get(&id)
+        .map(|p| !is_placeholder_profile(p))
+        .unwrap_or(false)
     {
         return Ok(());
     }
 
-    let user = user::show(id, &config.token).await?;
+    let user = config.api().user(id).await?;
     if let Err(e) = inspect_profile(&user, sender).await {
         warn!("Inspect profile error {e:?}");
     }
@@ -738,7 +1341,7 @@ async fn fetch_single_profile(
         .await
         .data_mut()
         .profiles
-        .insert(id, user.response);
+        .insert(id, user);
     Ok(())
 }
 
@@ -818,30 +1421,23 @@ async fn fetch_tweet_replies(
     sender: &Sender<DownloadInstruction>,
     message_sender: &Sender<Message>,
 ) -> Result<()> {
-    let search_results = egg_mode::search::search(format!("to:{}", config.screen_name()))
-        .since_tweet(tweet.id)
-        .count(100)
-        .call(&config.token)
+    let search_results = config
+        .api()
+        .search(&format!("to:{}", config.screen_name()), tweet.id, None)
         .await?;
-    handle_rate_limit(
-        &search_results.rate_limit_status,
-        "Tweet Replies",
-        message_sender.clone(),
-    )
-    .await;
+    if let Some(limit) = &search_results.rate_limit {
+        handle_rate_limit(limit, "Tweet Replies", message_sender.clone()).await;
+    }
 
     msg(
-        format!(
-            "Processing {} responses",
-            search_results.response.statuses.len()
-        ),
+        format!("Processing {} responses", search_results.items.len()),
         message_sender,
     )
     .await;
 
     let mut replies = Vec::new();
 
-    for related_tweet in search_results.response.statuses.into_iter() {
+    for related_tweet in search_results.items.into_iter() {
         if related_tweet.in_reply_to_status_id == Some(tweet.id) {
             if let Err(e) =
                 inspect_inner_tweet(&related_tweet, config, &storage, sender.clone()).await
@@ -888,11 +1484,12 @@ async fn inspect_profile(profile: &TwitterUser, sender: Sender<DownloadInstructi
 
 async fn handle_instruction(
     client: &Client,
+    source: &MediaSource,
     instruction: DownloadInstruction,
     shared_storage: Arc<Mutex<Storage>>,
 ) -> Result<()> {
-    let (extension, url) = match instruction {
-        DownloadInstruction::Image(url) => (extension_for_url(&url), url),
+    let (extension, mime, url, token) = match instruction {
+        DownloadInstruction::Image(url) => (extension_for_url(&url), None, url, None),
         DownloadInstruction::Movie(mime, url) => (
             match mime.subtype().as_str().to_lowercase().as_str() {
                 "mp4" => "mp4".to_string(),
@@ -901,39 +1498,68 @@ async fn handle_instruction(
                 "mov" => "mov".to_string(),
                 _ => extension_for_url(&url),
             },
+            Some(mime.to_string()),
             url,
+            None,
         ),
-        DownloadInstruction::ProfileMedia(url) => (extension_for_url(&url), url),
+        DownloadInstruction::ProfileMedia(url) => (extension_for_url(&url), None, url, None),
+        DownloadInstruction::MessageMedia(token, url) => {
+            (extension_for_url(&url), None, url, Some(token))
+        }
         _ => return Ok(()),
     };
-    let (absolute_path, relative_path) = {
+    let mut writer = {
         let storage = shared_storage.lock().await;
         if storage.data().media.contains_key(&url) {
             return Ok(());
         }
-        let mut hasher = DefaultHasher::new();
-        hasher.write(url.as_bytes());
-        let file_name = format!("{}.{extension}", hasher.finish());
-        (storage.media_path(&file_name), file_name)
+        storage.media_writer()?
     };
 
-    let mut fp = std::fs::File::create(&absolute_path)?;
-
-    let bytes = client.get(&url).send().await?.bytes().await?;
-
-    fp.write_all(&bytes)?;
+    // If the download fails, the writer removes the partial file
+    let content_type = match (source, token) {
+        (MediaSource::Fixtures(folder), _) => {
+            let name = url.rsplit('/').next().unwrap_or_default();
+            let recorded = std::fs::read(folder.join(name))
+                .map_err(|e| eyre!("No recorded media for {url}: {e}"))?;
+            writer.write_all(&recorded)?;
+            None
+        }
+        (MediaSource::Twitter, None) => {
+            let mut response = client.get(&url).send().await?.error_for_status()?;
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string());
+            while let Some(chunk) = response.chunk().await? {
+                writer.write_all(&chunk)?;
+            }
+            content_type
+        }
+        (MediaSource::Twitter, Some(token)) => {
+            let request = egg_mode::raw::request_get(&url, &token, None);
+            let (headers, body) = egg_mode::raw::response_raw_bytes(request).await?;
+            writer.write_all(&body)?;
+            headers
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        }
+    };
+    let mime = mime
+        .or(content_type)
+        .or_else(|| crate::media::mime_for_extension(&extension));
 
     shared_storage
         .lock()
         .await
-        .data_mut()
-        .media
-        .insert(url, relative_path);
+        .store_media(&url, writer, &extension, mime)?;
 
     Ok(())
 }
 
-fn extension_for_url(url: &str) -> String {
+pub fn extension_for_url(url: &str) -> String {
     let default = "png".to_string();
     let Ok(parsed) = url::Url::parse(url) else {
         return default;
@@ -990,3 +1616,80 @@ async fn sleep_until(time: i32) {
     let wait_duration = tokio::time::Duration::from_secs(seconds);
     tokio::time::sleep(wait_duration).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tokio::sync::mpsc::Receiver;
+
+    use super::*;
+
+    /// The storage the crawler sends when it is done
+    async fn finished(mut receiver: Receiver<Message>) -> Storage {
+        while let Some(message) = receiver.recv().await {
+            match message {
+                Message::Finished(storage) => return storage,
+                Message::Error(e) => panic!("The crawl failed: {e:?}"),
+                _ => {}
+            }
+        }
+        panic!("The crawler stopped without a storage")
+    }
+
+    fn fixtures(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn crawl_and_sync_fixtures() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = Some(folder.path().to_path_buf());
+
+        let config = Config::for_fixtures(path.clone(), None, &fixtures("crawl")).unwrap();
+        let (sender, receiver) = channel(256);
+        let receiving = tokio::spawn(finished(receiver));
+        crawl_new_storage(config, sender, 12345).await.unwrap();
+        let storage = receiving.await.unwrap();
+        let ids: Vec<TweetId> = storage.data().tweets.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [1600000000000000000, 1500000000000000000]);
+        assert_eq!(storage.data().followers, [777]);
+
+        let mut config = Config::for_fixtures(path, None, &fixtures("sync")).unwrap();
+        config.is_sync = true;
+        let (sender, receiver) = channel(256);
+        let receiving = tokio::spawn(finished(receiver));
+        crawl_into_storage(12345, config, storage, sender)
+            .await
+            .unwrap();
+        let storage = receiving.await.unwrap();
+        let data = storage.data();
+
+        let ids: Vec<TweetId> = data.tweets.iter().map(|t| t.id).collect();
+        assert_eq!(
+            ids,
+            [
+                1700000000000000000,
+                1600000000000000000,
+                1500000000000000000
+            ]
+        );
+        assert_eq!(data.mentions.len(), 1);
+        assert_eq!(data.likes.len(), 1);
+        assert_eq!(data.followers, [999, 777]);
+        assert_eq!(data.follows, [888]);
+        assert_eq!(data.profiles[&999].screen_name, "newcomer");
+        assert_eq!(data.lists.len(), 1);
+        assert_eq!(data.lists[0].members, [888]);
+        // The media comes from the fixtures, not from Twitter
+        for url in [
+            "https://pbs.twimg.com/media/photo.png",
+            "https://pbs.twimg.com/profile_images/999/newcomer.png",
+        ] {
+            let entry = &data.media[url];
+            assert!(storage.media_path(&entry.file).exists());
+        }
+    }
+}
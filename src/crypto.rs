@@ -0,0 +1,477 @@
+//! Optional passphrase based encryption of a storage folder. If a folder
+//! contains a vault file, the data file, the settings files, the deletions
+//! and the media are encrypted with a key derived from the passphrase (Argon2id) using
+//! XChaCha20-Poly1305. Nothing can be read before the vault is unlocked.
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use eyre::{bail, eyre, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const FILE_VAULT: &str = "_vault.json";
+/// The vault file while the storage is being encrypted. It is renamed to
+/// `FILE_VAULT` once everything is encrypted, until then files that aren't
+/// encrypted yet can still be read
+const FILE_VAULT_PENDING: &str = "_vault.json.pending";
+/// Every encrypted file starts with this, followed by the nonce
+const MAGIC: &[u8] = b"TVENC1";
+/// Files encrypted in chunks (media) start with this, see `ChunkedEncryption`
+const CHUNKED_MAGIC: &[u8] = b"TVENC2";
+/// The plaintext length of every chunk except the last one
+const CHUNK_LENGTH: usize = 64 * 1024;
+const NONCE_LENGTH: usize = 24;
+const TAG_LENGTH: usize = 16;
+const SALT_LENGTH: usize = 16;
+/// Encrypted in the vault file to find out whether a passphrase is correct
+const CHECK_PLAINTEXT: &[u8] = b"twitvault";
+/// Separates the key that names media files from the encryption key
+const MEDIA_NAME_CONTEXT: &[u8] = b"twitvault media names";
+
+/// The vault file. It only contains what is needed to derive the key again
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    kdf: String,
+    salt: String,
+    check: String,
+}
+
+/// An unlocked vault
+#[derive(Clone)]
+pub struct Vault {
+    key: Arc<Key>,
+    /// Whether the storage is still being encrypted, see `FILE_VAULT_PENDING`
+    pending: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Vault")
+    }
+}
+
+impl PartialEq for Vault {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.key, &other.key)
+    }
+}
+
+impl Eq for Vault {}
+
+impl Vault {
+    /// Whether the storage folder is encrypted (or partially encrypted)
+    pub fn exists(root_folder: &Path) -> bool {
+        root_folder.join(FILE_VAULT).exists() || root_folder.join(FILE_VAULT_PENDING).exists()
+    }
+
+    /// Create a new pending vault for the storage folder. This doesn't encrypt
+    /// anything yet, see `Storage::encrypt`. Once everything is encrypted,
+    /// the vault has to be completed with `complete`
+    pub fn create(root_folder: &Path, passphrase: &str) -> Result<Self> {
+        if Self::exists(root_folder) {
+            bail!(
+                "The storage at {} is already encrypted",
+                root_folder.display()
+            )
+        }
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let vault = Self::derive(passphrase, &salt)?;
+        let vault_file = VaultFile {
+            kdf: "argon2id".to_string(),
+            salt: hex::encode(salt),
+            check: hex::encode(vault.encrypt(CHECK_PLAINTEXT)?),
+        };
+        let path = root_folder.join(FILE_VAULT_PENDING);
+        std::fs::write(path, serde_json::to_vec(&vault_file)?)?;
+        vault.pending.store(true, Ordering::Relaxed);
+        Ok(vault)
+    }
+
+    /// Whether the storage is still being encrypted, e.g. because the
+    /// encryption was interrupted
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Mark the storage as completely encrypted. From now on, files that
+    /// aren't encrypted are rejected
+    pub fn complete(&self, root_folder: &Path) -> Result<()> {
+        let pending_path = root_folder.join(FILE_VAULT_PENDING);
+        if pending_path.exists() {
+            std::fs::rename(&pending_path, root_folder.join(FILE_VAULT))?;
+        }
+        self.pending.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn unlock(root_folder: &Path, passphrase: &str) -> Result<Self> {
+        let pending = !root_folder.join(FILE_VAULT).exists();
+        let path = match pending {
+            true => root_folder.join(FILE_VAULT_PENDING),
+            false => root_folder.join(FILE_VAULT),
+        };
+        let input = std::fs::read(path)?;
+        let vault_file: VaultFile = serde_json::from_slice(&input)?;
+        if vault_file.kdf != "argon2id" {
+            bail!("Unknown key derivation {}", vault_file.kdf)
+        }
+        let vault = Self::derive(passphrase, &hex::decode(vault_file.salt)?)?;
+        vault.pending.store(pending, Ordering::Relaxed);
+        match vault.decrypt(&hex::decode(vault_file.check)?) {
+            Ok(check) if check == CHECK_PLAINTEXT => Ok(vault),
+            _ => bail!("Wrong passphrase"),
+        }
+    }
+
+    fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| eyre!("Could not derive key: {e}"))?;
+        Ok(Self {
+            key: Arc::new(key),
+            pending: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// The name of an encrypted media file with the SHA-256 `sha256` (hex).
+    /// Without the key, the name doesn't reveal which file it contains
+    pub fn media_name(&self, sha256: &str) -> String {
+        let key = hmac_sha256(self.key.as_slice(), MEDIA_NAME_CONTEXT);
+        hex::encode(hmac_sha256(&key, sha256.as_bytes()))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new(&self.key);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| eyre!("Could not encrypt"))?;
+        let mut output = Vec::with_capacity(MAGIC.len() + NONCE_LENGTH + ciphertext.len());
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        if bytes.starts_with(CHUNKED_MAGIC) {
+            return self.decrypt_chunked(&bytes[CHUNKED_MAGIC.len()..]);
+        }
+        if !is_encrypted(bytes) || bytes.len() < MAGIC.len() + NONCE_LENGTH {
+            bail!("The data is not encrypted")
+        }
+        let (nonce, ciphertext) = bytes[MAGIC.len()..].split_at(NONCE_LENGTH);
+        let cipher = XChaCha20Poly1305::new(&self.key);
+        cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| eyre!("Could not decrypt, the data is damaged"))
+    }
+
+    /// Encrypt one chunk of a chunked file. The position of the chunk and
+    /// whether it is the last one are authenticated, so chunks can't be
+    /// reordered or cut off
+    fn encrypt_chunk(&self, index: u64, last: bool, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new(&self.key);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = chunk_aad(index, last);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| eyre!("Could not encrypt"))?;
+        let mut output = Vec::with_capacity(NONCE_LENGTH + ciphertext.len());
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    /// The chunks of a file written by `ChunkedEncryption`, without the magic bytes
+    fn decrypt_chunked(&self, mut bytes: &[u8]) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new(&self.key);
+        let mut output = Vec::with_capacity(bytes.len());
+        let mut index = 0;
+        loop {
+            let length = bytes.len().min(NONCE_LENGTH + CHUNK_LENGTH + TAG_LENGTH);
+            if length < NONCE_LENGTH + TAG_LENGTH {
+                bail!("Could not decrypt, the data is damaged")
+            }
+            let (chunk, rest) = bytes.split_at(length);
+            let (nonce, ciphertext) = chunk.split_at(NONCE_LENGTH);
+            let aad = chunk_aad(index, rest.is_empty());
+            let plaintext = cipher
+                .decrypt(
+                    XNonce::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| eyre!("Could not decrypt, the data is damaged"))?;
+            output.extend_from_slice(&plaintext);
+            if rest.is_empty() {
+                return Ok(output);
+            }
+            bytes = rest;
+            index += 1;
+        }
+    }
+}
+
+/// HMAC-SHA256 (RFC 2104) for keys of up to one block
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_LENGTH: usize = 64;
+    debug_assert!(key.len() <= BLOCK_LENGTH);
+    let mut inner_pad = [0x36u8; BLOCK_LENGTH];
+    let mut outer_pad = [0x5cu8; BLOCK_LENGTH];
+    for (n, byte) in key.iter().enumerate() {
+        inner_pad[n] ^= byte;
+        outer_pad[n] ^= byte;
+    }
+    let inner = Sha256::new()
+        .chain_update(inner_pad)
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(outer_pad)
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn chunk_aad(index: u64, last: bool) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[..8].copy_from_slice(&index.to_le_bytes());
+    aad[8] = last as u8;
+    aad
+}
+
+/// Encrypts a file while it is written, so that it doesn't have to be kept
+/// in memory. The plaintext is split into chunks of `CHUNK_LENGTH` which are
+/// encrypted one by one. `Vault::decrypt` reads these files as well
+pub struct ChunkedEncryption {
+    vault: Vault,
+    buffer: Vec<u8>,
+    index: u64,
+}
+
+impl ChunkedEncryption {
+    /// Start a chunked file by writing its magic bytes to `output`
+    pub fn new(vault: Vault, output: &mut impl Write) -> Result<Self> {
+        output.write_all(CHUNKED_MAGIC)?;
+        Ok(Self {
+            vault,
+            buffer: Vec::with_capacity(CHUNK_LENGTH),
+            index: 0,
+        })
+    }
+
+    /// Encrypt the complete chunks to `output`. A complete chunk is only
+    /// written once more data follows, as the last chunk is marked
+    pub fn update(&mut self, mut plaintext: &[u8], output: &mut impl Write) -> Result<()> {
+        while !plaintext.is_empty() {
+            if self.buffer.len() == CHUNK_LENGTH {
+                self.write_chunk(false, output)?;
+            }
+            let length = plaintext.len().min(CHUNK_LENGTH - self.buffer.len());
+            self.buffer.extend_from_slice(&plaintext[..length]);
+            plaintext = &plaintext[length..];
+        }
+        Ok(())
+    }
+
+    /// Encrypt the remaining data as the last chunk
+    pub fn finish(mut self, output: &mut impl Write) -> Result<()> {
+        self.write_chunk(true, output)
+    }
+
+    fn write_chunk(&mut self, last: bool, output: &mut impl Write) -> Result<()> {
+        let chunk = self.vault.encrypt_chunk(self.index, last, &self.buffer)?;
+        output.write_all(&chunk)?;
+        self.buffer.clear();
+        self.index += 1;
+        Ok(())
+    }
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC) || bytes.starts_with(CHUNKED_MAGIC)
+}
+
+/// Whether a file is encrypted, without reading all of it
+pub fn is_encrypted_file(path: &Path) -> Result<bool> {
+    let mut start = Vec::with_capacity(MAGIC.len());
+    std::fs::File::open(path)?
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut start)?;
+    Ok(is_encrypted(&start))
+}
+
+/// Read a file and decrypt it if necessary. Without a vault, files that
+/// aren't encrypted are returned as they are. With a vault, they are
+/// rejected, as they could have been swapped in for the encrypted ones,
+/// unless the storage is still being encrypted
+pub fn read_file(path: &Path, vault: Option<&Vault>) -> Result<Vec<u8>> {
+    let input = std::fs::read(path)?;
+    if !is_encrypted(&input) {
+        if vault.is_some_and(|vault| !vault.is_pending()) {
+            bail!("{} is not encrypted, but the storage is", path.display())
+        }
+        return Ok(input);
+    }
+    let Some(vault) = vault else {
+        bail!(
+            "{} is encrypted, please unlock the storage first",
+            path.display()
+        )
+    };
+    vault.decrypt(&input)
+}
+
+/// Encrypt a file in place, unless it is encrypted already
+pub fn encrypt_file(path: &Path, vault: &Vault) -> Result<()> {
+    let content = std::fs::read(path)?;
+    if is_encrypted(&content) {
+        return Ok(());
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, vault.encrypt(&content)?)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Overwrite a file with zeros before removing it, so that the plaintext
+/// doesn't simply stay on the disk
+pub fn remove_file_securely(path: &Path) -> Result<()> {
+    let length = std::fs::metadata(path)?.len();
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    std::io::copy(&mut std::io::repeat(0).take(length), &mut file)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Encrypt the data if there is a vault
+pub fn encrypt_if_needed(data: Vec<u8>, vault: Option<&Vault>) -> Result<Vec<u8>> {
+    match vault {
+        Some(vault) => vault.encrypt(&data),
+        None => Ok(data),
+    }
+}
+
+/// Ask for the passphrase in the terminal
+pub fn prompt_passphrase(confirm: bool) -> Result<String> {
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("The passphrase can't be empty")
+    }
+    if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        bail!("The passphrases don't match")
+    }
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_vault_reads_plaintext_until_completed() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("data");
+        std::fs::write(&path, b"plain").unwrap();
+
+        Vault::create(folder.path(), "secret").unwrap();
+        let vault = Vault::unlock(folder.path(), "secret").unwrap();
+        assert!(vault.is_pending());
+        assert_eq!(read_file(&path, Some(&vault)).unwrap(), b"plain");
+
+        vault.complete(folder.path()).unwrap();
+        assert!(read_file(&path, Some(&vault)).is_err());
+        let vault = Vault::unlock(folder.path(), "secret").unwrap();
+        assert!(!vault.is_pending());
+    }
+
+    /// Encrypt `plaintext` with `ChunkedEncryption`, in pieces of odd sizes
+    fn encrypt_chunked(vault: &Vault, plaintext: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut encryption = ChunkedEncryption::new(vault.clone(), &mut output).unwrap();
+        for piece in plaintext.chunks(1000) {
+            encryption.update(piece, &mut output).unwrap();
+        }
+        encryption.finish(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn unlock_needs_the_passphrase() {
+        let folder = tempfile::tempdir().unwrap();
+        let vault = Vault::create(folder.path(), "secret").unwrap();
+        assert!(Vault::unlock(folder.path(), "wrong").is_err());
+        let encrypted = vault.encrypt(b"data").unwrap();
+        assert!(is_encrypted(&encrypted));
+        let unlocked = Vault::unlock(folder.path(), "secret").unwrap();
+        assert_eq!(unlocked.decrypt(&encrypted).unwrap(), b"data");
+    }
+
+    #[test]
+    fn chunked_encryption_round_trips() {
+        let folder = tempfile::tempdir().unwrap();
+        let vault = Vault::create(folder.path(), "secret").unwrap();
+        for length in [0, 1, CHUNK_LENGTH, CHUNK_LENGTH + 1, CHUNK_LENGTH * 5 / 2] {
+            let plaintext: Vec<u8> = (0..length).map(|n| n as u8).collect();
+            let encrypted = encrypt_chunked(&vault, &plaintext);
+            assert!(encrypted.starts_with(CHUNKED_MAGIC));
+            assert_eq!(vault.decrypt(&encrypted).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn chunked_encryption_detects_truncation_and_reordering() {
+        let folder = tempfile::tempdir().unwrap();
+        let vault = Vault::create(folder.path(), "secret").unwrap();
+        let plaintext = vec![7u8; CHUNK_LENGTH * 5 / 2];
+        let encrypted = encrypt_chunked(&vault, &plaintext);
+        let chunk = NONCE_LENGTH + CHUNK_LENGTH + TAG_LENGTH;
+        let start = CHUNKED_MAGIC.len();
+
+        // Without the last chunk, the second one isn't marked as last
+        assert!(vault.decrypt(&encrypted[..start + 2 * chunk]).is_err());
+        assert!(vault.decrypt(&encrypted[..start + chunk]).is_err());
+        assert!(vault.decrypt(&encrypted[..encrypted.len() - 1]).is_err());
+
+        let mut reordered = encrypted[..start].to_vec();
+        reordered.extend_from_slice(&encrypted[start + chunk..start + 2 * chunk]);
+        reordered.extend_from_slice(&encrypted[start..start + chunk]);
+        reordered.extend_from_slice(&encrypted[start + 2 * chunk..]);
+        assert_eq!(reordered.len(), encrypted.len());
+        assert!(vault.decrypt(&reordered).is_err());
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            hex::encode(mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
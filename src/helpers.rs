@@ -1,53 +1,223 @@
-use crate::{config::Config, crawler::DownloadInstruction};
-use egg_mode::tweet::Tweet;
+use crate::{
+    config::Config,
+    crawler::DownloadInstruction,
+    storage::{Storage, TweetId, UserId},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use egg_mode::{entities::MediaEntity, list::List, tweet::Tweet, user::TwitterUser};
+use std::collections::{HashMap, HashSet};
 use tracing::warn;
 
 pub fn media_in_tweet(tweet: &Tweet) -> Option<Vec<DownloadInstruction>> {
     let Some(entities) = &tweet.extended_entities else { return None };
 
-    let mut output = Vec::new();
-
-    for media in &entities.media {
-        match &media.video_info {
-            Some(n) => {
-                let mut selected_variant = n.variants.first();
-                for variant in &n.variants {
-                    match (
-                        variant.content_type.subtype(),
-                        &selected_variant.map(|e| e.bitrate),
-                    ) {
-                        (mime::MP4, Some(bitrate)) if bitrate < &variant.bitrate => {
-                            selected_variant = Some(variant)
-                        }
-                        _ => (),
-                    }
-                }
-                let Some(variant) = selected_variant else { continue };
-                output.push(DownloadInstruction::Movie(
-                    variant.content_type.clone(),
-                    variant.url.clone(),
-                ))
+    Some(entities.media.iter().filter_map(media_instruction).collect())
+}
+
+/// The image, or the video with the highest bitrate
+pub fn media_instruction(media: &MediaEntity) -> Option<DownloadInstruction> {
+    let Some(n) = &media.video_info else {
+        return Some(DownloadInstruction::Image(media.media_url_https.clone()))
+    };
+    let mut selected_variant = n.variants.first();
+    for variant in &n.variants {
+        match (
+            variant.content_type.subtype(),
+            &selected_variant.map(|e| e.bitrate),
+        ) {
+            (mime::MP4, Some(bitrate)) if bitrate < &variant.bitrate => {
+                selected_variant = Some(variant)
             }
-            None => output.push(DownloadInstruction::Image(media.media_url_https.clone())),
+            _ => (),
         }
     }
-
-    Some(output)
+    let variant = selected_variant?;
+    Some(DownloadInstruction::Movie(
+        variant.content_type.clone(),
+        variant.url.clone(),
+    ))
 }
 
 pub async fn delete_tweet(tweet_id: u64, config: &Config) -> Result<bool, String> {
     egg_mode::tweet::delete(tweet_id, &config.token)
         .await
-        .map(|_| true)
         .map_err(|e| {
             warn!("Could not delete tweet: {e:?}");
             format!("{e:?}")
-        })
+        })?;
+    // The tweet stays in the archive, marked as deleted with TwitVault
+    let storage_path = config.actual_storage_path();
+    if let Err(e) = Storage::record_deletion(&storage_path, tweet_id, config.vault()) {
+        warn!("Could not record the deletion of {tweet_id}: {e:?}");
+    }
+    Ok(true)
+}
+
+/// Tweet ids since November 2010 are snowflakes which contain
+/// the creation time. For older ids, this returns the unix epoch
+pub fn date_from_snowflake(id: u64) -> DateTime<Utc> {
+    const TWITTER_EPOCH: i64 = 1288834974657;
+    const FIRST_SNOWFLAKE: u64 = 29700859247;
+    if id < FIRST_SNOWFLAKE {
+        return Utc.timestamp_opt(0, 0).unwrap();
+    }
+    let millis = (id >> 22) as i64 + TWITTER_EPOCH;
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .unwrap_or_else(|| Utc.timestamp_opt(0, 0).unwrap())
+}
+
+/// A stand-in profile for data where we only know the id
+/// (e.g. from a Twitter archive). An empty `screen_name` means it is
+/// unknown, see `profile_url`.
+pub fn placeholder_profile(id: u64, name: &str, screen_name: &str) -> TwitterUser {
+    TwitterUser {
+        contributors_enabled: false,
+        created_at: Utc.timestamp_opt(0, 0).unwrap(),
+        default_profile: true,
+        default_profile_image: true,
+        description: None,
+        entities: Default::default(),
+        favourites_count: 0,
+        follow_request_sent: None,
+        followers_count: 0,
+        friends_count: 0,
+        geo_enabled: false,
+        id,
+        is_translator: false,
+        lang: None,
+        listed_count: 0,
+        location: None,
+        name: name.to_string(),
+        profile_background_color: String::new(),
+        profile_background_image_url: None,
+        profile_background_image_url_https: None,
+        profile_background_tile: None,
+        profile_banner_url: None,
+        profile_image_url: String::new(),
+        profile_image_url_https: String::new(),
+        profile_link_color: String::new(),
+        profile_sidebar_border_color: String::new(),
+        profile_sidebar_fill_color: String::new(),
+        profile_text_color: String::new(),
+        profile_use_background_image: false,
+        protected: false,
+        screen_name: screen_name.to_string(),
+        show_all_inline_media: None,
+        status: None,
+        statuses_count: 0,
+        time_zone: None,
+        url: None,
+        utc_offset: None,
+        verified: false,
+        withheld_in_countries: None,
+        withheld_scope: None,
+    }
+}
+
+/// Placeholders have no profile image. Real profiles always
+/// have one, even if it is the default avatar
+pub fn is_placeholder_profile(profile: &TwitterUser) -> bool {
+    profile.profile_image_url_https.is_empty()
+}
+
+/// Give the ids without a profile a placeholder until a crawl downloads the actual profile
+pub fn insert_placeholder_profiles(profiles: &mut HashMap<UserId, TwitterUser>, ids: &[UserId]) {
+    for id in ids {
+        profiles
+            .entry(*id)
+            .or_insert_with(|| placeholder_profile(*id, &format!("User {id}"), ""));
+    }
+}
+
+/// The profile on Twitter. Profiles without a screen name are linked by their id
+pub fn profile_url(profile: &TwitterUser) -> String {
+    match profile.screen_name.is_empty() {
+        true => format!("https://twitter.com/intent/user?user_id={}", profile.id),
+        false => format!("https://twitter.com/{}", profile.screen_name),
+    }
+}
+
+/// The tweet on Twitter. Twitter redirects `i` to the actual author
+pub fn tweet_url(author: &TwitterUser, id: TweetId) -> String {
+    match author.screen_name.is_empty() {
+        true => format!("https://twitter.com/i/status/{id}"),
+        false => format!("https://twitter.com/{}/status/{id}", author.screen_name),
+    }
+}
+
+/// A stand-in list for lists where we only know the id (e.g. from
+/// a Twitter archive). A crawl replaces it with the actual list.
+pub fn placeholder_list(id: u64, owner: TwitterUser) -> List {
+    List {
+        name: format!("List {id}"),
+        user: owner,
+        slug: String::new(),
+        id,
+        subscriber_count: 0,
+        member_count: 0,
+        full_name: format!("List {id}"),
+        description: String::new(),
+        uri: format!("/i/lists/{id}"),
+        created_at: date_from_snowflake(id),
+    }
+}
+
+/// Placeholder lists have no slug. Real lists always have one
+pub fn is_placeholder_list(list: &List) -> bool {
+    list.slug.is_empty()
+}
+
+/// Append the ids that aren't in `ids` yet. Returns how many were added
+pub fn append_unknown_ids(ids: &mut Vec<u64>, new_ids: &[u64]) -> usize {
+    let mut known: HashSet<u64> = ids.iter().copied().collect();
+    let before = ids.len();
+    ids.extend(new_ids.iter().filter(|id| known.insert(**id)));
+    ids.len() - before
+}
+
+/// Insert the tweets that aren't in `tweets` yet. Each one goes before the
+/// next of `new_tweets` that is known, so both orders are kept, e.g. the
+/// order in which tweets were liked. Returns how many were added
+pub fn insert_unknown_tweets(tweets: &mut Vec<Tweet>, new_tweets: Vec<Tweet>) -> usize {
+    let positions: HashMap<TweetId, usize> = tweets
+        .iter()
+        .enumerate()
+        .map(|(position, t)| (t.id, position))
+        .collect();
+    let known = tweets.len();
+    // The tweets to insert before each known one, the last entry is for the end
+    let mut inserts: Vec<Vec<Tweet>> = vec![Vec::new(); known + 1];
+    let mut pending = Vec::new();
+    let mut seen = HashSet::new();
+    for tweet in new_tweets {
+        match positions.get(&tweet.id) {
+            Some(position) => inserts[*position].append(&mut pending),
+            None if seen.insert(tweet.id) => pending.push(tweet),
+            None => {}
+        }
+    }
+    inserts[known].append(&mut pending);
+    let added = seen.len();
+
+    let mut merged = Vec::with_capacity(known + added);
+    let mut inserts = inserts.into_iter();
+    for tweet in tweets.drain(..) {
+        merged.extend(inserts.next().unwrap_or_default());
+        merged.push(tweet);
+    }
+    merged.extend(inserts.flatten());
+    *tweets = merged;
+    added
 }
 
 /// Sorta cross-platform way of opening a file
 pub fn open_file(path: &str) {
     use std::process::Command;
+    // Decrypted media only exists in memory
+    if path.starts_with("data:") {
+        return;
+    }
     #[cfg(target_os = "windows")]
     {
         Command::new("explorer").arg(path).spawn().ok();
@@ -1,95 +1,427 @@
-//! If a Twitter archive exists, use it to import tweets and likes
-use std::{collections::HashSet, io::Seek, path::Path, str::FromStr, sync::Arc};
+//! If a Twitter archive exists, use it to import the profile, tweets (including
+//! deleted tweets and long note tweets), likes, follows, lists and direct messages
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
-use eyre::Result;
+use eyre::{bail, eyre, Result};
 use serde::{Deserialize, Deserializer, Serialize};
-use tokio::sync::{mpsc::channel, Mutex};
+use tokio::sync::{mpsc::Sender, Mutex};
 use tracing::{info, warn};
 
-use crate::{config::Config, storage::Storage, types::Message};
+use crate::{
+    archive::Archive,
+    config::Config,
+    crawler::{DownloadInstruction, MediaSource},
+    helpers::{append_unknown_ids, insert_placeholder_profiles, insert_unknown_tweets},
+    storage::{Conversation, DirectMessage, List, ListKind, Storage},
+    types::Message,
+};
 use egg_mode::{
     entities::{
         HashtagEntity, MediaEntity, MediaSize, MediaSizes, MentionEntity, UrlEntity, VideoInfo,
         VideoVariant,
     },
     tweet::{ExtendedTweetEntities, Tweet, TweetEntities, TweetSource},
+    user::TwitterUser,
 };
 use std::borrow::Cow;
-use std::io::Read;
 
-const ARCHIVE_DATA_FOLDER: &str = "data";
+/// Report the progress every n tweets
+const IMPORT_PROGRESS_INTERVAL: usize = 1000;
+
 const ARCHIVE_TWEETS_FILE: &str = "tweets.js";
+const ARCHIVE_TWEETS_MEDIA_FOLDER: &str = "tweets_media";
+const ARCHIVE_DELETED_TWEETS_FILE: &str = "deleted-tweets.js";
+const ARCHIVE_DELETED_TWEETS_MEDIA_FOLDER: &str = "deleted_tweets_media";
+const ARCHIVE_NOTE_TWEETS_FILE: &str = "note-tweet.js";
+const ARCHIVE_LIKES_FILE: &str = "like.js";
+const ARCHIVE_FOLLOWERS_FILE: &str = "follower.js";
+const ARCHIVE_FOLLOWING_FILE: &str = "following.js";
+const ARCHIVE_ACCOUNT_FILE: &str = "account.js";
+const ARCHIVE_PROFILE_FILE: &str = "profile.js";
+const ARCHIVE_PROFILE_MEDIA_FOLDER: &str = "profile_media";
+const ARCHIVE_LISTS_CREATED_FILE: &str = "lists-created.js";
+const ARCHIVE_LISTS_SUBSCRIBED_FILE: &str = "lists-subscribed.js";
+const ARCHIVE_LISTS_MEMBER_FILE: &str = "lists-member.js";
+const ARCHIVE_DM_FILE: &str = "direct-messages.js";
+const ARCHIVE_DM_MEDIA_FOLDER: &str = "direct_messages_media";
+const ARCHIVE_GROUP_DM_FILE: &str = "direct-messages-group.js";
+const ARCHIVE_GROUP_DM_MEDIA_FOLDER: &str = "direct_messages_group_media";
 
+/// Import from an archive folder or from one or more archive zip files.
+/// Without a config, nothing will be downloaded.
 pub async fn import_archive(
     storage: Storage,
-    config: &Config,
+    config: Option<&Config>,
+    paths: &[PathBuf],
+    message_sender: &Sender<Message>,
+) -> Result<Storage> {
+    let archive = Archive::open(paths)?;
+    import_into_storage(archive, storage, config, message_sender).await
+}
+
+/// Create a new storage at `path` for the owner of the archive. As the
+/// profile comes from the archive, this doesn't require a login.
+pub async fn import_new_archive(
     path: impl AsRef<Path>,
+    config: Option<&Config>,
+    paths: &[PathBuf],
+    message_sender: &Sender<Message>,
 ) -> Result<Storage> {
-    let tweet_file = path
-        .as_ref()
-        .join(ARCHIVE_DATA_FOLDER)
-        .join(ARCHIVE_TWEETS_FILE);
-    let mut reader = std::fs::File::open(tweet_file)?;
-
-    // find the beginning of the json
-    let buf: &mut [u8] = &mut [0];
-    let mut start = 0;
-    loop {
-        reader.read_exact(buf)?;
-        if buf[0] == b'[' && start > 0 {
-            reader.seek(std::io::SeekFrom::Start(start - 1))?;
-            break;
+    let mut archive = Archive::open(paths)?;
+    let profile = archive_profile(&mut archive)?;
+    let vault = config.and_then(|c| c.vault().cloned());
+    let mut storage = Storage::new(profile.clone(), path, vault)?;
+    storage.with_data(|d| {
+        d.profiles.insert(profile.id, profile.clone());
+    });
+    import_into_storage(archive, storage, config, message_sender).await
+}
+
+/// Import into `storage` or, if there is none, into a new storage at `path`.
+/// The storage is saved afterwards. Progress and the result are reported
+/// through `sender`, like `crawler::crawl_into_storage`.
+pub async fn import_with_progress(
+    storage: Option<Storage>,
+    path: PathBuf,
+    config: Option<Config>,
+    paths: Vec<PathBuf>,
+    sender: Sender<Message>,
+) -> Result<()> {
+    let import_task = tokio::spawn(async move {
+        let imported = match storage {
+            Some(storage) => import_archive(storage, config.as_ref(), &paths, &sender).await,
+            None => import_new_archive(&path, config.as_ref(), &paths, &sender).await,
+        };
+        let message = match imported.and_then(|storage| storage.save().map(|_| storage)) {
+            Ok(mut storage) => {
+                storage.data_mut().rebuild_index();
+                Message::Finished(storage)
+            }
+            Err(e) => Message::Error(e),
+        };
+        if let Err(e) = sender.send(message).await {
+            println!("Could not send import result {e:?}");
         }
-        start += 1;
-    }
-    let mut buffer = Vec::new();
-    reader.read_to_end(&mut buffer)?;
-    let decoded: Vec<TweetContainer> = serde_json::from_slice(&buffer)?;
-    let total_new = decoded.len();
+    });
 
-    if total_new == 0 {
-        return Ok(storage);
-    }
+    import_task.await?;
 
-    let known_ids: HashSet<u64> = storage.data().tweets.iter().map(|e| e.id).collect();
-    // get a copy of all known tweets so we can insert new ones and in the
-    // end sort them all
-    let mut tweets = storage.data().tweets.clone();
+    Ok(())
+}
 
+async fn import_into_storage(
+    mut archive: Archive,
+    storage: Storage,
+    config: Option<&Config>,
+    message_sender: &Sender<Message>,
+) -> Result<Storage> {
     let shared_storage = Arc::new(Mutex::new(storage));
 
+    let should_download_media = config.map(|c| c.crawl_options().media).unwrap_or(false);
+    let source = config.map_or(MediaSource::Twitter, Config::media_source);
     let cloned_storage = shared_storage.clone();
     let (instruction_task, instruction_sender) =
-        crate::crawler::create_instruction_handler(config.crawl_options().media, cloned_storage);
+        crate::crawler::create_instruction_handler(should_download_media, source, cloned_storage);
+
+    import_profile(&mut archive, shared_storage.clone(), message_sender).await?;
+
+    for (file, media_folder) in [
+        (ARCHIVE_TWEETS_FILE, ARCHIVE_TWEETS_MEDIA_FOLDER),
+        (
+            ARCHIVE_DELETED_TWEETS_FILE,
+            ARCHIVE_DELETED_TWEETS_MEDIA_FOLDER,
+        ),
+    ] {
+        import_tweets(
+            &mut archive,
+            file,
+            media_folder,
+            shared_storage.clone(),
+            config,
+            &instruction_sender,
+            message_sender,
+        )
+        .await?;
+    }
+
+    import_note_tweets(&mut archive, shared_storage.clone(), message_sender).await?;
+
+    import_likes(&mut archive, shared_storage.clone(), message_sender).await?;
+
+    import_follows(&mut archive, shared_storage.clone(), message_sender).await?;
+
+    for (file, kind) in [
+        (ARCHIVE_LISTS_CREATED_FILE, ListKind::Created),
+        (ARCHIVE_LISTS_SUBSCRIBED_FILE, ListKind::Subscribed),
+        (ARCHIVE_LISTS_MEMBER_FILE, ListKind::Member),
+    ] {
+        import_lists(
+            &mut archive,
+            file,
+            kind,
+            shared_storage.clone(),
+            message_sender,
+        )
+        .await?;
+    }
+
+    for (file, media_folder) in [
+        (ARCHIVE_DM_FILE, ARCHIVE_DM_MEDIA_FOLDER),
+        (ARCHIVE_GROUP_DM_FILE, ARCHIVE_GROUP_DM_MEDIA_FOLDER),
+    ] {
+        import_conversations(
+            &mut archive,
+            file,
+            media_folder,
+            shared_storage.clone(),
+            message_sender,
+        )
+        .await?;
+    }
+
+    report("Waiting for Media downloads".to_string(), message_sender).await;
+    if let Err(e) = instruction_sender
+        .send(crate::crawler::DownloadInstruction::Done)
+        .await
+    {
+        warn!("Could not stop instruction task: {e:?}");
+    }
+
+    // wait for the tasks to finish
+    info!("Waiting for Instruction task to finish downloading media");
+    if let Err(e) = instruction_task.await {
+        warn!("Error executing instructions: {e:?}");
+    }
+
+    let new_storage = shared_storage.lock_owned().await.clone();
+
+    Ok(new_storage)
+}
+
+/// Build the profile of the archive owner from `account.js` and `profile.js`.
+/// The archive doesn't contain counts (followers, tweets, ...) so those are 0.
+pub fn archive_profile(archive: &mut Archive) -> Result<TwitterUser> {
+    let account = archive
+        .read_data_file::<AccountContainer>(ARCHIVE_ACCOUNT_FILE)
+        .ok()
+        .flatten()
+        .and_then(|mut e| e.pop())
+        .ok_or_else(|| eyre!("No valid {ARCHIVE_ACCOUNT_FILE} in archive"))?
+        .account;
+    let profile = archive
+        .read_data_file::<ProfileContainer>(ARCHIVE_PROFILE_FILE)
+        .ok()
+        .flatten()
+        .and_then(|mut e| e.pop())
+        .map(|e| e.profile);
+
+    let mut user = crate::helpers::placeholder_profile(
+        account.account_id,
+        &account.account_display_name,
+        &account.username,
+    );
+    user.created_at = account.created_at;
+    if let Some(profile) = profile {
+        user.description = profile.description.bio.filter(|e| !e.is_empty());
+        user.location = profile.description.location.filter(|e| !e.is_empty());
+        user.url = profile.description.website.filter(|e| !e.is_empty());
+        user.profile_banner_url = profile.header_media_url;
+        if let Some(avatar) = profile.avatar_media_url {
+            user.default_profile_image = false;
+            user.profile_image_url = avatar.replacen("https://", "http://", 1);
+            user.profile_image_url_https = avatar;
+        }
+    }
+    Ok(user)
+}
+
+/// Updates the owner profile from the archive if the archive is newer than
+/// the last crawl, otherwise only fills in the parts that are missing (e.g.
+/// because the storage was created from an archive). Copies the avatar and
+/// header images.
+async fn import_profile(
+    archive: &mut Archive,
+    shared_storage: Arc<Mutex<Storage>>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report("Importing profile".to_string(), message_sender).await;
+    let archived = match archive_profile(archive) {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Could not read the archive profile: {e:?}");
+            return Ok(());
+        }
+    };
+
+    let mut storage = shared_storage.lock().await;
+    if storage.data().profile.id != archived.id {
+        warn!(
+            "The archive belongs to {} but the storage to {}. Not importing the profile",
+            archived.screen_name,
+            storage.data().profile.screen_name
+        );
+        return Ok(());
+    }
+
+    let generated = archive.generation_date();
+    let data = storage.data_mut();
+    let crawled = data.authors_last_seen().remove(&archived.id);
+    let archive_is_newer = match (generated, crawled) {
+        (Some(generated), Some(crawled)) => generated > crawled,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+    let profile = &mut data.profile;
+    if crate::helpers::is_placeholder_profile(profile) {
+        *profile = archived.clone();
+    } else if archive_is_newer {
+        // The archive has no counts or colors, those are kept
+        profile.name = archived.name;
+        profile.screen_name = archived.screen_name;
+        profile.description = archived.description.or(profile.description.take());
+        profile.location = archived.location.or(profile.location.take());
+        profile.url = archived.url.or(profile.url.take());
+        profile.profile_banner_url = archived
+            .profile_banner_url
+            .or(profile.profile_banner_url.take());
+        if !archived.profile_image_url_https.is_empty() {
+            profile.default_profile_image = archived.default_profile_image;
+            profile.profile_image_url = archived.profile_image_url;
+            profile.profile_image_url_https = archived.profile_image_url_https;
+        }
+    } else {
+        profile.description = profile.description.take().or(archived.description);
+        profile.location = profile.location.take().or(archived.location);
+        profile.url = profile.url.take().or(archived.url);
+        profile.profile_banner_url = profile
+            .profile_banner_url
+            .take()
+            .or(archived.profile_banner_url);
+    }
+    let profile = profile.clone();
+    data.profiles.insert(profile.id, profile.clone());
+
+    for url in [
+        Some(&profile.profile_image_url_https),
+        profile.profile_banner_url.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if storage.data().media.contains_key(url) {
+            continue;
+        }
+        let Some(archived) = archived_media_name(profile.id, url) else {
+            continue
+        };
+        // Urls without an extension (e.g. profile banners) are stored as jpg
+        let folder = ARCHIVE_PROFILE_MEDIA_FOLDER;
+        let jpg = format!("{archived}.jpg");
+        if !copy_archived_media(&mut storage, archive, folder, &archived, url)?
+            && !copy_archived_media(&mut storage, archive, folder, &jpg, url)?
+        {
+            info!("Profile media {url} is not in the archive");
+        }
+    }
+
+    Ok(())
+}
+
+/// Imports `tweets.js` or `deleted-tweets.js`. Deleted tweets have the same
+/// format, with an additional `deleted_at` which is stored in `Data::tweet_meta`.
+async fn import_tweets(
+    archive: &mut Archive,
+    file: &str,
+    media_folder: &str,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: Option<&Config>,
+    instruction_sender: &Sender<DownloadInstruction>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report(format!("Importing {file}"), message_sender).await;
+    let Some(decoded) = archive.read_data_file::<TweetContainer>(file)? else {
+        // Only newer archives contain deleted tweets
+        if file == ARCHIVE_TWEETS_FILE {
+            bail!("No {ARCHIVE_TWEETS_FILE} in archive")
+        }
+        return Ok(())
+    };
 
-    let (message_sender, _) = channel::<Message>(4096);
+    if decoded.is_empty() {
+        return Ok(());
+    }
+
+    // get a copy of all known tweets so we can insert new ones and in the
+    // end sort them all
+    let mut tweets = shared_storage.lock().await.data().tweets.clone();
+    let known_ids: HashSet<u64> = tweets.iter().map(|e| e.id).collect();
 
     // only insert those tweets that we don't have in storage yet.
     // then, collect the profiles and the media
-    let mut new_tweets = 0;
-    for container in decoded.into_iter() {
+    let parsed = decoded.len();
+    let (mut new_tweets, mut skipped_tweets) = (0, 0);
+    let (mut copied_media, mut missing_media) = (0, 0);
+    for (index, container) in decoded.into_iter().enumerate() {
+        if index % IMPORT_PROGRESS_INTERVAL == 0 {
+            report(
+                format!("{file}: {index} of {parsed} tweets, {new_tweets} new, {copied_media} media files copied"),
+                message_sender,
+            )
+            .await;
+        }
         let id = container.tweet.id;
+        // Tweets we crawled before they were deleted still get marked
+        if let Some(deleted_at) = container.tweet.deleted_at.as_deref() {
+            match parse_date(deleted_at) {
+                Ok(date) => {
+                    let mut storage = shared_storage.lock().await;
+                    let meta = storage.data_mut().tweet_meta.entry(id).or_default();
+                    meta.deleted_at = Some(date);
+                }
+                Err(e) => warn!("Could not parse deletion date of tweet {id}: {e:?}"),
+            }
+        }
         if known_ids.contains(&id) {
+            skipped_tweets += 1;
             continue;
         }
         match Tweet::try_from(container.tweet) {
             Ok(n) => {
-                if let Err(e) = crate::crawler::inspect_tweet(
+                // Media that is in the archive is registered before `inspect_tweet`
+                // so that the instruction handler will not download it again
+                let (copied, missing) = import_tweet_media(
                     &n,
-                    shared_storage.clone(),
-                    config,
-                    &instruction_sender,
-                    &message_sender,
-                )
-                .await
-                {
-                    warn!("Could not inspect tweet {id}: {e:?}");
+                    archive,
+                    media_folder,
+                    &mut *shared_storage.lock().await,
+                );
+                copied_media += copied;
+                missing_media += missing;
+                // Without a config, we can't access the API
+                if let Some(config) = config {
+                    if let Err(e) = crate::crawler::inspect_tweet(
+                        &n,
+                        shared_storage.clone(),
+                        config,
+                        instruction_sender,
+                        message_sender,
+                    )
+                    .await
+                    {
+                        warn!("Could not inspect tweet {id}: {e:?}");
+                    }
                 }
                 tweets.push(n);
                 new_tweets += 1;
             }
             Err(e) => {
                 warn!("Could not parse tweet {id}: {e:?}");
+                skipped_tweets += 1;
                 continue;
             }
         }
@@ -97,39 +429,603 @@ pub async fn import_archive(
 
     tweets.sort_by(|a, b| b.id.cmp(&a.id));
 
-    info!("Waiting for Media downloads");
-    if let Err(e) = instruction_sender
-        .send(crate::crawler::DownloadInstruction::Done)
-        .await
-    {
-        warn!("Could not stop instruction task: {e:?}");
+    report(
+        format!(
+            "{file}: parsed {parsed} tweets, {new_tweets} new, {skipped_tweets} skipped. Total: {}",
+            tweets.len()
+        ),
+        message_sender,
+    )
+    .await;
+    report(
+        format!("copied {copied_media} media files from the archive, {missing_media} are missing"),
+        message_sender,
+    )
+    .await;
+
+    shared_storage.lock().await.data_mut().tweets = tweets;
+
+    Ok(())
+}
+
+/// Tweets longer than 280 characters are truncated in `tweets.js`. The full
+/// text is in `note-tweet.js`, which doesn't reference the tweet id. Notes are
+/// matched by a link to the note in the tweet, if there is one. Otherwise by
+/// their creation date and the start of the text, as long as that's unambiguous.
+async fn import_note_tweets(
+    archive: &mut Archive,
+    shared_storage: Arc<Mutex<Storage>>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report(
+        format!("Importing {ARCHIVE_NOTE_TWEETS_FILE}"),
+        message_sender,
+    )
+    .await;
+    let Some(decoded) = archive.read_data_file::<NoteTweetContainer>(ARCHIVE_NOTE_TWEETS_FILE)? else {
+        return Ok(())
+    };
+
+    let mut storage = shared_storage.lock().await;
+    let tweets = &mut storage.data_mut().tweets;
+
+    let (mut joined, mut unmatched) = (0, 0);
+    let mut joined_ids = HashSet::new();
+    for container in decoded.into_iter() {
+        let note = container.note_tweet;
+        let linked = tweets
+            .iter()
+            .position(|tweet| links_to_note(tweet, &note.note_tweet_id));
+        let index = linked.or_else(|| {
+            let mut candidates = tweets.iter().enumerate().filter(|(_, tweet)| {
+                !joined_ids.contains(&tweet.id)
+                    && (tweet.created_at - note.created_at).num_seconds().abs()
+                        <= NOTE_TWEET_MAX_SECONDS
+                    && note_prefix(&tweet.text)
+                        .map_or(false, |prefix| note.core.text.starts_with(&prefix))
+            });
+            match (candidates.next(), candidates.next()) {
+                (Some((index, _)), None) => Some(index),
+                _ => None,
+            }
+        });
+        let Some(tweet) = index.map(|index| &mut tweets[index]) else {
+            info!("No unique tweet found for note {}", note.note_tweet_id);
+            unmatched += 1;
+            continue
+        };
+        joined_ids.insert(tweet.id);
+        apply_note_text(tweet, note.core.text);
+        joined += 1;
     }
 
-    // wait for the tasks to finish
-    info!("Waiting for Instruction task to finish downloading media");
-    if let Err(e) = instruction_task.await {
-        warn!("Error executing instructions: {e:?}");
+    report(
+        format!("joined {joined} note tweets, {unmatched} have no matching tweet"),
+        message_sender,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Notes and their tweets are created within the same request, but
+/// the timestamps can differ slightly
+const NOTE_TWEET_MAX_SECONDS: i64 = 60;
+
+/// Whether one of the links in the tweet points to the note
+fn links_to_note(tweet: &Tweet, note_id: &str) -> bool {
+    tweet.entities.urls.iter().any(|url| {
+        url.expanded_url.as_deref().map_or(false, |expanded| {
+            expanded
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .map_or(false, |last| last == note_id)
+        })
+    })
+}
+
+/// The part of a truncated tweet which is also in the note: everything before
+/// the `…`, unescaped like the note. Tweets without it aren't truncated and need no note.
+/// Returns `None` for (unlikely) empty prefixes, as they would match any note.
+fn note_prefix(text: &str) -> Option<String> {
+    let (prefix, _) = text.split_once('…')?;
+    let prefix = unescape_html(prefix.trim_end());
+    (!prefix.is_empty()).then_some(prefix)
+}
+
+/// The archive escapes these in the tweet text, the note text isn't escaped
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Replace the text with the full note text. Entities pointing into the
+/// truncated part (e.g. the link to the note) would point to the wrong text.
+/// Entity ranges count characters, not bytes.
+fn apply_note_text(tweet: &mut Tweet, text: String) {
+    let Some(prefix) = note_prefix(&tweet.text) else {
+        return
+    };
+    let end = prefix.chars().count();
+    let entities = &mut tweet.entities;
+    entities.hashtags.retain(|e| e.range.1 <= end);
+    entities.symbols.retain(|e| e.range.1 <= end);
+    entities.urls.retain(|e| e.range.1 <= end);
+    entities.user_mentions.retain(|e| e.range.1 <= end);
+    if let Some(media) = entities.media.as_mut() {
+        media.retain(|e| e.range.1 <= end);
     }
+    tweet.display_text_range = Some((0, text.chars().count()));
+    tweet.text = text;
+    tweet.truncated = false;
+}
 
-    info!("imported {new_tweets} new tweets. Total: {}", tweets.len());
+/// The archive contains every like ever made, but only the id and
+/// the text of the liked tweet. Likes we already know from crawling
+/// stay untouched, the remaining ones are appended as minimal tweets.
+async fn import_likes(
+    archive: &mut Archive,
+    shared_storage: Arc<Mutex<Storage>>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report(format!("Importing {ARCHIVE_LIKES_FILE}"), message_sender).await;
+    let Some(decoded) = archive.read_data_file::<LikeContainer>(ARCHIVE_LIKES_FILE)? else {
+        return Ok(())
+    };
 
-    let mut new_storage = shared_storage.lock_owned().await.clone();
+    let parsed = decoded.len();
+    // The archive has the likes newest first, like the crawled ones
+    let likes = decoded
+        .into_iter()
+        .map(|container| Tweet::from(container.like))
+        .collect();
 
-    new_storage.data_mut().tweets = tweets;
+    let mut storage = shared_storage.lock().await;
+    let known = &mut storage.data_mut().likes;
+    let added = insert_unknown_tweets(known, likes);
 
-    Ok(new_storage)
+    report(
+        format!(
+            "parsed {parsed} likes, {added} new, {} skipped. Total: {}",
+            parsed - added,
+            known.len()
+        ),
+        message_sender,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// The archive only contains the account ids of followers and follows.
+/// New ids are appended (like a non-sync crawl does) and get a placeholder
+/// profile until a crawl downloads the actual profile.
+async fn import_follows(
+    archive: &mut Archive,
+    shared_storage: Arc<Mutex<Storage>>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report(
+        "Importing followers and follows".to_string(),
+        message_sender,
+    )
+    .await;
+    let followers: Vec<u64> = archive
+        .read_data_file::<FollowerContainer>(ARCHIVE_FOLLOWERS_FILE)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| e.follower.account_id)
+        .collect();
+    let follows: Vec<u64> = archive
+        .read_data_file::<FollowingContainer>(ARCHIVE_FOLLOWING_FILE)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| e.following.account_id)
+        .collect();
+
+    let mut storage = shared_storage.lock().await;
+    let data = storage.data_mut();
+
+    let new_followers = append_unknown_ids(&mut data.followers, &followers);
+    let new_follows = append_unknown_ids(&mut data.follows, &follows);
+
+    insert_placeholder_profiles(&mut data.profiles, &followers);
+    insert_placeholder_profiles(&mut data.profiles, &follows);
+
+    let message = format!(
+        "imported {new_followers} new followers, {new_follows} new follows. Total: {} / {}",
+        data.followers.len(),
+        data.follows.len()
+    );
+    report(message, message_sender).await;
+
+    Ok(())
+}
+
+/// The archive only contains the urls of lists. Lists with an id in the url
+/// are imported as placeholders (without members) until a crawl replaces them.
+/// Lists we already know are left untouched.
+async fn import_lists(
+    archive: &mut Archive,
+    file: &str,
+    kind: ListKind,
+    shared_storage: Arc<Mutex<Storage>>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report(format!("Importing {file}"), message_sender).await;
+    let Some(decoded) = archive.read_data_file::<ListContainer>(file)? else {
+        return Ok(())
+    };
+
+    let mut storage = shared_storage.lock().await;
+    let mut known_ids: HashSet<u64> = storage.data().lists.iter().map(|e| e.list.id).collect();
+
+    // We only know the owner of our own lists, the archive has nothing but
+    // the url for the others. A crawl replaces them with the actual lists
+    let owner = match kind {
+        ListKind::Created => storage.data().profile.clone(),
+        _ => crate::helpers::placeholder_profile(0, "Unknown Owner", ""),
+    };
+
+    let mut new_lists = Vec::new();
+    for container in decoded.into_iter() {
+        let url = container.user_list_info.url;
+        let Some(id) = list_id_from_url(&url) else {
+            warn!("Can't import list {url} without an id");
+            continue
+        };
+        if !known_ids.insert(id) {
+            continue;
+        }
+        let list = crate::helpers::placeholder_list(id, owner.clone());
+        new_lists.push(List {
+            name: list.name.clone(),
+            list,
+            members: Vec::new(),
+            kind,
+        });
+    }
+
+    report(
+        format!("imported {} new lists from {file}", new_lists.len()),
+        message_sender,
+    )
+    .await;
+
+    storage.data_mut().lists.append(&mut new_lists);
+
+    Ok(())
+}
+
+/// `https://twitter.com/i/lists/{id}`. Older archives use
+/// `https://twitter.com/{screen_name}/lists/{slug}` which has no id
+fn list_id_from_url(url: &str) -> Option<u64> {
+    let (_, id) = url.split_once("/i/lists/")?;
+    id.trim_end_matches('/').parse().ok()
+}
+
+/// Conversations are merged by their id, messages by their id.
+/// The media of the messages is copied from the archive.
+async fn import_conversations(
+    archive: &mut Archive,
+    file: &str,
+    media_folder: &str,
+    shared_storage: Arc<Mutex<Storage>>,
+    message_sender: &Sender<Message>,
+) -> Result<()> {
+    report(format!("Importing {file}"), message_sender).await;
+    let Some(decoded) = archive.read_data_file::<ConversationContainer>(file)? else {
+        return Ok(())
+    };
+
+    let mut storage = shared_storage.lock().await;
+    let mut new_messages = 0;
+    let mut missing_media = 0;
+
+    for container in decoded.into_iter() {
+        let archived = container.dm_conversation;
+        let position = match storage
+            .data()
+            .conversations
+            .iter()
+            .position(|c| c.id == archived.conversation_id)
+        {
+            Some(n) => n,
+            None => {
+                storage.data_mut().conversations.push(Conversation {
+                    id: archived.conversation_id.clone(),
+                    name: None,
+                    participants: Vec::new(),
+                    messages: Vec::new(),
+                });
+                storage.data().conversations.len() - 1
+            }
+        };
+
+        let mut participants = Vec::new();
+        let mut messages = Vec::new();
+        let mut name = None;
+        for event in archived.messages {
+            if let Some(join) = event.participants_join {
+                participants.extend(join.user_ids);
+            }
+            if let Some(update) = event.conversation_name_update {
+                name = Some(update.name);
+            }
+            let Some(message) = event.message_create else {
+                continue
+            };
+            participants.push(message.sender_id);
+            participants.extend(message.recipient_id);
+            for url in &message.media_urls {
+                if !storage.data().media.contains_key(url)
+                    && !import_media_file(&mut storage, archive, media_folder, message.id, url)?
+                {
+                    missing_media += 1;
+                }
+            }
+            messages.push(DirectMessage {
+                id: message.id,
+                sender_id: message.sender_id,
+                recipient_id: message.recipient_id,
+                created_at: message.created_at,
+                text: message.text,
+                media: message.media_urls,
+            });
+        }
+
+        let data = storage.data_mut();
+        insert_placeholder_profiles(&mut data.profiles, &participants);
+
+        let conversation = &mut data.conversations[position];
+        if name.is_some() {
+            conversation.name = name;
+        }
+        append_unknown_ids(&mut conversation.participants, &participants);
+        let known_ids: HashSet<u64> = conversation.messages.iter().map(|m| m.id).collect();
+        for message in messages {
+            if !known_ids.contains(&message.id) {
+                conversation.messages.push(message);
+                new_messages += 1;
+            }
+        }
+        conversation.messages.sort_by(|a, b| b.id.cmp(&a.id));
+    }
+
+    report(
+        format!(
+            "imported {new_messages} new messages from {file}, {missing_media} media files missing"
+        ),
+        message_sender,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Copies the media of a tweet from the archive. Returns the number of
+/// copied and the number of missing files
+fn import_tweet_media(
+    tweet: &Tweet,
+    archive: &mut Archive,
+    media_folder: &str,
+    storage: &mut Storage,
+) -> (usize, usize) {
+    let Some(media) = crate::helpers::media_in_tweet(tweet) else {
+        return (0, 0)
+    };
+    let (mut copied, mut missing) = (0, 0);
+    for entry in media {
+        let url = match entry {
+            DownloadInstruction::Image(url) => url,
+            DownloadInstruction::Movie(_, url) => url,
+            _ => continue,
+        };
+        if storage.data().media.contains_key(&url) {
+            continue;
+        }
+        match import_media_file(storage, archive, media_folder, tweet.id, &url) {
+            Ok(true) => copied += 1,
+            Ok(false) => missing += 1,
+            Err(e) => {
+                warn!("Could not copy media {url} for tweet {}: {e:?}", tweet.id);
+                missing += 1;
+            }
+        }
+    }
+    (copied, missing)
+}
+
+/// Archive media files are named `{owner_id}-{last url path segment}` where the owner
+/// is the tweet or message the media belongs to. Copies the file into the storage
+/// and registers it under `url`. Returns false if the archive doesn't contain the file.
+fn import_media_file(
+    storage: &mut Storage,
+    archive: &mut Archive,
+    media_folder: &str,
+    owner_id: u64,
+    url: &str,
+) -> Result<bool> {
+    let Some(archived) = archived_media_name(owner_id, url) else {
+        return Ok(false)
+    };
+    copy_archived_media(storage, archive, media_folder, &archived, url)
+}
+
+/// The name of the media file of `url` in the archive
+fn archived_media_name(owner_id: u64, url: &str) -> Option<String> {
+    let segment = url::Url::parse(url)
+        .ok()?
+        .path_segments()?
+        .last()?
+        .to_string();
+    Some(format!("{owner_id}-{segment}"))
+}
+
+/// Copy the archived file into the storage and register it under `url`.
+/// Returns false if the archive doesn't contain the file
+fn copy_archived_media(
+    storage: &mut Storage,
+    archive: &mut Archive,
+    media_folder: &str,
+    archived: &str,
+    url: &str,
+) -> Result<bool> {
+    let mut writer = storage.media_writer()?;
+    if !archive.copy_media(media_folder, archived, &mut writer)? {
+        return Ok(false);
+    }
+    let extension = match archived.rsplit_once('.') {
+        Some((_, extension)) => extension.to_string(),
+        None => crate::crawler::extension_for_url(url),
+    };
+    let mime = crate::media::mime_for_extension(&extension);
+    storage.store_media(url, writer, &extension, mime)?;
+    Ok(true)
+}
+
+/// Log `message` and show it in the UI
+async fn report(message: String, sender: &Sender<Message>) {
+    info!("{message}");
+    crate::crawler::msg(&message, sender).await;
 }
 
 #[derive(Debug, Deserialize)]
 struct TweetContainer<'a> {
-    #[serde(bound = "'de: 'a")]
     tweet: ArchiveTweet<'a>,
 }
 
+#[derive(Debug, Deserialize)]
+struct LikeContainer {
+    like: ArchiveLike,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveLike {
+    #[serde(deserialize_with = "deserialize_u64")]
+    tweet_id: u64,
+    full_text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountContainer {
+    account: ArchiveAccountInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveAccountInfo {
+    #[serde(deserialize_with = "deserialize_u64")]
+    account_id: u64,
+    username: String,
+    account_display_name: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileContainer {
+    profile: ArchiveProfile,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveProfile {
+    description: ArchiveProfileDescription,
+    avatar_media_url: Option<String>,
+    header_media_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveProfileDescription {
+    bio: Option<String>,
+    website: Option<String>,
+    location: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListContainer {
+    user_list_info: ArchiveListInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveListInfo {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FollowerContainer {
+    follower: ArchiveAccount,
+}
+
+#[derive(Debug, Deserialize)]
+struct FollowingContainer {
+    following: ArchiveAccount,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveAccount {
+    #[serde(deserialize_with = "deserialize_u64")]
+    account_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConversationContainer {
+    dm_conversation: ArchiveConversation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveConversation {
+    conversation_id: String,
+    messages: Vec<ArchiveMessageEvent>,
+}
+
+/// Each event only has one of these fields set
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveMessageEvent {
+    message_create: Option<ArchiveMessage>,
+    participants_join: Option<ArchiveParticipantsJoin>,
+    conversation_name_update: Option<ArchiveConversationName>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveMessage {
+    #[serde(deserialize_with = "deserialize_u64")]
+    id: u64,
+    #[serde(deserialize_with = "deserialize_u64")]
+    sender_id: u64,
+    #[serde(default, deserialize_with = "deserialize_ou64")]
+    recipient_id: Option<u64>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    media_urls: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveParticipantsJoin {
+    #[serde(deserialize_with = "deserialize_u64s")]
+    user_ids: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveConversationName {
+    name: String,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 struct ArchiveTweet<'a> {
     source: Option<Cow<'a, str>>,
-    #[serde(borrow)]
     entities: Entity<'a>,
     #[serde(default, deserialize_with = "deserialize_orange")]
     display_text_range: Option<(usize, usize)>,
@@ -152,11 +1048,32 @@ struct ArchiveTweet<'a> {
     #[serde(default, deserialize_with = "deserialize_ou64")]
     in_reply_to_user_id: Option<u64>,
     quoted_status_id: Option<u64>,
+    /// Only in `deleted-tweets.js`
+    #[serde(default)]
+    deleted_at: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteTweetContainer {
+    note_tweet: ArchiveNoteTweet,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveNoteTweet {
+    note_tweet_id: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    core: ArchiveNoteTweetCore,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveNoteTweetCore {
+    text: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct Entity<'a> {
-    #[serde(borrow)]
     hashtags: Vec<ArchiveHashtag<'a>>,
     user_mentions: Vec<ArchiveMention<'a>>,
     urls: Vec<ArchiveUrl<'a>>,
@@ -290,6 +1207,50 @@ impl<'a> TryFrom<ArchiveTweet<'a>> for Tweet {
     }
 }
 
+impl From<ArchiveLike> for Tweet {
+    fn from(value: ArchiveLike) -> Self {
+        // We don't know the author of a liked tweet. Without a screen name,
+        // the tweet is linked by its id, see `helpers::tweet_url`
+        let author = crate::helpers::placeholder_profile(0, "Unknown Author", "");
+        Tweet {
+            coordinates: None,
+            created_at: crate::helpers::date_from_snowflake(value.tweet_id),
+            current_user_retweet: None,
+            display_text_range: None,
+            entities: TweetEntities {
+                hashtags: Vec::new(),
+                symbols: Vec::new(),
+                urls: Vec::new(),
+                user_mentions: Vec::new(),
+                media: None,
+            },
+            extended_entities: None,
+            favorite_count: 0,
+            favorited: Some(true),
+            filter_level: None,
+            id: value.tweet_id,
+            in_reply_to_user_id: None,
+            in_reply_to_screen_name: None,
+            in_reply_to_status_id: None,
+            lang: None,
+            place: None,
+            possibly_sensitive: None,
+            quoted_status_id: None,
+            quoted_status: None,
+            retweet_count: 0,
+            retweeted: None,
+            retweeted_status: None,
+            source: None,
+            text: value.full_text.unwrap_or_default(),
+            truncated: false,
+            user: Some(Box::new(author)),
+            withheld_copyright: false,
+            withheld_in_countries: None,
+            withheld_scope: None,
+        }
+    }
+}
+
 fn parse_date(date: &str) -> Result<chrono::DateTime<chrono::Utc>> {
     // "Wed Nov 23 08:23:27 +0000 2022"
     use chrono::{DateTime, Utc};
@@ -461,6 +1422,16 @@ where
     Ok(number)
 }
 
+fn deserialize_u64s<'de, D>(deserializer: D) -> Result<Vec<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let data: Vec<String> = Vec::deserialize(deserializer)?;
+    data.iter()
+        .map(|e| e.parse::<u64>().map_err(serde::de::Error::custom))
+        .collect()
+}
+
 fn deserialize_ou64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
 where
     D: Deserializer<'de>,
@@ -476,7 +1447,7 @@ fn deserialize_range<'de, D>(deserializer: D) -> Result<(usize, usize), D::Error
 where
     D: Deserializer<'de>,
 {
-    let data: Vec<&'de str> = Vec::deserialize(deserializer)?;
+    let data: Vec<String> = Vec::deserialize(deserializer)?;
     let start = data[0].parse::<usize>().map_err(serde::de::Error::custom)?;
     let end = data[1].parse::<usize>().map_err(serde::de::Error::custom)?;
     Ok((start, end))
@@ -486,7 +1457,7 @@ fn deserialize_orange<'de, D>(deserializer: D) -> Result<Option<(usize, usize)>,
 where
     D: Deserializer<'de>,
 {
-    let Some(data) = Vec::<&'de str>::deserialize(deserializer).ok() else {
+    let Some(data) = Vec::<String>::deserialize(deserializer).ok() else {
         return Ok(None)
     };
     let start = data[0].parse::<usize>().map_err(serde::de::Error::custom)?;
@@ -1,26 +1,40 @@
+mod api;
+mod archive;
+mod backend;
 mod config;
 mod crawler;
+mod crypto;
 mod helpers;
 mod importer;
+mod media;
+mod merge;
 mod search;
 mod storage;
+mod tweet_index;
 mod types;
 mod ui;
 
 use clap::{ArgMatches, Command};
 use eyre::{bail, Result};
 use tokio::{
-    sync::mpsc::{channel, Receiver},
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
     task::JoinHandle,
 };
 use tracing::{info, warn};
 
+use backend::BackendKind;
 use config::Config;
+use crawler::MediaSource;
+use crypto::Vault;
 use storage::Storage;
 
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
 };
 
 use crate::types::Message;
@@ -32,7 +46,7 @@ async fn main() -> Result<()> {
 
     // check if we have a path to a custom storage
     let raw_args: Vec<_> = std::env::args().collect();
-    let (config, storage, storage_path) =
+    let (config, storage, storage_path, vault) =
         match (raw_args.get(1).map(|e| e.as_str()), raw_args.get(2)) {
             (Some("--custom-archive"), Some(custom)) => {
                 let custom_path = PathBuf::from_str(custom)?;
@@ -41,9 +55,10 @@ async fn main() -> Result<()> {
                         .expect("Expect to be able to create the data directory");
                 }
                 println!("Try opening Storage: {}", &custom_path.display());
-                let config = config::Config::open(Some(custom_path.clone())).ok();
-                let storage = Storage::open(custom_path.clone());
-                (config, storage, custom_path)
+                let vault = unlock_in_terminal(&custom_path, raw_args.len() > 3)?;
+                let config = config::Config::open(Some(custom_path.clone()), vault.clone()).ok();
+                let storage = Storage::open(custom_path.clone(), vault.clone());
+                (config, storage, custom_path, vault)
             }
             _ => {
                 let storage_path = config::Config::storage_path(None);
@@ -52,12 +67,22 @@ async fn main() -> Result<()> {
                         .expect("Expect to be able to create the data directory");
                 }
                 println!("Try opening Storage: {}", storage_path.display());
-                let config = config::Config::open(None).ok();
-                let storage = Storage::open(&storage_path);
-                (config, storage, storage_path)
+                let vault = unlock_in_terminal(&storage_path, raw_args.len() > 1)?;
+                let config = config::Config::open(None, vault.clone()).ok();
+                let storage = Storage::open(&storage_path, vault.clone());
+                (config, storage, storage_path, vault)
             }
         };
 
+    // A storage that exists but can't be opened (e.g. because it is damaged or
+    // was written by a newer TwitVault) must not be treated as missing, crawling
+    // or importing would overwrite it. Only a locked storage is unlocked by the UI
+    let is_locked = vault.is_none() && Vault::exists(&storage_path);
+    let storage = match storage {
+        Err(e) if Storage::exists(&storage_path) && !is_locked => return Err(e),
+        other => other,
+    };
+
     let cmd = match &storage {
         Ok(existing) => clap::Command::new(name)
             .bin_name(name)
@@ -71,12 +96,55 @@ async fn main() -> Result<()> {
             .help("Absolute path to a different archive folder")
             .required(false))
             .subcommand_required(false)
-            .subcommand(Command::new("sync"))
             .subcommand(
-                Command::new("import")
-                    .arg(clap::Arg::new("archive-path").required(true).short('c')),
+                Command::new("sync")
+                    .arg(
+                        clap::Arg::new("check-deleted")
+                            .long("check-deleted")
+                            .help("Look up all archived tweets to find the ones that were deleted on Twitter")
+                            .action(clap::ArgAction::SetTrue),
+                    )
+                    .arg(fixtures_arg())
+                    .arg(api_arg())
+                    .arg(threads_arg()),
+            )
+            .subcommand(import_command())
+            .subcommand(Command::new("inspect"))
+            .subcommand(
+                Command::new("migrate-sqlite")
+                    .about("Move the storage from the JSON file into a SQLite database"),
+            )
+            .subcommand(
+                Command::new("compress")
+                    .about("Compress the data file of the storage with zstd"),
+            )
+            .subcommand(
+                Command::new("verify")
+                    .about("Check all media files for missing or corrupted files"),
+            )
+            .subcommand(
+                Command::new("maintenance")
+                    .about("Remove unused media files and forget media files that don't exist anymore")
+                    .arg(
+                        clap::Arg::new("redownload")
+                            .long("redownload")
+                            .help("Download the media files that don't exist anymore again")
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(
+                Command::new("encrypt")
+                    .about("Encrypt the storage and the login with a passphrase"),
             )
-            .subcommand(Command::new("inspect")),
+            .subcommand(
+                Command::new("merge")
+                    .about("Add everything from another TwitVault archive of the same account")
+                    .arg(
+                        clap::Arg::new("other-archive")
+                            .help("The folder of the other archive")
+                            .required(true),
+                    ),
+            ),
         Err(_) => clap::Command::new(name)
             .bin_name(name)
             .after_help(format!(
@@ -92,46 +160,166 @@ async fn main() -> Result<()> {
                 Command::new("crawl")
                     .arg(clap::Arg::new("custom-user")
                     .help("Don't crawl the data of the authenticated user, but instead of the given custom-user which is the Twitter user id such as 6473172. You can find the id for a user via this website: https://tweeterid.com")
-                    .required(false).short('u')),
-            ),
+                    .required(false).short('u'))
+                    .arg(fixtures_arg())
+                    .arg(api_arg())
+                    .arg(threads_arg()),
+            )
+            .subcommand(import_command()),
     };
 
     let matches = cmd.get_matches();
     match (matches.subcommand(), storage, config) {
-        // Try to crawl with a pre-defined config
-        (Some(("crawl", custom)), Err(_), Some(config)) => {
-            action_crawl(&config, &storage_path, custom).await?
-        }
-        // If there's no config, perform the login dance in the terminal, then crawl
-        (Some(("crawl", custom)), Err(_), None) => {
-            let config = Config::load(Some(storage_path.clone()))
-                .await
-                .expect("Could not create config");
+        (Some(("crawl", custom)), Err(_), config) => {
+            let fixtures = fixtures_config(custom, &storage_path, vault.clone())?;
+            let config = match (fixtures, config) {
+                // Crawl the recorded responses instead of Twitter
+                (Some(fixtures), _) => fixtures,
+                // Try to crawl with a pre-defined config
+                (None, Some(config)) => config,
+                // If there's no config, perform the login dance in the terminal, then crawl
+                (None, None) => Config::load(Some(storage_path.clone()), vault)
+                    .await
+                    .expect("Could not create config"),
+            };
             action_crawl(&config, &storage_path, custom).await?
         }
         // Import a Twitter archive
-        (Some(("import", archive)), Ok(storage), Some(config)) => {
-            action_import(&config, storage, archive).await?
+        (Some(("import", archive)), Ok(storage), config) => {
+            action_import(config.as_ref(), Some(storage), &storage_path, archive).await?
+        }
+        // Create a new storage from a Twitter archive. Doesn't need a login
+        (Some(("import", archive)), Err(_), config) => {
+            action_import(config.as_ref(), None, &storage_path, archive).await?
         }
         // For an existing storage, inspect it
         (Some(("inspect", _)), Ok(storage), _) => action_inspect(&storage).await?,
+        // Move an existing storage into SQLite
+        (Some(("migrate-sqlite", _)), Ok(storage), _) => action_migrate_sqlite(storage).await?,
+        // Compress the data file of an existing storage
+        (Some(("compress", _)), Ok(storage), _) => action_compress(storage).await?,
+        // Check the media files of an existing storage
+        (Some(("verify", _)), Ok(storage), _) => action_verify(storage).await?,
+        // Clean up the media folder of an existing storage
+        (Some(("maintenance", options)), Ok(storage), _) => {
+            action_maintenance(storage, options).await?
+        }
+        // Encrypt an existing storage
+        (Some(("encrypt", _)), Ok(storage), config) => action_encrypt(storage, config).await?,
+        // Merge another storage into an existing storage
+        (Some(("merge", other)), Ok(storage), _) => action_merge(storage, other).await?,
         // For an existing storage, sync it
-        (Some(("sync", _)), Ok(storage), Some(config)) => action_sync(&config, storage).await?,
+        (Some(("sync", options)), Ok(storage), config) => {
+            match fixtures_config(options, &storage_path, vault)?.or(config) {
+                Some(config) => action_sync(&config, storage, options).await?,
+                None => action_ui(Some(storage), None, storage_path).await?,
+            }
+        }
         // In all other cases, show the UI
         (_, optional_storage, optional_config) => {
-            action_ui(optional_storage.ok(), optional_config).await?
+            action_ui(optional_storage.ok(), optional_config, storage_path).await?
         }
     };
 
     Ok(())
 }
 
-async fn action_import(config: &Config, storage: Storage, matches: &ArgMatches) -> Result<()> {
-    let Some(path) = matches.get_one::<String>("archive-path") else {
+fn import_command() -> Command {
+    Command::new("import").arg(
+        clap::Arg::new("archive-path")
+            .help("The archive folder, the archive zip file or all parts of a split archive")
+            .required(true)
+            .num_args(1..)
+            .short('c'),
+    )
+}
+
+fn fixtures_arg() -> clap::Arg {
+    clap::Arg::new("fixtures")
+        .long("fixtures")
+        .help("Don't call Twitter, but use the recorded responses in the given folder")
+        .required(false)
+}
+
+fn api_arg() -> clap::Arg {
+    clap::Arg::new("api")
+        .long("api")
+        .help("The Twitter API to crawl with: v1 or v2. The choice is remembered for later syncs")
+        .value_parser(["v1", "v2"])
+        .conflicts_with("fixtures")
+        .required(false)
+}
+
+fn threads_arg() -> clap::Arg {
+    clap::Arg::new("threads")
+        .long("threads")
+        .value_name("DEPTH")
+        .help("Reconstruct the threads of the tweets and mentions, with up to DEPTH levels of replies")
+        .value_parser(clap::value_parser!(usize))
+        .required(false)
+}
+
+/// Crawl the threads with the depth from `--threads`, if it was given
+fn apply_threads(config: &mut Config, matches: &ArgMatches) {
+    let Some(depth) = matches.get_one::<usize>("threads") else {
+        return
+    };
+    let options = config.crawl_options().changed(|o| {
+        o.threads = true;
+        o.thread_depth = *depth;
+    });
+    config.set_crawl_options(&options);
+}
+
+/// Switch to the API from `--api`, if it was given
+fn apply_api_version(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let Some(version) = matches.get_one::<String>("api") else {
+        return Ok(())
+    };
+    config.set_api_version(version.parse()?)?;
+    info!("Crawling with the Twitter API {version}");
+    Ok(())
+}
+
+/// The config for `--fixtures`, if it was given
+fn fixtures_config(
+    matches: &ArgMatches,
+    storage_path: &Path,
+    vault: Option<Vault>,
+) -> Result<Option<Config>> {
+    let Some(fixtures) = matches.get_one::<String>("fixtures") else {
+        return Ok(None)
+    };
+    let config =
+        Config::for_fixtures(Some(storage_path.to_path_buf()), vault, Path::new(fixtures))?;
+    Ok(Some(config))
+}
+
+fn archive_paths(matches: &ArgMatches) -> Result<Vec<PathBuf>> {
+    let Some(paths) = matches.get_many::<String>("archive-path") else {
         bail!("Missing parameter --archive-path [...]")
     };
-    let storage = importer::import_archive(storage, config, path).await?;
-    storage.save()?;
+    Ok(paths.map(PathBuf::from).collect())
+}
+
+async fn action_import(
+    config: Option<&Config>,
+    storage: Option<Storage>,
+    storage_path: &Path,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let paths = archive_paths(matches)?;
+    let (sender, receiver) = channel(256);
+    let logger = log_task(receiver);
+    importer::import_with_progress(
+        storage,
+        storage_path.to_path_buf(),
+        config.cloned(),
+        paths,
+        sender,
+    )
+    .await?;
+    let storage = logger.await??;
     action_inspect(&storage).await?;
     Ok(())
 }
@@ -150,8 +338,11 @@ async fn action_crawl(config: &Config, _storage_path: &Path, matches: &ArgMatche
     info!("Crawling");
     let (sender, receiver) = channel(256);
 
-    // In custom-user mode, disable responses and mentions
     let mut config = config.clone();
+    apply_api_version(&mut config, matches)?;
+    apply_threads(&mut config, matches);
+
+    // In custom-user mode, disable responses and mentions
     if user_id != config.user_id() {
         let mut options = config.crawl_options().clone();
         options.mentions = false;
@@ -168,10 +359,16 @@ async fn action_crawl(config: &Config, _storage_path: &Path, matches: &ArgMatche
     Ok(())
 }
 
-async fn action_sync(config: &Config, storage: Storage) -> Result<()> {
+async fn action_sync(config: &Config, storage: Storage, matches: &ArgMatches) -> Result<()> {
     info!("Syncing");
     let mut config = config.clone();
+    apply_api_version(&mut config, matches)?;
+    apply_threads(&mut config, matches);
     config.is_sync = true;
+    if matches.get_flag("check-deleted") {
+        let options = config.crawl_options().changed(|o| o.check_deleted = true);
+        config.set_crawl_options(&options);
+    }
     let (sender, receiver) = channel(256);
     crawler::crawl_into_storage(config.user_id(), config.clone(), storage, sender).await?;
     let storage = log_task(receiver).await??;
@@ -202,7 +399,159 @@ fn log_task(mut receiver: Receiver<Message>) -> JoinHandle<Result<Storage>> {
     })
 }
 
+async fn action_migrate_sqlite(mut storage: Storage) -> Result<()> {
+    if storage.backend_kind() == BackendKind::Sqlite {
+        bail!("The storage already uses SQLite")
+    }
+    info!("Migrating storage to SQLite");
+    storage.migrate_to_sqlite()?;
+    println!(
+        "Migrated the storage to SQLite. The old data file in {} is kept as a backup",
+        storage.root_folder.display()
+    );
+    action_inspect(&storage).await?;
+    Ok(())
+}
+
+async fn action_compress(mut storage: Storage) -> Result<()> {
+    if storage.compressed() {
+        bail!("The storage is already compressed")
+    }
+    info!("Compressing storage");
+    storage.compress()?;
+    println!(
+        "Compressed the data file in {}",
+        storage.root_folder.display()
+    );
+    Ok(())
+}
+
+async fn action_verify(mut storage: Storage) -> Result<()> {
+    info!("Verifying {} media files", storage.data().media.len());
+    let report = media::verify(&mut storage)?;
+    if report.unverified > 0 || report.renamed > 0 {
+        storage.save()?;
+    }
+    for path in report.replaced_files.iter() {
+        std::fs::remove_file(path)?;
+    }
+    for url in report.missing.iter() {
+        println!("missing: {url}");
+    }
+    for url in report.corrupted.iter() {
+        println!("corrupted: {url}");
+    }
+    println!("verified: {}", report.verified);
+    println!("hashed for the first time: {}", report.unverified);
+    println!("renamed to their hash: {}", report.renamed);
+    println!("missing: {}", report.missing.len());
+    println!("corrupted: {}", report.corrupted.len());
+    Ok(())
+}
+
+async fn action_maintenance(mut storage: Storage, matches: &ArgMatches) -> Result<()> {
+    let report = media::collect_garbage(&mut storage)?;
+    for name in report.removed_files.iter() {
+        println!("removed file: {name}");
+    }
+    for (url, _) in report.dangling.iter() {
+        println!("missing file: {url}");
+    }
+    println!("removed files: {}", report.removed_files.len());
+    println!("forgotten media: {}", report.dangling.len());
+
+    if matches.get_flag("redownload") && !report.dangling.is_empty() {
+        info!("Downloading {} media files again", report.dangling.len());
+        let before = storage.data().media.len();
+        let shared_storage = Arc::new(Mutex::new(storage));
+        let (instruction_task, instruction_sender) =
+            crawler::create_instruction_handler(true, MediaSource::Twitter, shared_storage.clone());
+        for (url, entry) in report.dangling {
+            let instruction = media::download_instruction(url, &entry);
+            instruction_sender.send(instruction).await?;
+        }
+        instruction_sender
+            .send(crawler::DownloadInstruction::Done)
+            .await?;
+        instruction_task.await?;
+        storage = shared_storage.lock_owned().await.clone();
+        println!("downloaded again: {}", storage.data().media.len() - before);
+    }
+
+    storage.save()?;
+    Ok(())
+}
+
+async fn action_merge(mut storage: Storage, matches: &ArgMatches) -> Result<()> {
+    let Some(other_path) = matches.get_one::<String>("other-archive") else {
+        bail!("Missing parameter other-archive")
+    };
+    let other_vault = unlock_in_terminal(Path::new(other_path), true)?;
+    let other = Storage::open(other_path, other_vault)?;
+    info!(
+        "Merging {other_path} into {}",
+        storage.root_folder.display()
+    );
+    let counts = merge::merge(&mut storage, &other)?;
+    storage.save()?;
+    for count in counts {
+        println!(
+            "{}: {} kept, {} added from {other_path}, {} replaced by {other_path}",
+            count.name, count.kept, count.added, count.replaced
+        );
+    }
+    Ok(())
+}
+
+async fn action_encrypt(mut storage: Storage, config: Option<Config>) -> Result<()> {
+    if storage.backend_kind() != BackendKind::Json {
+        bail!("Only storages that aren't stored in SQLite can be encrypted")
+    }
+    let vault = match storage.vault() {
+        // An interrupted encryption is finished with the same passphrase
+        Some(vault) if vault.is_pending() => vault.clone(),
+        Some(_) => bail!("The storage is already encrypted"),
+        None => {
+            println!(
+                "Please choose a passphrase. Without it, the storage can't be opened anymore."
+            );
+            let passphrase = crypto::prompt_passphrase(true)?;
+            Vault::create(&storage.root_folder, &passphrase)?
+        }
+    };
+    info!("Encrypting the storage");
+    storage.encrypt(vault.clone())?;
+    match config {
+        Some(mut config) => config.encrypt(vault.clone())?,
+        // The settings files can exist even if they couldn't be opened
+        None => Config::encrypt_files(Some(storage.root_folder.clone()), &vault)?,
+    }
+    vault.complete(&storage.root_folder)?;
+    println!(
+        "The storage in {} is now encrypted",
+        storage.root_folder.display()
+    );
+    Ok(())
+}
+
+/// Subcommands unlock an encrypted storage in the terminal. Without a
+/// subcommand, the UI asks for the passphrase instead
+fn unlock_in_terminal(storage_path: &Path, has_subcommand: bool) -> Result<Option<Vault>> {
+    if !has_subcommand || !Vault::exists(storage_path) {
+        return Ok(None);
+    }
+    println!("The storage at {} is encrypted", storage_path.display());
+    let passphrase = crypto::prompt_passphrase(false)?;
+    let vault = Vault::unlock(storage_path, &passphrase)?;
+    if vault.is_pending() {
+        println!("The encryption was interrupted, run `encrypt` again to finish it");
+    }
+    Ok(Some(vault))
+}
+
 async fn action_inspect(storage: &Storage) -> Result<()> {
+    println!("backend: {}", storage.backend_kind());
+    println!("compressed: {}", storage.compressed());
     println!("tweets: {}", storage.data().tweets.len());
     println!("mentions: {}", storage.data().mentions.len());
     println!("responses: {}", storage.data().responses.len());
@@ -214,11 +563,17 @@ async fn action_inspect(storage: &Storage) -> Result<()> {
         println!(" {} members: {}", list.name, list.members.len());
     }
     println!("media: {}", storage.data().media.len());
+    println!("conversations: {}", storage.data().conversations.len());
+    println!("threads: {}", storage.data().threads.len());
     Ok(())
 }
 
-async fn action_ui(storage: Option<Storage>, config: Option<Config>) -> Result<()> {
-    ui::run_ui(storage, config);
+async fn action_ui(
+    storage: Option<Storage>,
+    config: Option<Config>,
+    storage_path: PathBuf,
+) -> Result<()> {
+    ui::run_ui(storage, config, storage_path);
     Ok(())
 }
 
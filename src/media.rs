@@ -0,0 +1,293 @@
+//! Media files are stored in the media folder under the SHA-256 of their
+//! content (`{sha256}.{extension}`). In encrypted storages, the name is
+//! keyed with the vault, see `Vault::media_name`. `Data::media` maps the
+//! original url to a `MediaEntry` which also records size, mime type and hash.
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+use crate::crawler::DownloadInstruction;
+use crate::crypto::{self, ChunkedEncryption, Vault};
+use crate::storage::{Storage, UrlString};
+
+/// Unfinished files in the media folder end with this
+pub const TEMP_SUFFIX: &str = ".tmp";
+
+/// A file in the media folder
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MediaEntry {
+    /// The name of the file in the media folder
+    pub file: String,
+    /// Size in bytes. Unknown for media stored before hashing until `verify` ran
+    pub size: Option<u64>,
+    pub mime: Option<String>,
+    /// Hex encoded SHA-256 of the content. Unknown for media stored before
+    /// hashing until `verify` ran
+    pub sha256: Option<String>,
+}
+
+/// Writes a new media file into a temporary file in the media folder and
+/// hashes it on the way. If it is dropped before `finish`, the temporary
+/// file is removed again. For encrypted storages, the content is encrypted
+/// in chunks while it is written.
+pub struct MediaWriter {
+    folder: PathBuf,
+    file: NamedTempFile,
+    encryption: Option<ChunkedEncryption>,
+    vault: Option<Vault>,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl MediaWriter {
+    pub fn new(folder: &Path, vault: Option<Vault>) -> Result<Self> {
+        let mut file = tempfile::Builder::new()
+            .suffix(TEMP_SUFFIX)
+            .tempfile_in(folder)?;
+        let encryption = match vault.clone() {
+            Some(vault) => Some(ChunkedEncryption::new(vault, &mut file)?),
+            None => None,
+        };
+        Ok(Self {
+            folder: folder.to_path_buf(),
+            file,
+            encryption,
+            vault,
+            hasher: Sha256::new(),
+            size: 0,
+        })
+    }
+
+    /// Move the file to its final name. If a file with the same content
+    /// already exists, it is reused
+    pub fn finish(mut self, extension: &str, mime: Option<String>) -> Result<MediaEntry> {
+        if let Some(encryption) = self.encryption.take() {
+            encryption.finish(&mut self.file)?;
+        }
+        self.file.flush()?;
+        let sha256 = format!("{:x}", self.hasher.finalize());
+        let file_name = media_file_name(&sha256, extension, self.vault.as_ref());
+        let destination = self.folder.join(&file_name);
+        if !destination.exists() {
+            self.file.persist(&destination)?;
+        }
+        Ok(MediaEntry {
+            file: file_name,
+            size: Some(self.size),
+            mime,
+            sha256: Some(sha256),
+        })
+    }
+}
+
+impl Write for MediaWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = match self.encryption.as_mut() {
+            Some(encryption) => {
+                encryption
+                    .update(buf, &mut self.file)
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                buf.len()
+            }
+            None => self.file.write(buf)?,
+        };
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// The name of a media file with the given content hash
+pub fn media_file_name(sha256: &str, extension: &str, vault: Option<&Vault>) -> String {
+    let name = match vault {
+        Some(vault) => vault.media_name(sha256),
+        None => sha256.to_string(),
+    };
+    match extension.is_empty() {
+        true => name,
+        false => format!("{name}.{extension}"),
+    }
+}
+
+/// The SHA-256 and the size of a file. Encrypted files are hashed decrypted
+pub fn hash_file(path: &Path, vault: Option<&Vault>) -> Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = match vault {
+        Some(vault) => {
+            let content = crypto::read_file(path, Some(vault))?;
+            hasher.update(&content);
+            content.len() as u64
+        }
+        None => std::io::copy(&mut File::open(path)?, &mut hasher)?,
+    };
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+/// The mime type of the common Twitter media extensions
+pub fn mime_for_extension(extension: &str) -> Option<String> {
+    let mime = match extension.to_lowercase().as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "avi" => "video/x-msvideo",
+        "3gp" => "video/3gpp",
+        _ => return None,
+    };
+    Some(mime.to_string())
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Entries with a matching file
+    pub verified: usize,
+    /// Entries without a hash so far. Their hash was recorded now
+    pub unverified: usize,
+    /// Entries whose file was not named after its hash yet. They refer to a
+    /// file named after the hash now
+    pub renamed: usize,
+    /// The files of the renamed entries under their old name. They can be
+    /// removed once the data is saved
+    pub replaced_files: Vec<PathBuf>,
+    /// Entries whose file doesn't exist
+    pub missing: Vec<UrlString>,
+    /// Entries whose file doesn't match the recorded hash or size
+    pub corrupted: Vec<UrlString>,
+}
+
+/// Re-hash every media file and compare it with `Data::media`.
+/// Media stored before hashing gets its hash and size recorded, and files
+/// that aren't named after their hash yet (see `media_file_name`) get
+/// a link with that name. The old files are listed in the report.
+pub fn verify(storage: &mut Storage) -> Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut recorded = Vec::new();
+    for (url, entry) in storage.data().media.iter() {
+        let path = storage.media_path(&entry.file);
+        if !path.exists() {
+            report.missing.push(url.clone());
+            continue;
+        }
+        let (sha256, size) = hash_file(&path, storage.vault())?;
+        match entry.sha256.as_ref() {
+            None => {
+                report.unverified += 1;
+                recorded.push((url.clone(), sha256, size));
+            }
+            Some(expected) if expected != &sha256 || entry.size != Some(size) => {
+                report.corrupted.push(url.clone())
+            }
+            Some(_) => {
+                report.verified += 1;
+                recorded.push((url.clone(), sha256, size));
+            }
+        }
+    }
+
+    let mut replaced_files = HashSet::new();
+    for (url, sha256, size) in recorded {
+        let Some(entry) = storage.data().media.get(&url) else {
+            continue
+        };
+        let extension = entry
+            .file
+            .rsplit_once('.')
+            .map(|(_, e)| e)
+            .unwrap_or_default();
+        let file_name = media_file_name(&sha256, extension, storage.vault());
+        if entry.file != file_name {
+            let path = storage.media_path(&entry.file);
+            let destination = storage.media_path(&file_name);
+            // Two urls with the same content share the file
+            if !destination.exists() {
+                std::fs::hard_link(&path, &destination)
+                    .or_else(|_| std::fs::copy(&path, &destination).map(|_| ()))?;
+            }
+            replaced_files.insert(path);
+            report.renamed += 1;
+        }
+        if let Some(entry) = storage.data_mut().media.get_mut(&url) {
+            entry.file = file_name;
+            entry.sha256 = Some(sha256);
+            entry.size = Some(size);
+        }
+    }
+    report.replaced_files = replaced_files.into_iter().collect();
+    Ok(report)
+}
+
+#[derive(Debug, Default)]
+pub struct MaintenanceReport {
+    /// Files in the media folder that no entry refers to, e.g. unfinished downloads
+    pub removed_files: Vec<String>,
+    /// Entries whose file doesn't exist. They were removed from `Data::media`
+    pub dangling: Vec<(UrlString, MediaEntry)>,
+}
+
+/// Remove files from the media folder that aren't referenced by `Data::media`
+/// and drop the entries whose file doesn't exist anymore.
+pub fn collect_garbage(storage: &mut Storage) -> Result<MaintenanceReport> {
+    let mut report = MaintenanceReport::default();
+
+    let referenced: HashSet<&str> = storage
+        .data()
+        .media
+        .values()
+        .map(|entry| entry.file.as_str())
+        .collect();
+    for dir_entry in std::fs::read_dir(storage.media_folder())? {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type()?.is_file() {
+            continue;
+        }
+        let Some(name) = dir_entry.file_name().to_str().map(|name| name.to_string()) else {
+            continue
+        };
+        if !referenced.contains(name.as_str()) {
+            std::fs::remove_file(dir_entry.path())?;
+            report.removed_files.push(name);
+        }
+    }
+
+    let dangling: Vec<UrlString> = storage
+        .data()
+        .media
+        .iter()
+        .filter(|(_, entry)| !storage.media_path(&entry.file).exists())
+        .map(|(url, _)| url.clone())
+        .collect();
+    let media = &mut storage.data_mut().media;
+    for url in dangling {
+        if let Some(entry) = media.remove(&url) {
+            report.dangling.push((url, entry));
+        }
+    }
+    Ok(report)
+}
+
+/// The instruction to download the media for `entry` again
+pub fn download_instruction(url: UrlString, entry: &MediaEntry) -> DownloadInstruction {
+    let movie = entry
+        .mime
+        .as_ref()
+        .and_then(|mime| mime.parse::<mime::Mime>().ok())
+        .filter(|mime| mime.type_() == mime::VIDEO);
+    match movie {
+        Some(mime) => DownloadInstruction::Movie(mime, url),
+        None => DownloadInstruction::Image(url),
+    }
+}
@@ -0,0 +1,255 @@
+//! Combine two storages of the same account, e.g. partial archives that
+//! were crawled on different machines. Everything from `other` that
+//! `storage` doesn't have yet is added to `storage`.
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use egg_mode::tweet::Tweet;
+use eyre::{bail, Result};
+use tracing::warn;
+
+use crate::helpers::{
+    append_unknown_ids, insert_unknown_tweets, is_placeholder_list, is_placeholder_profile,
+};
+use crate::storage::{Conversation, Storage, UserId};
+
+/// How many entries of a kind were already there and how many came from the other storage
+#[derive(Debug)]
+pub struct MergeCount {
+    pub name: &'static str,
+    /// Entries that were only in the storage that was merged into
+    pub kept: usize,
+    /// Entries that were only in the other storage
+    pub added: usize,
+    /// Entries in both storages where the one from the other storage won
+    pub replaced: usize,
+}
+
+impl MergeCount {
+    fn new(name: &'static str, kept: usize, added: usize) -> Self {
+        Self {
+            name,
+            kept,
+            added,
+            replaced: 0,
+        }
+    }
+}
+
+/// Merge `other` into `storage`. Tweets are unioned by id, profiles from
+/// the storage that saw them last win
+pub fn merge(storage: &mut Storage, other: &Storage) -> Result<Vec<MergeCount>> {
+    if storage.data().profile.id != other.data().profile.id {
+        bail!(
+            "The archives belong to different accounts: @{} and @{}",
+            storage.data().profile.screen_name,
+            other.data().profile.screen_name
+        )
+    }
+    let mut counts = Vec::new();
+    let theirs = other.data();
+    let media_counts = merge_media(storage, other)?;
+    let data = storage.data_mut();
+
+    let our_last_seen = data.authors_last_seen();
+    let their_last_seen = theirs.authors_last_seen();
+    let other_is_newer = |id: &UserId| their_last_seen.get(id) > our_last_seen.get(id);
+
+    if other_is_newer(&theirs.profile.id) && !is_placeholder_profile(&theirs.profile) {
+        data.profile = theirs.profile.clone();
+    }
+
+    counts.push(union_tweets("tweets", &mut data.tweets, &theirs.tweets));
+    counts.push(union_tweets(
+        "mentions",
+        &mut data.mentions,
+        &theirs.mentions,
+    ));
+    counts.push(union_tweets("likes", &mut data.likes, &theirs.likes));
+    counts.push(union_tweets(
+        "bookmarks",
+        &mut data.bookmarks,
+        &theirs.bookmarks,
+    ));
+
+    let mut responses = MergeCount::new("responses", 0, 0);
+    for (id, tweets) in theirs.responses.iter() {
+        let entry = data.responses.entry(*id).or_default();
+        let count = union_tweets("responses", entry, tweets);
+        responses.kept += count.kept;
+        responses.added += count.added;
+    }
+    responses.kept += data
+        .responses
+        .iter()
+        .filter(|(id, _)| !theirs.responses.contains_key(id))
+        .map(|(_, tweets)| tweets.len())
+        .sum::<usize>();
+    counts.push(responses);
+
+    let mut profiles = MergeCount::new("profiles", 0, 0);
+    for (id, profile) in theirs.profiles.iter() {
+        match data.profiles.get_mut(id) {
+            None => {
+                data.profiles.insert(*id, profile.clone());
+                profiles.added += 1;
+            }
+            Some(existing) => {
+                // A real profile always beats a placeholder from an archive import
+                let replace = match (
+                    is_placeholder_profile(existing),
+                    is_placeholder_profile(profile),
+                ) {
+                    (true, false) => true,
+                    (false, true) => false,
+                    _ => other_is_newer(id),
+                };
+                if replace {
+                    *existing = profile.clone();
+                    profiles.replaced += 1;
+                }
+            }
+        }
+    }
+    profiles.kept = data.profiles.len() - profiles.added - profiles.replaced;
+    counts.push(profiles);
+
+    let before = data.followers.len();
+    let added = append_unknown_ids(&mut data.followers, &theirs.followers);
+    counts.push(MergeCount::new("followers", before, added));
+    let before = data.follows.len();
+    let added = append_unknown_ids(&mut data.follows, &theirs.follows);
+    counts.push(MergeCount::new("follows", before, added));
+
+    let mut lists = MergeCount::new("lists", data.lists.len(), 0);
+    for list in theirs.lists.iter() {
+        match data.lists.iter_mut().find(|l| l.list.id == list.list.id) {
+            None => {
+                data.lists.push(list.clone());
+                lists.added += 1;
+            }
+            Some(existing) => {
+                if is_placeholder_list(&existing.list) && !is_placeholder_list(&list.list) {
+                    existing.name = list.name.clone();
+                    existing.list = list.list.clone();
+                    lists.replaced += 1;
+                    lists.kept -= 1;
+                }
+                append_unknown_ids(&mut existing.members, &list.members);
+            }
+        }
+    }
+    counts.push(lists);
+
+    counts.push(merge_conversations(
+        &mut data.conversations,
+        &theirs.conversations,
+    ));
+
+    // Threads that share a tweet are merged into one
+    let before: usize = data.threads.values().map(|t| t.tweets().len()).sum();
+    for thread in theirs.threads.values() {
+        data.insert_thread(thread.clone());
+    }
+    let after: usize = data.threads.values().map(|t| t.tweets().len()).sum();
+    counts.push(MergeCount::new("thread tweets", before, after - before));
+
+    let mut meta = MergeCount::new("tweet meta", data.tweet_meta.len(), 0);
+    for (id, theirs) in theirs.tweet_meta.iter() {
+        match data.tweet_meta.get_mut(id) {
+            None => {
+                data.tweet_meta.insert(*id, theirs.clone());
+                meta.added += 1;
+            }
+            Some(ours) => {
+                ours.deleted_at = ours.deleted_at.or(theirs.deleted_at);
+                ours.deleted_via_twitvault |= theirs.deleted_via_twitvault;
+                ours.first_seen = match (ours.first_seen, theirs.first_seen) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                // Whoever saw the tweet last knows whether it is still unavailable
+                if theirs.last_seen > ours.last_seen {
+                    ours.unavailable_since = theirs.unavailable_since;
+                }
+                ours.last_seen = ours.last_seen.max(theirs.last_seen);
+            }
+        }
+    }
+    counts.push(meta);
+
+    counts.push(media_counts);
+    data.rebuild_index();
+    Ok(counts)
+}
+
+/// Add the tweets from `theirs` that aren't in `ours`, in the order of both
+fn union_tweets(name: &'static str, ours: &mut Vec<Tweet>, theirs: &[Tweet]) -> MergeCount {
+    let kept = ours.len();
+    let added = insert_unknown_tweets(ours, theirs.to_vec());
+    MergeCount::new(name, kept, added)
+}
+
+fn merge_conversations(ours: &mut Vec<Conversation>, theirs: &[Conversation]) -> MergeCount {
+    let mut count = MergeCount::new("messages", 0, 0);
+    count.kept = ours.iter().map(|c| c.messages.len()).sum();
+    let mut index: HashMap<String, usize> = ours
+        .iter()
+        .enumerate()
+        .map(|(position, c)| (c.id.clone(), position))
+        .collect();
+    for conversation in theirs {
+        let Some(position) = index.get(&conversation.id) else {
+            count.added += conversation.messages.len();
+            index.insert(conversation.id.clone(), ours.len());
+            ours.push(conversation.clone());
+            continue
+        };
+        let existing = &mut ours[*position];
+        if existing.name.is_none() {
+            existing.name = conversation.name.clone();
+        }
+        append_unknown_ids(&mut existing.participants, &conversation.participants);
+        let known: HashSet<u64> = existing.messages.iter().map(|m| m.id).collect();
+        for message in conversation.messages.iter() {
+            if !known.contains(&message.id) {
+                existing.messages.push(message.clone());
+                count.added += 1;
+            }
+        }
+        existing.messages.sort_by(|a, b| b.id.cmp(&a.id));
+    }
+    count
+}
+
+/// Copy the media files that only exist in `other`
+fn merge_media(storage: &mut Storage, other: &Storage) -> Result<MergeCount> {
+    let mut count = MergeCount::new("media", storage.data().media.len(), 0);
+    for (url, entry) in other.data().media.iter() {
+        if storage.data().media.contains_key(url) {
+            continue;
+        }
+        let source = other.media_path(&entry.file);
+        if !source.exists() {
+            warn!("Media file {} for {url} is missing", source.display());
+            continue;
+        }
+        count.added += 1;
+        // Encrypted files have to be decrypted and encrypted again with our key
+        if storage.vault().is_some() || other.vault().is_some() {
+            let mut writer = storage.media_writer()?;
+            writer.write_all(&other.read_media(&entry.file)?)?;
+            let extension = entry.file.rsplit_once('.').map(|(_, e)| e).unwrap_or("png");
+            storage.store_media(url, writer, extension, entry.mime.clone())?;
+            continue;
+        }
+        // Files are named after their content (older ones after their url),
+        // so an existing file is the same file
+        let destination = storage.media_path(&entry.file);
+        if !destination.exists() {
+            std::fs::copy(&source, &destination)?;
+        }
+        storage.data_mut().media.insert(url.clone(), entry.clone());
+    }
+    Ok(count)
+}
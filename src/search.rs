@@ -34,6 +34,7 @@ pub struct Options {
     pub responses: bool,
     pub profiles: bool,
     pub likes: bool,
+    pub bookmarks: bool,
 }
 
 impl Options {
@@ -52,6 +53,7 @@ impl Default for Options {
             responses: true,
             profiles: true,
             likes: true,
+            bookmarks: true,
         }
     }
 }
@@ -71,6 +73,9 @@ pub fn search(term: String, data: &Data, options: Options) -> Vec<SearchResult>
     if options.likes {
         search_tweets(&regex, &data.likes, &mut results);
     }
+    if options.bookmarks {
+        search_tweets(&regex, &data.bookmarks, &mut results);
+    }
     if options.responses {
         for i in data.responses.values() {
             search_tweets(&regex, i, &mut results);
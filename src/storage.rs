@@ -1,20 +1,56 @@
+use chrono::{DateTime, Utc};
 use egg_mode::{list, tweet::Tweet, user::TwitterUser};
-use eyre::Result;
+use eyre::{bail, Result};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+use tracing::warn;
+
+use crate::backend::{
+    self, BackendKind, JsonBackend, SqliteBackend, StorageBackend, SCHEMA_VERSION,
+};
+use crate::crypto::{self, Vault};
+use crate::media::{MediaEntry, MediaWriter};
+use crate::tweet_index::{Collection, TweetIndex};
+
 /// The folder locations for the different data
 const FOLDER_MEDIA: &str = "media";
-const FILE_ROOT: &str = "_data.json";
+/// Tweets deleted with TwitVault. The UI only has a read-only copy of the
+/// storage, so deletions are recorded here and applied when the storage is opened
+const FILE_DELETIONS: &str = "_deletions.json";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct List {
     pub name: String,
     pub list: list::List,
     pub members: Vec<UserId>,
+    pub kind: ListKind,
+}
+
+/// How the account relates to a list
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ListKind {
+    /// Created by the account
+    #[default]
+    Created,
+    /// Subscribed to by the account
+    Subscribed,
+    /// The account is a member of the list
+    Member,
+}
+
+impl std::fmt::Display for ListKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListKind::Created => f.write_str("Created"),
+            ListKind::Subscribed => f.write_str("Subscribed"),
+            ListKind::Member => f.write_str("Member"),
+        }
+    }
 }
 
 impl PartialEq for List {
@@ -28,9 +64,149 @@ impl Eq for List {}
 pub type UserId = u64;
 pub type TweetId = u64;
 pub type UrlString = String;
+pub type MessageId = u64;
+
+/// A direct message conversation with one or more users
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Conversation {
+    /// `{user_id}-{user_id}` for one-on-one conversations
+    pub id: String,
+    /// Group conversations can have a name
+    pub name: Option<String>,
+    pub participants: Vec<UserId>,
+    /// Newest message first
+    pub messages: Vec<DirectMessage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirectMessage {
+    pub id: MessageId,
+    pub sender_id: UserId,
+    /// Group messages don't have a recipient
+    pub recipient_id: Option<UserId>,
+    pub created_at: DateTime<Utc>,
+    pub text: String,
+    /// The urls of the attached media, these are keys into `Data::media`
+    pub media: Vec<UrlString>,
+}
+
+/// A conversation on Twitter, see `crawler::fetch_threads`. The tweets form
+/// a tree: every tweet except the root replies to another tweet of the thread
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "StoredThread")]
+pub struct Thread {
+    /// The first tweet of the conversation, or the oldest one that could
+    /// be loaded (e.g. if a tweet in between was deleted)
+    pub root: TweetId,
+    /// All tweets of the thread, including the root
+    tweets: Vec<Tweet>,
+    /// The ids of the replies to each tweet, oldest first
+    replies: HashMap<TweetId, Vec<TweetId>>,
+    /// The position of each tweet in `tweets`
+    #[serde(skip)]
+    positions: HashMap<TweetId, usize>,
+}
+
+/// The stored fields of a `Thread`, the positions are rebuilt when it is loaded
+#[derive(Deserialize)]
+struct StoredThread {
+    root: TweetId,
+    tweets: Vec<Tweet>,
+    replies: HashMap<TweetId, Vec<TweetId>>,
+}
+
+impl From<StoredThread> for Thread {
+    fn from(stored: StoredThread) -> Self {
+        let positions = stored
+            .tweets
+            .iter()
+            .enumerate()
+            .map(|(position, tweet)| (tweet.id, position))
+            .collect();
+        Self {
+            root: stored.root,
+            tweets: stored.tweets,
+            replies: stored.replies,
+            positions,
+        }
+    }
+}
+
+impl Thread {
+    pub fn new(root: Tweet) -> Self {
+        let mut thread = Self {
+            root: root.id,
+            tweets: Vec::new(),
+            replies: HashMap::new(),
+            positions: HashMap::new(),
+        };
+        thread.insert(root);
+        thread
+    }
+
+    /// All tweets of the thread, including the root
+    pub fn tweets(&self) -> &[Tweet] {
+        &self.tweets
+    }
+
+    pub fn contains(&self, id: TweetId) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    pub fn tweet(&self, id: TweetId) -> Option<&Tweet> {
+        self.positions
+            .get(&id)
+            .and_then(|position| self.tweets.get(*position))
+    }
+
+    /// The replies to a tweet of the thread, oldest first
+    pub fn replies(&self, id: TweetId) -> impl Iterator<Item = &Tweet> {
+        self.replies
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.tweet(*id))
+    }
+
+    /// Add a tweet to the thread. Returns `false` if it was already known.
+    /// The parent of the root becomes the new root
+    pub fn insert(&mut self, tweet: Tweet) -> bool {
+        if self.contains(tweet.id) {
+            return false;
+        }
+        if let Some(parent) = tweet.in_reply_to_status_id {
+            let replies = self.replies.entry(parent).or_default();
+            replies.push(tweet.id);
+            replies.sort_unstable();
+        }
+        // Replies are always newer than the tweet they reply to
+        self.root = self.root.min(tweet.id);
+        self.positions.insert(tweet.id, self.tweets.len());
+        self.tweets.push(tweet);
+        true
+    }
+}
+
+/// Information about a tweet that isn't part of the tweet itself
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TweetMeta {
+    /// When the tweet was deleted on Twitter
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Whether the tweet was deleted with TwitVault
+    pub deleted_via_twitvault: bool,
+    /// When a crawl first found the tweet
+    pub first_seen: Option<DateTime<Utc>>,
+    /// When a crawl or a deletion check last found the tweet on Twitter
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Since when a deletion check can't see the tweet although it wasn't
+    /// deleted, e.g. because the author became protected or was suspended
+    pub unavailable_since: Option<DateTime<Utc>>,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Data {
+    /// The version of the layout of this struct, see `backend::migrations`
+    pub schema_version: u64,
     /// The profile of the owner
     pub profile: TwitterUser,
     /// The tweets of the owner
@@ -48,67 +224,201 @@ pub struct Data {
     pub follows: Vec<UserId>,
     /// Lists
     pub lists: Vec<List>,
-    /// Downloaded media with path to local file
+    /// Downloaded media with the local file
     /// - Tweet Media: ExtendedUrlString
     /// - Profiles: Various Urls
-    pub media: HashMap<UrlString, String>,
+    pub media: HashMap<UrlString, MediaEntry>,
     /// The likes the user performed
-    #[serde(default)]
     pub likes: Vec<Tweet>,
+    /// The tweets the user bookmarked
+    pub bookmarks: Vec<Tweet>,
+    /// Direct message conversations
+    pub conversations: Vec<Conversation>,
+    /// Conversations reconstructed by the thread crawler, by the id of their root
+    pub threads: HashMap<TweetId, Thread>,
+    /// Additional information for tweets in any of the collections
+    pub tweet_meta: HashMap<TweetId, TweetMeta>,
+    /// Where to find each tweet, see `rebuild_index`
+    #[serde(skip)]
+    index: TweetIndex,
 }
 
 impl Data {
+    /// A tweet from any of the collections, including quoted and retweeted tweets.
+    /// Tweets added to the collections are only found after `rebuild_index`
     pub fn any_tweet(&self, id: TweetId) -> Option<&Tweet> {
-        for tweets in [&self.tweets, &self.mentions, &self.likes] {
-            for t in tweets {
-                if t.id == id {
-                    return Some(t);
-                }
-            }
+        self.index.get(self, id)
+    }
+
+    /// When a crawl last saw a tweet of each author, which is when their
+    /// profile was current
+    pub fn authors_last_seen(&self) -> HashMap<UserId, DateTime<Utc>> {
+        let mut last_seen: HashMap<UserId, DateTime<Utc>> = HashMap::new();
+        let collections = [&self.tweets, &self.mentions, &self.likes, &self.bookmarks];
+        let tweets = collections
+            .into_iter()
+            .chain(self.responses.values())
+            .flatten();
+        for tweet in tweets {
+            let (Some(user), Some(seen)) = (
+                &tweet.user,
+                self.tweet_meta.get(&tweet.id).and_then(|m| m.last_seen),
+            ) else {
+                continue
+            };
+            let entry = last_seen.entry(user.id).or_insert(seen);
+            *entry = (*entry).max(seen);
         }
-        for tweets in self.responses.values() {
-            for t in tweets {
-                if t.id == id {
-                    return Some(t);
-                }
+        last_seen
+    }
+
+    /// The thread that contains the tweet
+    pub fn thread(&self, id: TweetId) -> Option<&Thread> {
+        let root = self.index.thread_root(id)?;
+        self.threads.get(&root)
+    }
+
+    /// Store a thread. Known threads that share a tweet with it are
+    /// parts of the same conversation, they are merged into one
+    pub fn insert_thread(&mut self, mut thread: Thread) {
+        let mut overlapping: Vec<TweetId> = thread
+            .tweets()
+            .iter()
+            .filter_map(|tweet| self.index.thread_root(tweet.id))
+            .collect();
+        overlapping.sort_unstable();
+        overlapping.dedup();
+        for root in overlapping {
+            let Some(known) = self.threads.remove(&root) else {
+                continue
+            };
+            for tweet in known.tweets {
+                thread.insert(tweet);
             }
         }
-        None
+        self.index.insert_thread(&thread);
+        self.threads.insert(thread.root, thread);
+    }
+
+    /// The collections that contain the tweet, e.g. whether it is also liked
+    pub fn collections(&self, id: TweetId) -> &[Collection] {
+        self.index.collections(id)
+    }
+
+    /// Record that the tweets were found on Twitter just now
+    pub fn mark_seen(&mut self, ids: impl IntoIterator<Item = TweetId>) {
+        let now = Utc::now();
+        for id in ids {
+            let meta = self.tweet_meta.entry(id).or_default();
+            meta.first_seen.get_or_insert(now);
+            meta.last_seen = Some(now);
+            meta.unavailable_since = None;
+        }
+    }
+
+    /// Record that the tweet exists on Twitter, but can't be seen. An
+    /// earlier date is kept
+    pub fn mark_unavailable(&mut self, id: TweetId, since: DateTime<Utc>) {
+        let meta = self.tweet_meta.entry(id).or_default();
+        meta.unavailable_since.get_or_insert(since);
+    }
+
+    /// Record that the tweet doesn't exist on Twitter anymore. An earlier
+    /// deletion date is kept
+    pub fn mark_deleted(&mut self, id: TweetId, deleted_at: DateTime<Utc>, via_twitvault: bool) {
+        let meta = self.tweet_meta.entry(id).or_default();
+        meta.deleted_at.get_or_insert(deleted_at);
+        meta.deleted_via_twitvault |= via_twitvault;
+    }
+
+    pub fn index(&self) -> &TweetIndex {
+        &self.index
+    }
+
+    /// Index all tweets again. Required after the collections changed
+    pub fn rebuild_index(&mut self) {
+        self.index = TweetIndex::build(self);
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Storage {
     pub root_folder: PathBuf,
-    data_path: PathBuf,
+    backend: Arc<dyn StorageBackend>,
     data: Data,
+    /// If the storage is encrypted
+    vault: Option<Vault>,
+    /// Encrypted media is decrypted into memory to display it, see `MediaCache`
+    media_cache: Option<Arc<MediaCache>>,
 }
 
 impl Storage {
-    fn storage_for_data(path: impl AsRef<Path>, data: Data) -> Result<Self> {
+    fn storage_for_data(
+        path: impl AsRef<Path>,
+        mut data: Data,
+        vault: Option<Vault>,
+    ) -> Result<Self> {
         let root_folder = path.as_ref().to_path_buf();
+        if vault.is_none() && Vault::exists(&root_folder) {
+            bail!(
+                "The storage at {} is encrypted, please unlock it first",
+                root_folder.display()
+            )
+        }
         if !root_folder.exists() {
             std::fs::create_dir(&root_folder)?;
         }
         if !root_folder.join(FOLDER_MEDIA).exists() {
             std::fs::create_dir(&root_folder.join(FOLDER_MEDIA))?;
         }
-        let data_path = root_folder.join(FILE_ROOT);
+        let backend = backend::detect(&root_folder, vault.clone());
+        data.rebuild_index();
+        let media_cache = vault.as_ref().map(|_| Arc::default());
         Ok(Storage {
             root_folder,
-            data_path,
+            backend,
             data,
+            vault,
+            media_cache,
         })
     }
 
+    pub fn media_folder(&self) -> PathBuf {
+        self.root_folder.join(FOLDER_MEDIA)
+    }
+
     pub fn media_path(&self, filename: &str) -> PathBuf {
-        self.root_folder.join(FOLDER_MEDIA).join(filename)
+        self.media_folder().join(filename)
+    }
+
+    /// Start writing a new file into the media folder
+    pub fn media_writer(&self) -> Result<MediaWriter> {
+        MediaWriter::new(&self.media_folder(), self.vault.clone())
+    }
+
+    /// The content of a file in the media folder, decrypted if necessary
+    pub fn read_media(&self, filename: &str) -> Result<Vec<u8>> {
+        crypto::read_file(&self.media_path(filename), self.vault.as_ref())
+    }
+
+    /// Store the file written by `writer` under its hash and register it for `url`
+    pub fn store_media(
+        &mut self,
+        url: &str,
+        writer: MediaWriter,
+        extension: &str,
+        mime: Option<String>,
+    ) -> Result<()> {
+        let entry = writer.finish(extension, mime)?;
+        self.data.media.insert(url.to_string(), entry);
+        Ok(())
     }
 
-    pub fn new(profile: TwitterUser, path: impl AsRef<Path>) -> Result<Self> {
+    pub fn new(profile: TwitterUser, path: impl AsRef<Path>, vault: Option<Vault>) -> Result<Self> {
         Self::storage_for_data(
             path,
             Data {
+                schema_version: SCHEMA_VERSION,
                 profile,
                 tweets: Default::default(),
                 mentions: Default::default(),
@@ -119,15 +429,59 @@ impl Storage {
                 lists: Default::default(),
                 media: Default::default(),
                 likes: Default::default(),
+                bookmarks: Default::default(),
+                conversations: Default::default(),
+                threads: Default::default(),
+                tweet_meta: Default::default(),
+                index: Default::default(),
             },
+            vault,
         )
     }
 
-    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let data_path = path.as_ref().join(FILE_ROOT);
-        let input = std::fs::read(&data_path)?;
-        let data: Data = serde_json::from_slice(&input)?;
-        Self::storage_for_data(path, data)
+    /// Whether there is a storage at `path`, even if it can't be opened
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        backend::exists(path.as_ref())
+    }
+
+    /// Open the storage at `path` with the backend the data was stored with.
+    /// An encrypted storage requires its unlocked `vault`
+    pub fn open(path: impl AsRef<Path>, vault: Option<Vault>) -> Result<Self> {
+        if vault.is_none() && Vault::exists(path.as_ref()) {
+            bail!(
+                "The storage at {} is encrypted, please unlock it first",
+                path.as_ref().display()
+            )
+        }
+        let backend = backend::detect(path.as_ref(), vault.clone());
+        let data = backend.load()?;
+        let mut storage = Self::storage_for_data(path, data, vault)?;
+        // The backend that loaded the data knows the format of the file
+        storage.backend = backend;
+        storage.apply_deletions()?;
+        Ok(storage)
+    }
+
+    /// Record that a tweet was deleted with TwitVault, see `FILE_DELETIONS`
+    pub fn record_deletion(root_folder: &Path, id: TweetId, vault: Option<&Vault>) -> Result<()> {
+        let mut deletions = read_deletions(root_folder, vault)?;
+        deletions.insert(id, Utc::now());
+        let output = crypto::encrypt_if_needed(serde_json::to_vec(&deletions)?, vault)?;
+        std::fs::write(root_folder.join(FILE_DELETIONS), output)?;
+        Ok(())
+    }
+
+    /// Mark the tweets that were deleted with TwitVault
+    pub fn apply_deletions(&mut self) -> Result<()> {
+        let deletions = read_deletions(&self.root_folder, self.vault.as_ref())?;
+        for (id, deleted_at) in deletions {
+            self.data.mark_deleted(id, deleted_at, true);
+        }
+        Ok(())
+    }
+
+    pub fn vault(&self) -> Option<&Vault> {
+        self.vault.as_ref()
     }
 
     pub fn data(&self) -> &Data {
@@ -146,25 +500,123 @@ impl Storage {
         MediaResolver {
             root_folder: self.root_folder.join(FOLDER_MEDIA),
             media: &self.data.media,
+            vault: self.vault.as_ref(),
+            cache: self.media_cache.as_deref(),
         }
     }
 
     // Blocking write
     pub fn save(&self) -> Result<()> {
-        use std::fs::OpenOptions;
-        let outfile = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&self.data_path)?;
-        Ok(serde_json::to_writer(outfile, &self.data)?)
+        self.backend.save(&self.data)
+    }
+
+    /// The backend the data is stored with
+    pub fn backend_kind(&self) -> BackendKind {
+        self.backend.kind()
+    }
+
+    /// Whether the data file is compressed
+    pub fn compressed(&self) -> bool {
+        self.backend.compressed()
+    }
+
+    /// Compress the data file with zstd from now on
+    pub fn compress(&mut self) -> Result<()> {
+        if self.backend_kind() != BackendKind::Json {
+            bail!("Only storages that aren't stored in SQLite can be compressed")
+        }
+        let backend =
+            JsonBackend::new(&self.root_folder, self.vault.clone()).with_compression(true);
+        self.backend = Arc::new(backend);
+        self.save()
+    }
+
+    /// Store the data in a SQLite database from now on. The JSON
+    /// data file is left untouched as a backup
+    pub fn migrate_to_sqlite(&mut self) -> Result<()> {
+        if self.vault.is_some() {
+            bail!("Encrypted storages can't be stored in SQLite")
+        }
+        let backend = SqliteBackend::create(&self.root_folder, &self.data)?;
+        self.backend = Arc::new(backend);
+        Ok(())
     }
+
+    /// Encrypt the data, the deletions and all media with `vault` from now on.
+    /// Previous generations of the data file are removed as they aren't encrypted.
+    /// The media files are encrypted into new files, the unencrypted ones are
+    /// only removed once the saved data refers to the new files
+    pub fn encrypt(&mut self, vault: Vault) -> Result<()> {
+        if self.backend_kind() != BackendKind::Json {
+            bail!("Only storages that aren't stored in SQLite can be encrypted")
+        }
+        let mut unencrypted = Vec::new();
+        for entry in std::fs::read_dir(self.media_folder())? {
+            let path = entry?.path();
+            if path.is_file() && !crypto::is_encrypted_file(&path)? {
+                unencrypted.push(path);
+            }
+        }
+        let mut encrypted: HashMap<String, MediaEntry> = HashMap::new();
+        for path in unencrypted.iter() {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue
+            };
+            let extension = name.rsplit_once('.').map(|(_, e)| e).unwrap_or_default();
+            let mut writer = MediaWriter::new(&self.media_folder(), Some(vault.clone()))?;
+            std::io::copy(&mut std::fs::File::open(path)?, &mut writer)?;
+            encrypted.insert(name.to_string(), writer.finish(extension, None)?);
+        }
+        for entry in self.data.media.values_mut() {
+            let Some(new_entry) = encrypted.get(&entry.file) else {
+                continue
+            };
+            entry.file = new_entry.file.clone();
+            entry.sha256 = new_entry.sha256.clone();
+            entry.size = new_entry.size;
+        }
+
+        let deletions_path = self.root_folder.join(FILE_DELETIONS);
+        if deletions_path.exists() {
+            crypto::encrypt_file(&deletions_path, &vault)?;
+        }
+        let backend = JsonBackend::new(&self.root_folder, Some(vault.clone()))
+            .with_compression(self.compressed());
+        self.backend = Arc::new(backend);
+        self.media_cache = Some(Arc::default());
+        self.vault = Some(vault);
+        self.save()?;
+        JsonBackend::remove_old_data(&self.root_folder)?;
+        for path in unencrypted {
+            crypto::remove_file_securely(&path)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_deletions(
+    root_folder: &Path,
+    vault: Option<&Vault>,
+) -> Result<HashMap<TweetId, DateTime<Utc>>> {
+    let path = root_folder.join(FILE_DELETIONS);
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    Ok(serde_json::from_slice(&crypto::read_file(&path, vault)?)?)
 }
 
+/// Decrypted media files as `data:` urls by file name. They are never
+/// written to the disk
+type MediaCache = Mutex<HashMap<String, String>>;
+
 #[allow(unused)]
 #[derive(Clone)]
 pub struct MediaResolver<'a> {
     root_folder: PathBuf,
-    media: &'a HashMap<UrlString, String>,
+    media: &'a HashMap<UrlString, MediaEntry>,
+    vault: Option<&'a Vault>,
+    /// Where encrypted media is decrypted to
+    cache: Option<&'a MediaCache>,
 }
 
 impl<'a> MediaResolver<'a> {
@@ -179,8 +631,143 @@ impl<'a> MediaResolver<'a> {
         #[cfg(not(target_os = "windows"))]
         {
             let found = self.media.get(url)?;
-            let path = self.root_folder.join(found);
-            Some(path.display().to_string())
+            let path = self.root_folder.join(&found.file);
+            let (Some(vault), Some(cache)) = (self.vault, self.cache) else {
+                return Some(path.display().to_string())
+            };
+            let mut cache = cache.lock().ok()?;
+            if let Some(decrypted) = cache.get(&found.file) {
+                return Some(decrypted.clone());
+            }
+            let content = match crypto::read_file(&path, Some(vault)) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("Could not decrypt {}: {e:?}", path.display());
+                    return None;
+                }
+            };
+            let mime = found.mime.as_deref().unwrap_or("application/octet-stream");
+            let decrypted = format!("data:{mime};base64,{}", base64::encode(content));
+            cache.insert(found.file.clone(), decrypted.clone());
+            Some(decrypted)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::helpers::placeholder_profile;
+
+    /// A tweet of the crawl fixtures with another id, replying to `reply_to`
+    fn tweet(id: TweetId, reply_to: Option<TweetId>) -> Tweet {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/crawl/timeline-user-12345.json"
+        );
+        let tweets: Vec<serde_json::Value> =
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        let mut tweet = tweets[0].clone();
+        tweet["id"] = id.into();
+        tweet["in_reply_to_status_id"] = reply_to.into();
+        serde_json::from_value(tweet).unwrap()
+    }
+
+    #[test]
+    fn any_tweet_finds_quoted_tweets_after_rebuilding_the_index() {
+        let folder = tempfile::tempdir().unwrap();
+        let profile = placeholder_profile(1, "Tester", "tester");
+        let mut storage = Storage::new(profile, folder.path().join("storage"), None).unwrap();
+        let data = storage.data_mut();
+        data.tweets.push(tweet(1, None));
+        data.rebuild_index();
+        let mut quoting = tweet(3, None);
+        quoting.quoted_status = Some(Box::new(tweet(2, None)));
+        data.tweets.insert(0, quoting);
+
+        // The index still points at the old position
+        assert!(data.any_tweet(1).is_none());
+        data.rebuild_index();
+        assert_eq!(data.any_tweet(1).map(|t| t.id), Some(1));
+        assert_eq!(data.any_tweet(2).map(|t| t.id), Some(2));
+        assert!(data.any_tweet(4).is_none());
+    }
+
+    #[test]
+    fn thread_root_is_the_oldest_tweet() {
+        let mut thread = Thread::new(tweet(3, Some(2)));
+        assert_eq!(thread.root, 3);
+        assert!(thread.insert(tweet(4, Some(3))));
+        assert_eq!(thread.root, 3);
+        assert!(thread.insert(tweet(2, Some(1))));
+        assert!(!thread.insert(tweet(4, Some(3))));
+        assert_eq!(thread.root, 2);
+        assert_eq!(thread.tweets().len(), 3);
+        let replies: Vec<TweetId> = thread.replies(2).map(|t| t.id).collect();
+        assert_eq!(replies, [3]);
+    }
+
+    #[test]
+    fn threads_sharing_a_tweet_are_merged() {
+        let folder = tempfile::tempdir().unwrap();
+        let profile = placeholder_profile(1, "Tester", "tester");
+        let mut storage = Storage::new(profile, folder.path().join("storage"), None).unwrap();
+        let data = storage.data_mut();
+        let mut first = Thread::new(tweet(2, None));
+        first.insert(tweet(3, Some(2)));
+        data.insert_thread(first);
+        data.insert_thread(Thread::new(tweet(10, None)));
+
+        // Shares 3 with the first thread and starts earlier
+        let mut second = Thread::new(tweet(1, None));
+        second.insert(tweet(2, Some(1)));
+        second.insert(tweet(3, Some(2)));
+        second.insert(tweet(4, Some(3)));
+        data.insert_thread(second);
+
+        assert_eq!(data.threads.len(), 2);
+        assert!(!data.threads.contains_key(&2));
+        let merged = &data.threads[&1];
+        assert_eq!(merged.tweets().len(), 4);
+        for id in 1..=4 {
+            assert_eq!(data.thread(id).map(|t| t.root), Some(1));
+            assert_eq!(data.any_tweet(id).map(|t| t.id), Some(id));
+        }
+        assert_eq!(data.thread(10).map(|t| t.root), Some(10));
+
+        // The index is the same as one built from scratch
+        data.rebuild_index();
+        assert_eq!(data.thread(3).map(|t| t.root), Some(1));
+        assert_eq!(data.any_tweet(4).map(|t| t.id), Some(4));
+    }
+
+    #[test]
+    fn encrypt_replaces_unencrypted_media() {
+        let folder = tempfile::tempdir().unwrap();
+        let root = folder.path().join("storage");
+        let profile = placeholder_profile(1, "Tester", "tester");
+        let mut storage = Storage::new(profile, &root, None).unwrap();
+        let url = "https://pbs.twimg.com/media/a.png";
+        let mut writer = storage.media_writer().unwrap();
+        writer.write_all(b"image").unwrap();
+        storage
+            .store_media(url, writer, "png", Some("image/png".to_string()))
+            .unwrap();
+        let unencrypted = storage.media_path(&storage.data().media[url].file);
+        storage.save().unwrap();
+
+        let vault = Vault::create(&root, "secret").unwrap();
+        storage.encrypt(vault).unwrap();
+        let entry = &storage.data().media[url];
+        assert!(!unencrypted.exists());
+        assert_ne!(storage.media_path(&entry.file), unencrypted);
+        assert!(crypto::is_encrypted_file(&storage.media_path(&entry.file)).unwrap());
+        assert_eq!(storage.read_media(&entry.file).unwrap(), b"image");
+        assert_eq!(
+            storage.resolver().resolve(url).unwrap(),
+            "data:image/png;base64,aW1hZ2U="
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! An in-memory index from tweet ids to where the tweet is stored in `Data`.
+//! It also covers the quoted and retweeted tweets inside other tweets.
+//! The index isn't stored, it is built when the storage is opened and
+//! after a crawl or an import.
+use std::collections::HashMap;
+
+use egg_mode::tweet::Tweet;
+
+use crate::storage::{Data, Thread, TweetId};
+
+/// The tweet collections of `Data`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Collection {
+    Tweets,
+    Mentions,
+    Likes,
+    Bookmarks,
+    Responses,
+    Threads,
+}
+
+impl std::fmt::Display for Collection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Collection::Tweets => f.write_str("Tweet"),
+            Collection::Mentions => f.write_str("Mention"),
+            Collection::Likes => f.write_str("Liked"),
+            Collection::Bookmarks => f.write_str("Bookmarked"),
+            Collection::Responses => f.write_str("Response"),
+            Collection::Threads => f.write_str("Thread"),
+        }
+    }
+}
+
+/// A tweet inside another tweet
+#[derive(Clone, Copy, Debug)]
+enum Inner {
+    Quoted,
+    Retweeted,
+}
+
+/// Where a tweet is stored
+#[derive(Clone, Debug)]
+struct Location {
+    collection: Collection,
+    /// The tweet the responses belong to, or the root of the thread
+    parent: Option<TweetId>,
+    position: usize,
+    /// The path to a quoted or retweeted tweet, empty for the tweet itself
+    inner: Vec<Inner>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TweetIndex {
+    locations: HashMap<TweetId, Location>,
+    /// The collections a tweet is directly part of. Quoted and
+    /// retweeted tweets are only listed if they are part of one themselves
+    collections: HashMap<TweetId, Vec<Collection>>,
+    /// The root of the thread each tweet is part of
+    thread_roots: HashMap<TweetId, TweetId>,
+}
+
+impl TweetIndex {
+    pub fn build(data: &Data) -> Self {
+        let mut index = Self::default();
+        for (collection, tweets) in [
+            (Collection::Tweets, &data.tweets),
+            (Collection::Mentions, &data.mentions),
+            (Collection::Likes, &data.likes),
+            (Collection::Bookmarks, &data.bookmarks),
+        ] {
+            for (position, tweet) in tweets.iter().enumerate() {
+                index.insert(tweet, collection, None, position);
+            }
+        }
+        for (parent, tweets) in data.responses.iter() {
+            for (position, tweet) in tweets.iter().enumerate() {
+                index.insert(tweet, Collection::Responses, Some(*parent), position);
+            }
+        }
+        for thread in data.threads.values() {
+            index.insert_thread(thread);
+        }
+        index
+    }
+
+    /// Index the tweets of a new or merged thread
+    pub fn insert_thread(&mut self, thread: &Thread) {
+        for (position, tweet) in thread.tweets().iter().enumerate() {
+            // A thread that was merged into this one
+            let merged = self
+                .locations
+                .get(&tweet.id)
+                .map_or(false, |known| known.collection == Collection::Threads);
+            if merged {
+                self.locations.remove(&tweet.id);
+            }
+            self.insert(tweet, Collection::Threads, Some(thread.root), position);
+            self.thread_roots.insert(tweet.id, thread.root);
+        }
+    }
+
+    fn insert(
+        &mut self,
+        tweet: &Tweet,
+        collection: Collection,
+        parent: Option<TweetId>,
+        position: usize,
+    ) {
+        let collections = self.collections.entry(tweet.id).or_default();
+        if !collections.contains(&collection) {
+            collections.push(collection);
+        }
+        let mut location = Location {
+            collection,
+            parent,
+            position,
+            inner: Vec::new(),
+        };
+        self.insert_location(tweet, &mut location);
+    }
+
+    /// Record the location of `tweet` and of the tweets inside it. Tweets that
+    /// are stored directly are preferred over copies inside other tweets
+    fn insert_location(&mut self, tweet: &Tweet, location: &mut Location) {
+        let known_inner = self
+            .locations
+            .get(&tweet.id)
+            .map(|known| !known.inner.is_empty());
+        match known_inner {
+            None => {
+                self.locations.insert(tweet.id, location.clone());
+            }
+            Some(true) if location.inner.is_empty() => {
+                self.locations.insert(tweet.id, location.clone());
+            }
+            _ => (),
+        }
+        for (inner, nested) in [
+            (Inner::Quoted, &tweet.quoted_status),
+            (Inner::Retweeted, &tweet.retweeted_status),
+        ] {
+            if let Some(nested) = nested {
+                location.inner.push(inner);
+                self.insert_location(nested, location);
+                location.inner.pop();
+            }
+        }
+    }
+
+    /// The collections that contain the tweet
+    pub fn collections(&self, id: TweetId) -> &[Collection] {
+        self.collections
+            .get(&id)
+            .map(|collections| collections.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The root of the thread that contains the tweet
+    pub fn thread_root(&self, id: TweetId) -> Option<TweetId> {
+        self.thread_roots.get(&id).copied()
+    }
+
+    /// The tweet from `data` at the indexed location. `None` if the index
+    /// doesn't know the tweet or if `data` changed since the index was built
+    pub fn get<'a>(&self, data: &'a Data, id: TweetId) -> Option<&'a Tweet> {
+        let location = self.locations.get(&id)?;
+        let tweets = match location.collection {
+            Collection::Tweets => &data.tweets,
+            Collection::Mentions => &data.mentions,
+            Collection::Likes => &data.likes,
+            Collection::Bookmarks => &data.bookmarks,
+            Collection::Responses => data.responses.get(&location.parent?)?,
+            Collection::Threads => data.threads.get(&location.parent?)?.tweets(),
+        };
+        let mut tweet = tweets.get(location.position)?;
+        for inner in location.inner.iter() {
+            tweet = match inner {
+                Inner::Quoted => tweet.quoted_status.as_deref()?,
+                Inner::Retweeted => tweet.retweeted_status.as_deref()?,
+            };
+        }
+        (tweet.id == id).then_some(tweet)
+    }
+}
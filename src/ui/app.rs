@@ -1,5 +1,6 @@
 #![allow(non_snake_case)]
 use std::cell::Cell;
+use std::path::PathBuf;
 
 use dioxus::desktop::tao::dpi::LogicalSize;
 use dioxus::desktop::tao::window::WindowBuilder;
@@ -7,20 +8,24 @@ use dioxus::desktop::use_window;
 use dioxus::prelude::*;
 
 use crate::config::Config;
+use crate::crypto::Vault;
 use crate::storage::Storage;
 
+use super::import_component::ImportComponent;
 use super::loading_component::LoadingComponent;
 use super::login_component::LoginComponent;
 use super::main_component::MainComponent;
 use super::setup_component::SetupComponent;
 use super::types::{LoadingState, StorageWrapper};
+use super::unlock_component::UnlockComponent;
 
-pub fn run_ui(storage: Option<Storage>, config: Option<Config>) {
+pub fn run_ui(storage: Option<Storage>, config: Option<Config>, storage_path: PathBuf) {
     dioxus::desktop::launch_with_props(
         App,
         AppProps {
             storage: Cell::new(storage),
             config: Cell::new(config),
+            storage_path,
         },
         |c| {
             c.with_window(default_menu).with_window(|w| {
@@ -43,10 +48,21 @@ pub fn run_ui(storage: Option<Storage>, config: Option<Config>) {
 struct AppProps {
     storage: Cell<Option<Storage>>,
     config: Cell<Option<Config>>,
+    /// Where a new storage is created when importing an archive
+    storage_path: PathBuf,
 }
 
 fn App(cx: Scope<AppProps>) -> Element {
-    let loading_state = use_state(&cx, LoadingState::default);
+    let loading_state = use_state(&cx, || {
+        if Vault::exists(&cx.props.storage_path) {
+            LoadingState::Locked
+        } else {
+            LoadingState::default()
+        }
+    });
+
+    // The unlocked vault if the storage is encrypted
+    let vault: &UseState<Option<Vault>> = use_state(&cx, || None);
 
     let storage: &UseState<Option<StorageWrapper>> = {
         let initial = cx.props.storage.take();
@@ -59,31 +75,42 @@ fn App(cx: Scope<AppProps>) -> Element {
     };
 
     let view = match (storage.get(), loading_state.get(), config.get()) {
-        (Some(n), _, Some(c)) => cx.render(rsx!(div {
+        // Importing can happen with or without an existing storage
+        (_, LoadingState::Importing, c) => cx.render(rsx! {
+            StartFlowContainer {
+                ImportComponent {
+                    storage: storage.clone(),
+                    storage_path: cx.props.storage_path.clone(),
+                    config: c.clone(),
+                    loading_state: loading_state.clone()
+                }
+            }
+        }),
+        // A storage can be browsed without a config (e.g. if it was imported
+        // from an archive). Only syncing and deleting require a login.
+        (Some(n), _, c) => cx.render(rsx!(div {
             MainComponent {
                 storage: n.clone(),
                 state: loading_state.clone(),
                 config: c.clone()
             }
         })),
-        (Some(_), _, None) => cx.render(rsx!(div {
-            class: "alert",
-            h3 {
-                "Error:"
-            }
-            p {
-                "Found storage but failed config "
-                "You may need to re-login"
-                button {
-                    class: "btn btn-primary",
-                    onclick: move |_| loading_state.set(LoadingState::default())
+        (None, LoadingState::Locked, _) => cx.render(rsx! {
+            StartFlowContainer {
+                UnlockComponent {
+                    storage: storage.clone(),
+                    config: config.clone(),
+                    vault: vault.clone(),
+                    storage_path: cx.props.storage_path.clone(),
+                    loading_state: loading_state.clone()
                 }
             }
-        })),
+        }),
         (None, LoadingState::Login, _) => cx.render(rsx! {
             StartFlowContainer {
                 LoginComponent {
-                    loading_state: loading_state.clone()
+                    loading_state: loading_state.clone(),
+                    vault: vault.get().clone()
                 }
             }
         }),
@@ -0,0 +1,171 @@
+#![allow(non_snake_case)]
+
+use std::path::PathBuf;
+
+use dioxus::events::*;
+use dioxus::prelude::*;
+
+use tokio::sync::mpsc::channel;
+use tracing::warn;
+
+use crate::config::Config;
+
+use crate::types::Message;
+
+use super::helpers::{Box, NextButton, Spinner};
+use super::types::LoadingState;
+use super::types::StorageWrapper;
+
+#[inline_props]
+pub fn ImportComponent(
+    cx: Scope,
+    storage: UseState<Option<StorageWrapper>>,
+    storage_path: PathBuf,
+    config: Option<Config>,
+    loading_state: UseState<LoadingState>,
+) -> Element {
+    // The archive folder or zip files, once entered
+    let paths: &UseState<Option<Vec<PathBuf>>> = use_state(&cx, || None);
+    let message_state = use_state(&cx, || Message::Initial);
+
+    use_future(&cx, paths, |paths| {
+        let existing = storage.get().as_ref().map(|s| s.storage());
+        let storage_path = storage_path.clone();
+        let config = config.clone();
+        let storage = storage.clone();
+        let message_state = message_state.clone();
+        let loading_state = loading_state.clone();
+        async move {
+            let Some(paths) = paths.get().clone() else {
+                return
+            };
+            let (sender, mut receiver) = channel(4096);
+            tokio::spawn(async move {
+                if let Err(e) = crate::importer::import_with_progress(
+                    existing,
+                    storage_path,
+                    config,
+                    paths,
+                    sender,
+                )
+                .await
+                {
+                    warn!("Error {e:?}");
+                }
+            });
+            while let Some(msg) = receiver.recv().await {
+                let finished = match msg {
+                    Message::Finished(o) => {
+                        storage.set(Some(StorageWrapper::new(o)));
+                        loading_state.set(LoadingState::default());
+                        true
+                    }
+                    other => {
+                        message_state.set(other);
+                        false
+                    }
+                };
+                if finished {
+                    break;
+                }
+            }
+        }
+    });
+
+    let cancel = rsx!(button {
+        class: "btn btn-link",
+        r#type: "button",
+        onclick: move |_| loading_state.set(LoadingState::default()),
+        "Cancel"
+    });
+
+    let ui = match (paths.get(), message_state.get()) {
+        (None, _) => rsx!(form {
+            onsubmit: |evt: FormEvent| {
+                let entered: Vec<PathBuf> = evt.values["paths"]
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .map(PathBuf::from)
+                    .collect();
+                if !entered.is_empty() {
+                    paths.set(Some(entered));
+                }
+            },
+            prevent_default: "onsubmit",
+
+            div {
+                class: "vstack gap-3",
+                p {
+                    class: "lead",
+                    "Import the Tweets, Likes, Followers, Follows, Lists and Direct Messages of a Twitter archive."
+                }
+                p {
+                    "Enter the path to the unzipped archive folder or to the archive zip file. If Twitter split your archive into multiple zip files, enter one path per line."
+                }
+                textarea {
+                    class: "form-control",
+                    id: "paths",
+                    name: "paths",
+                    rows: "3"
+                }
+                NextButton {
+                    title: "Import",
+                    kind: "submit",
+                    onclick: move |_| { },
+                }
+                cancel
+            }
+        }),
+        (Some(_), Message::Error(e)) => rsx!(div {
+            class: "alert alert-warning",
+            h3 {
+                "Uh oh. Something went wrong",
+            }
+            "{e:?}"
+            NextButton {
+                title: "Try Again",
+                kind: "button",
+                onclick: move |_| {
+                    message_state.set(Message::Initial);
+                    paths.set(None);
+                },
+            }
+        }),
+        (Some(_), Message::Loading(msg)) => rsx!(div {
+            class: "alert alert-info",
+            h3 {
+                "Importing..."
+            }
+            Spinner {
+                title: format!("{msg}")
+            }
+        }),
+        (Some(_), _) => rsx!(div {
+            class: "alert alert-info",
+            h3 {
+                "Importing..."
+            }
+        }),
+    };
+
+    let value = match paths.get() {
+        Some(_) => "Note: This can take a while for large archives.",
+        None => "Existing data is kept, the archive only adds what is missing.",
+    };
+
+    cx.render(rsx!(Box {
+        title: "Import a Twitter Archive",
+        div {
+            class: "card",
+            div {
+                class: "card-body",
+                ui
+                div {
+                    class: "alert alert-info",
+                    "{value}"
+                }
+            }
+        }
+    }))
+}
@@ -37,10 +37,12 @@ fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
     let column2 = use_atom_state(&cx, COLUMN2);
     let name = &cx.props.list.name;
     let creator = &cx.props.list.list.user.screen_name;
+    let creator_link = crate::helpers::profile_url(&cx.props.list.list.user);
     let creator_id = &cx.props.list.list.user.id;
     let subscribers = &cx.props.list.list.subscriber_count;
     let members = &cx.props.list.list.member_count;
     let description = &cx.props.list.list.description;
+    let kind = cx.props.list.kind;
 
     let twitter_button = rsx!(a {
         class: "card-link",
@@ -50,7 +52,7 @@ fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
 
     let twitter_button_a = rsx!(a {
         class: "card-link",
-        href: "https://twitter.com/{creator}",
+        href: "{creator_link}",
         "Creator on Twitter"
     });
 
@@ -69,6 +71,11 @@ fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
             h5 {
                 class: "card-title",
                 "{name}"
+                span {
+                    class: "badge bg-secondary ms-2",
+                    style: "font-size: .6rem; vertical-align: middle",
+                    "{kind}"
+                }
             }
             h6 {
                 class: "card-subtitle mb-2 text-muted",
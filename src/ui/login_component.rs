@@ -6,6 +6,7 @@ use dioxus::prelude::*;
 use tracing::warn;
 
 use crate::config::{Config, RequestData};
+use crate::crypto::Vault;
 
 use super::helpers::{Box, NextButton, Spinner};
 use super::types::LoadingState;
@@ -37,15 +38,20 @@ impl PartialEq for LoginState {
 impl Eq for LoginState {}
 
 #[inline_props]
-pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Element {
+pub fn LoginComponent(
+    cx: Scope,
+    loading_state: UseState<LoadingState>,
+    vault: Option<Vault>,
+) -> Element {
     let login_state = use_state(&cx, || LoginState::Initial);
     let current = (*login_state.current()).clone();
 
     let state_machine = use_future(&cx, login_state, move |login_state| {
         let current = (*login_state.current()).clone();
+        let vault = vault.clone();
         async move {
             match current {
-                LoginState::Initial => RequestData::request(None)
+                LoginState::Initial => RequestData::request(None, vault)
                     .await
                     .map(LoginStateResult::RequestData)
                     .unwrap_or_else(|e| LoginStateResult::Error(e.to_string())),
@@ -89,6 +95,10 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
                     login_state.set(LoginState::LoadingPin(n.clone()));
                 },
             }
+
+            ImportArchiveHint {
+                loading_state: loading_state.clone()
+            }
         }),
         (Some(LoginStateResult::RequestData(n)), LoginState::LoadingPin(_)) => rsx!(Box {
             title: "Enter Pin",
@@ -195,6 +205,10 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
                     state_machine.restart();
                 },
             }
+
+            ImportArchiveHint {
+                loading_state: loading_state.clone()
+            }
         }),
         _ => rsx!(div {
             Box {
@@ -210,3 +224,17 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
         ui
     }})
 }
+
+/// A Twitter archive can be imported without logging in
+#[inline_props]
+fn ImportArchiveHint(cx: Scope, loading_state: UseState<LoadingState>) -> Element {
+    cx.render(rsx!(p {
+        class: "mt-3 text-muted",
+        "Already downloaded your Twitter archive? "
+        a {
+            href: "#",
+            onclick: move |_| loading_state.set(LoadingState::Importing),
+            "Import it without logging in"
+        }
+    }))
+}
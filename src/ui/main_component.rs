@@ -19,6 +19,8 @@ pub enum Tab {
     Tweets,
     Mentions,
     Likes,
+    Bookmarks,
+    Messages,
     Followers,
     Follows,
     Lists,
@@ -31,6 +33,8 @@ impl std::fmt::Display for Tab {
             Tab::Tweets => f.write_str("Tweets"),
             Tab::Mentions => f.write_str("Mentions"),
             Tab::Likes => f.write_str("Likes"),
+            Tab::Bookmarks => f.write_str("Bookmarks"),
+            Tab::Messages => f.write_str("Messages"),
             Tab::Followers => f.write_str("Followers"),
             Tab::Follows => f.write_str("Follows"),
             Tab::Lists => f.write_str("Lists"),
@@ -45,6 +49,8 @@ impl Tab {
             Tab::Tweets => Shape::Home,
             Tab::Mentions => Shape::ChatAlt2,
             Tab::Likes => Shape::Heart,
+            Tab::Bookmarks => Shape::Bookmark,
+            Tab::Messages => Shape::Mail,
             Tab::Followers => Shape::Users,
             Tab::Follows => Shape::UserGroup,
             Tab::Lists => Shape::ViewList,
@@ -59,10 +65,14 @@ pub enum ColumnState {
     AnyTweet(TweetId),
     /// Responses to a tweet
     Responses(TweetId),
+    /// The thread that contains a tweet
+    Thread(TweetId),
     /// A given profile
     Profile(UserId),
     /// A list
     List(List),
+    /// The messages of the conversation with this id
+    Conversation(String),
     /// Nothing in the clumn
     None,
 }
@@ -86,7 +96,7 @@ pub fn Remainder(cx: Scope) -> Element {
 #[inline_props]
 pub fn MainComponent(
     cx: Scope,
-    config: Config,
+    config: Option<Config>,
     storage: StorageWrapper,
     state: UseState<LoadingState>,
 ) -> Element {
@@ -120,6 +130,14 @@ pub fn MainComponent(
                     label: Tab::Likes
                     selected: selected.clone()
                 }
+                NavElement {
+                    label: Tab::Bookmarks
+                    selected: selected.clone()
+                }
+                NavElement {
+                    label: Tab::Messages
+                    selected: selected.clone()
+                }
                 NavElement {
                     label: Tab::Follows
                     selected: selected.clone()
@@ -136,7 +154,8 @@ pub fn MainComponent(
                     label: Tab::Search
                     selected: selected.clone()
                 }
-                div {
+                // Syncing requires a login
+                config.as_ref().map(|config| rsx!(div {
                     class: "m-2 p-2 flex-column d-inline-flex align-items-center",
                     style: "cursor: pointer",
                     onclick: move |_| state.set(LoadingState::Loading({
@@ -154,6 +173,21 @@ pub fn MainComponent(
                         style: "font-size: .55rem",
                         "Sync"
                     }
+                }))
+                div {
+                    class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+                    style: "cursor: pointer",
+                    onclick: move |_| state.set(LoadingState::Importing),
+                    Icon {
+                        icon: Shape::Archive,
+                        fill: "white",
+                        size: 20
+                    }
+                    span {
+                        class: "text-light",
+                        style: "font-size: .55rem",
+                        "Import"
+                    }
                 }
                 div {
                     class: "mt-auto d-flex align-items-center text-center",
@@ -0,0 +1,244 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+
+use dioxus::{fermi::use_atom_state, prelude::*};
+use egg_mode::user::TwitterUser;
+
+use crate::helpers::open_file;
+use crate::media::MediaEntry;
+use crate::storage::{Conversation, DirectMessage, MediaResolver, UrlString, UserId};
+
+use super::helpers::{BottomSpacer, ShowMoreButton};
+use super::main_component::{ColumnState, COLUMN2};
+
+#[derive(Props)]
+pub struct ConversationListProps<'a> {
+    conversations: &'a [Conversation],
+    profiles: &'a HashMap<UserId, TwitterUser>,
+    /// The owner of the archive, left out of the participants
+    owner: UserId,
+}
+
+pub fn ConversationListComponent<'a>(cx: Scope<'a, ConversationListProps>) -> Element<'a> {
+    // The conversation with the newest message first
+    let mut conversations: Vec<&Conversation> = cx.props.conversations.iter().collect();
+    conversations.sort_by_key(|c| std::cmp::Reverse(c.messages.first().map(|m| m.id)));
+
+    let conversations_rendered = conversations.into_iter().map(|conversation| {
+        cx.render(rsx!(ConversationComponent {
+            conversation: conversation,
+            profiles: cx.props.profiles,
+            owner: cx.props.owner
+        }))
+    });
+
+    cx.render(rsx!(div {
+        h5 {
+            style: "margin-top: 10px; margin-bottom: 5px; margin-left: 15px; font-weight: bold; color: slategray;",
+            "Messages"
+        }
+        conversations_rendered
+        BottomSpacer {}
+    }))
+}
+
+#[derive(Props)]
+struct ConversationProps<'a> {
+    conversation: &'a Conversation,
+    profiles: &'a HashMap<UserId, TwitterUser>,
+    owner: UserId,
+}
+
+fn ConversationComponent<'a>(cx: Scope<'a, ConversationProps>) -> Element<'a> {
+    let column2 = use_atom_state(&cx, COLUMN2);
+    let conversation = cx.props.conversation;
+    let title = conversation_title(conversation, cx.props.profiles, cx.props.owner);
+    let count = conversation.messages.len();
+    let last = conversation.messages.first();
+    let date = last
+        .map(|m| m.created_at.format("%d/%m/%y %H:%M").to_string())
+        .unwrap_or_default();
+    let text = last.map(|m| m.text.as_str()).unwrap_or_default();
+
+    cx.render(rsx!(div {
+        class: "card",
+        style: "margin-bottom: 10px",
+        div {
+            class: "card-body",
+            h5 {
+                class: "card-title",
+                "{title}"
+            }
+            h6 {
+                class: "card-subtitle mb-2 text-muted",
+                "{count} Messages, last on {date}"
+            }
+            p {
+                class: "card-text text-truncate",
+                "{text}"
+            }
+        }
+        div {
+            class: "card-footer",
+            a {
+                href: "#",
+                class: "card-link",
+                onclick: move |_| column2.set(ColumnState::Conversation(conversation.id.clone())),
+                "Open"
+            }
+        }
+    }))
+}
+
+#[derive(Props)]
+pub struct MessageListProps<'a> {
+    conversation: &'a Conversation,
+    profiles: &'a HashMap<UserId, TwitterUser>,
+    media: MediaResolver<'a>,
+    /// The downloaded media, to tell images and videos apart
+    entries: &'a HashMap<UrlString, MediaEntry>,
+    owner: UserId,
+}
+
+pub fn MessageListComponent<'a>(cx: Scope<'a, MessageListProps>) -> Element<'a> {
+    let page_size = 100;
+    let page = use_state(&cx, || page_size);
+    let conversation = cx.props.conversation;
+    let title = conversation_title(conversation, cx.props.profiles, cx.props.owner);
+    let has_more = conversation.messages.len() > *page.get();
+
+    let messages_rendered = conversation
+        .messages
+        .iter()
+        .take(*page.get())
+        .map(|message| {
+            cx.render(rsx!(MessageComponent {
+                message: message,
+                profiles: cx.props.profiles,
+                media: cx.props.media.clone(),
+                entries: cx.props.entries,
+                owner: cx.props.owner
+            }))
+        });
+
+    cx.render(rsx!(div {
+        h5 {
+            style: "margin-top: 10px; margin-bottom: 5px; margin-left: 15px; font-weight: bold; color: slategray;",
+            "{title}"
+        }
+        messages_rendered
+        ShowMoreButton {
+            visible: has_more,
+            onclick: move |_| page.set(page.get() + page_size)
+        }
+        BottomSpacer {}
+    }))
+}
+
+#[derive(Props)]
+struct MessageProps<'a> {
+    message: &'a DirectMessage,
+    profiles: &'a HashMap<UserId, TwitterUser>,
+    media: MediaResolver<'a>,
+    entries: &'a HashMap<UrlString, MediaEntry>,
+    owner: UserId,
+}
+
+fn MessageComponent<'a>(cx: Scope<'a, MessageProps>) -> Element<'a> {
+    let column2 = use_atom_state(&cx, COLUMN2);
+    let message = cx.props.message;
+    let sender = display_name(cx.props.profiles, message.sender_id);
+    let date = message.created_at.format("%d/%m/%y %H:%M").to_string();
+    let class = if message.sender_id == cx.props.owner {
+        "card text-bg-primary"
+    } else {
+        "card"
+    };
+
+    // Media that wasn't downloaded can't be shown, Twitter only
+    // serves it to signed requests
+    let media_rendered = message.media.iter().filter_map(|url| {
+        let path = cx.props.media.resolve(url)?;
+        let is_video = cx
+            .props
+            .entries
+            .get(url)
+            .and_then(|entry| entry.mime.as_ref())
+            .map(|mime| mime.starts_with("video/"))
+            .unwrap_or_else(|| url.ends_with(".mp4"));
+        let rendered = if is_video {
+            rsx!(div {
+                class: "ratio ratio-16x9",
+                video {
+                    controls: "true",
+                    source {
+                        src: "{path}"
+                    }
+                }
+            })
+        } else {
+            let cloned = path.clone();
+            rsx!(img {
+                src: "{cloned}",
+                style: "cursor: pointer",
+                class: "card-img-bottom img-thumbnail",
+                onclick: move |_| open_file(&path)
+            })
+        };
+        Some(rendered)
+    });
+
+    cx.render(rsx!(div {
+        class: "{class}",
+        style: "margin-bottom: 10px",
+        div {
+            class: "card-body",
+            h6 {
+                class: "card-subtitle mb-2",
+                a {
+                    href: "#",
+                    class: "link-secondary",
+                    onclick: move |_| column2.set(ColumnState::Profile(message.sender_id)),
+                    "{sender}"
+                }
+                small {
+                    class: "ms-2 opacity-75",
+                    "{date}"
+                }
+            }
+            p {
+                class: "card-text",
+                "{message.text}"
+            }
+        }
+        media_rendered
+    }))
+}
+
+/// The name of a group conversation, or the other participants
+fn conversation_title(
+    conversation: &Conversation,
+    profiles: &HashMap<UserId, TwitterUser>,
+    owner: UserId,
+) -> String {
+    if let Some(name) = &conversation.name {
+        return name.clone();
+    }
+    let names: Vec<String> = conversation
+        .participants
+        .iter()
+        .filter(|id| **id != owner)
+        .map(|id| display_name(profiles, *id))
+        .collect();
+    if names.is_empty() {
+        return conversation.id.clone();
+    }
+    names.join(", ")
+}
+
+fn display_name(profiles: &HashMap<UserId, TwitterUser>, id: UserId) -> String {
+    profiles
+        .get(&id)
+        .map(|profile| profile.name.clone())
+        .unwrap_or_else(|| format!("User {id}"))
+}
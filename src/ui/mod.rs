@@ -1,16 +1,20 @@
 mod app;
 mod helpers;
+mod import_component;
 mod list_list;
 mod loading_component;
 mod login_component;
 mod main_component;
+mod message_list;
 mod primary_column;
 mod search_list;
 mod secondary_column;
 mod setup_component;
+mod thread_list;
 mod tweet_component;
 mod tweet_list;
 mod types;
+mod unlock_component;
 mod user_component;
 mod user_list;
 
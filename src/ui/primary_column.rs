@@ -5,9 +5,11 @@ use std::borrow::Cow;
 use dioxus::prelude::*;
 
 use crate::config::Config;
+use crate::tweet_index::Collection;
 
 use super::list_list::ListListComponent;
 use super::main_component::Tab;
+use super::message_list::ConversationListComponent;
 use super::search_list::SearchComponent;
 use super::tweet_list::TweetListComponent;
 use super::types::StorageWrapper;
@@ -18,7 +20,7 @@ pub fn MainColumn(
     cx: Scope,
     storage: StorageWrapper,
     selected: UseState<Tab>,
-    config: Config,
+    config: Option<Config>,
 ) -> Element {
     let current = (*selected.current()).clone();
     let label = current.to_string();
@@ -39,7 +41,10 @@ pub fn MainColumn(
                         label: label,
                         user: &storage.data().profile,
                         responses: &storage.data().responses,
-                        config: config
+                        meta: &storage.data().tweet_meta,
+                        index: storage.data().index(),
+                        collection: Collection::Tweets,
+                        config: config.as_ref()
                     }
                 }
             }
@@ -56,7 +61,10 @@ pub fn MainColumn(
                         label: label.clone(),
                         user: &storage.data().profile,
                         responses: &storage.data().responses,
-                        config: config
+                        meta: &storage.data().tweet_meta,
+                        index: storage.data().index(),
+                        collection: Collection::Mentions,
+                        config: config.as_ref()
                     }
                 }
             }
@@ -73,7 +81,30 @@ pub fn MainColumn(
                         label: label.clone(),
                         user: &storage.data().profile,
                         responses: &storage.data().responses,
-                        config: config
+                        meta: &storage.data().tweet_meta,
+                        index: storage.data().index(),
+                        collection: Collection::Likes,
+                        config: config.as_ref()
+                    }
+                }
+            }
+        } else {rsx!{ div { }}}}
+        {if current == Tab::Bookmarks {
+            let label = current.to_string();
+            rsx!{
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    TweetListComponent {
+                        data: &storage.data().bookmarks,
+                        media: storage.resolver(),
+                        label: label.clone(),
+                        user: &storage.data().profile,
+                        responses: &storage.data().responses,
+                        meta: &storage.data().tweet_meta,
+                        index: storage.data().index(),
+                        collection: Collection::Bookmarks,
+                        config: config.as_ref()
                     }
                 }
             }
@@ -89,7 +120,7 @@ pub fn MainColumn(
                         media: storage.resolver(),
                         profiles: &storage.data().profiles,
                         label: label.clone(),
-                        config: config
+                        config: config.as_ref()
                     }
                 }
             }
@@ -105,7 +136,7 @@ pub fn MainColumn(
                         media: storage.resolver(),
                         profiles: &storage.data().profiles,
                         label: label.clone(),
-                        config: config
+                        config: config.as_ref()
                     }
                 }
             }
@@ -121,6 +152,19 @@ pub fn MainColumn(
                 }
             }
         } else {rsx!{ div {}}}}
+        {if current == Tab::Messages {
+            rsx! {
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    ConversationListComponent {
+                        conversations: &storage.data().conversations,
+                        profiles: &storage.data().profiles,
+                        owner: storage.data().profile.id
+                    }
+                }
+            }
+        } else {rsx!{ div {}}}}
         {if current == Tab::Search {
             rsx! {
                 div {
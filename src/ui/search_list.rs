@@ -66,6 +66,13 @@ pub fn SearchComponent(cx: Scope, storage: StorageWrapper) -> Element<'a> {
                         onclick: move |_| filters.set(filters.get().change(|s| s.likes = !s.likes))
                     }
 
+                    Checkbox {
+                        label: "Bookmarks",
+                        name: "bookmarks"
+                        checked: filters.bookmarks,
+                        onclick: move |_| filters.set(filters.get().change(|s| s.bookmarks = !s.bookmarks))
+                    }
+
                     Checkbox {
                         label: "Responses",
                         name: "responses"
@@ -6,10 +6,13 @@ use dioxus::fermi::{use_atom_state, AtomState};
 use dioxus::prelude::*;
 
 use crate::config::Config;
+use crate::tweet_index::Collection;
 use crate::ui::user_list::AuthorListComponent;
 
 use super::helpers::Box;
 use super::main_component::{ColumnState, COLUMN2};
+use super::message_list::MessageListComponent;
+use super::thread_list::ThreadComponent;
 use super::tweet_component::TweetComponent;
 use super::tweet_list::TweetListComponent;
 use super::types::StorageWrapper;
@@ -20,7 +23,7 @@ pub fn SecondaryColumn(
     cx: Scope,
     storage: StorageWrapper,
     selected: AtomState<ColumnState>,
-    config: Config,
+    config: Option<Config>,
 ) -> Element {
     let column2 = use_atom_state(&cx, COLUMN2);
 
@@ -45,12 +48,34 @@ pub fn SecondaryColumn(
                         label: label,
                         user: &storage.data().profile,
                         responses: &storage.data().responses,
-                        config: config
+                        meta: &storage.data().tweet_meta,
+                        index: storage.data().index(),
+                        collection: Collection::Responses,
+                        config: config.as_ref()
                     }
                 }
             }
         } else {rsx!{ div {} }}}
 
+        {if let ColumnState::Thread(id) = column2.current().as_ref() {
+            storage.data().thread(*id).map(|thread| rsx!{
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    ThreadComponent {
+                        thread: thread,
+                        media: storage.resolver(),
+                        user: &storage.data().profile,
+                        responses: &storage.data().responses,
+                        meta: &storage.data().tweet_meta,
+                        index: storage.data().index(),
+                        selected: *id,
+                        config: config.as_ref()
+                    }
+                }
+            }).unwrap_or_else(|| rsx!{ div {} })
+        } else {rsx!{ div {} }}}
+
         {if let ColumnState::List(ref list) = column2.current().as_ref() {
             let label = format!("List: {}", list.name);
             rsx!{
@@ -62,12 +87,29 @@ pub fn SecondaryColumn(
                         media: storage.resolver(),
                         profiles: &storage.data().profiles,
                         label: label
-                        config: config
+                        config: config.as_ref()
                     }
                 }
             }
         } else {rsx!{ div {} }}}
 
+        {if let ColumnState::Conversation(id) = column2.current().as_ref() {
+            let conversation = storage.data().conversations.iter().find(|c| &c.id == id);
+            conversation.map(|conversation| rsx!{
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    MessageListComponent {
+                        conversation: conversation,
+                        profiles: &storage.data().profiles,
+                        media: storage.resolver(),
+                        entries: &storage.data().media,
+                        owner: storage.data().profile.id
+                    }
+                }
+            }).unwrap_or_else(|| rsx!{ div {} })
+        } else {rsx!{ div {} }}}
+
         {if let ColumnState::Profile(id) = column2.current().as_ref() {
             if let Some(profile) = storage.data().profiles.get(id) {
                 rsx!{
@@ -77,7 +119,7 @@ pub fn SecondaryColumn(
                         AuthorComponent {
                             profile: profile,
                             media: storage.resolver(),
-                            config: config
+                            config: config.as_ref()
                         }
                     }
                 }
@@ -110,7 +152,10 @@ pub fn SecondaryColumn(
                             media: storage.resolver(),
                             user: &storage.data().profile,
                             responses: None,
-                            config: config
+                            meta: storage.data().tweet_meta.get(&tweet.id),
+                            collections: storage.data().collections(tweet.id),
+                            listed_in: None,
+                            config: config.as_ref()
                         }
                     }
                 }
@@ -45,6 +45,27 @@ pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<Loading
                     checked: params.get().likes,
                     disabled: false
                 }
+                Checkbox {
+                    name: "Bookmarks",
+                    label: "Your bookmarks (requires the Twitter API v2)",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.bookmarks = !o.bookmarks)),
+                    checked: params.get().bookmarks,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Direct Messages",
+                    label: "Your direct messages of the last 30 days",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.direct_messages = !o.direct_messages)),
+                    checked: params.get().direct_messages,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Threads",
+                    label: "The whole conversations of your tweets and mentions",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.threads = !o.threads)),
+                    checked: params.get().threads,
+                    disabled: false
+                }
                 Checkbox {
                     name: "User Profiles",
                     label: "From Responses and Mentions",
@@ -80,6 +101,13 @@ pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<Loading
                     checked: params.get().media,
                     disabled: false
                 }
+                Checkbox {
+                    name: "Deleted Tweets",
+                    label: "When syncing, check which archived tweets were deleted on Twitter",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.check_deleted = !o.check_deleted)),
+                    checked: params.get().check_deleted,
+                    disabled: false
+                }
             }
             params.tweet_responses.then(|| {
                 rsx!(div {
@@ -0,0 +1,81 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+
+use dioxus::prelude::*;
+use egg_mode::tweet::Tweet;
+use egg_mode::user::TwitterUser;
+
+use crate::config::Config;
+use crate::storage::{MediaResolver, Thread, TweetId, TweetMeta};
+use crate::tweet_index::{Collection, TweetIndex};
+
+use super::helpers::BottomSpacer;
+use super::tweet_component::TweetComponent;
+
+/// Replies deeper than this aren't indented any further
+const MAX_INDENT: usize = 6;
+
+#[derive(Props)]
+pub struct ThreadProps<'a> {
+    thread: &'a Thread,
+    media: MediaResolver<'a>,
+    user: &'a TwitterUser,
+    responses: &'a HashMap<u64, Vec<Tweet>>,
+    meta: &'a HashMap<TweetId, TweetMeta>,
+    index: &'a TweetIndex,
+    /// The tweet the thread was opened from
+    selected: TweetId,
+    config: Option<&'a Config>,
+}
+
+pub fn ThreadComponent<'a>(cx: Scope<'a, ThreadProps>) -> Element<'a> {
+    let thread = cx.props.thread;
+    let count = thread.tweets().len();
+
+    let mut tweets = Vec::new();
+    flatten(thread, thread.root, 0, &mut tweets);
+
+    let tweets_rendered = tweets.into_iter().map(|(depth, tweet)| {
+        let responses = cx.props.responses.get(&tweet.id).as_ref().map(|e| e.len());
+        let indent = depth.min(MAX_INDENT) * 16;
+        let border = if tweet.id == cx.props.selected {
+            "border-start border-primary border-3"
+        } else {
+            ""
+        };
+        cx.render(rsx!(div {
+            class: "{border}",
+            style: "margin-left: {indent}px",
+            TweetComponent {
+                tweet: tweet,
+                media: cx.props.media.clone(),
+                user: cx.props.user
+                responses: responses,
+                meta: cx.props.meta.get(&tweet.id),
+                collections: cx.props.index.collections(tweet.id),
+                listed_in: Some(Collection::Threads),
+                config: cx.props.config
+            }
+        }))
+    });
+
+    cx.render(rsx!(div {
+        h5 {
+            style: "margin-top: 10px; margin-bottom: 5px; margin-left: 15px; font-weight: bold; color: slategray;",
+            "Thread: {count} Tweets"
+        }
+        tweets_rendered
+        BottomSpacer {}
+    }))
+}
+
+/// The tweets of the thread depth first, each reply below the tweet it replies to
+fn flatten<'a>(thread: &'a Thread, id: TweetId, depth: usize, into: &mut Vec<(usize, &'a Tweet)>) {
+    let Some(tweet) = thread.tweet(id) else {
+        return
+    };
+    into.push((depth, tweet));
+    for reply in thread.replies(id) {
+        flatten(thread, reply.id, depth + 1, into);
+    }
+}
@@ -8,8 +8,9 @@ use egg_mode::user::TwitterUser;
 
 use crate::config::Config;
 use crate::crawler::DownloadInstruction;
-use crate::helpers::{delete_tweet, open_file};
-use crate::storage::MediaResolver;
+use crate::helpers::{delete_tweet, open_file, profile_url, tweet_url};
+use crate::storage::{MediaResolver, TweetMeta};
+use crate::tweet_index::Collection;
 
 use egg_mode::tweet::Tweet;
 
@@ -22,7 +23,12 @@ pub struct TweetProps<'a> {
     media: MediaResolver<'a>,
     user: &'a TwitterUser,
     responses: Option<Option<usize>>,
-    config: &'a Config,
+    meta: Option<&'a TweetMeta>,
+    /// The collections that contain the tweet
+    collections: &'a [Collection],
+    /// The collection that is currently shown doesn't get a badge
+    listed_in: Option<Collection>,
+    config: Option<&'a Config>,
 }
 
 pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
@@ -42,17 +48,25 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
 
     let modal_id = format!("modal-{}", tweet.id);
 
-    // we can only delete our own tweets
-    let can_delete = cx.props.user.id == user.id;
+    // we can only delete our own tweets and only if we're logged in
+    let can_delete = cx.props.user.id == user.id && cx.props.config.is_some();
+
+    let tweet_link = tweet_url(user, tweet.id);
+    let author_link = profile_url(user);
+    // Placeholder profiles have no screen name
+    let handle = match user.screen_name.is_empty() {
+        true => String::new(),
+        false => format!("@{}", user.screen_name),
+    };
 
     // The deletion action
-    let cloned_config = cx.props.config.clone();
+    let cloned_config = cx.props.config.cloned();
     let deletion_tweet: &UseState<Option<u64>> = use_state(&cx, || None);
     let deletion_future = use_future(&cx, deletion_tweet, |oid| async move {
-        let Some(id) = oid.get() else {
+        let (Some(id), Some(config)) = (oid.get(), cloned_config) else {
             return None
         };
-        Some(delete_tweet(*id, &cloned_config).await)
+        Some(delete_tweet(*id, &config).await)
     });
 
     let action_dropdown = rsx! {
@@ -75,14 +89,14 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                 li {
                     a {
                         class: "dropdown-item fs-6",
-                        href: "https://twitter.com/{user.screen_name}/status/{tweet.id}",
+                        href: "{tweet_link}",
                         "Open on Twitter"
                     }
                 }
                 li {
                     a {
                         class: "dropdown-item",
-                        href: "https://twitter.com/{user.screen_name}",
+                        href: "{author_link}",
                         "Open Author on Twitter"
                     }
                 }
@@ -188,6 +202,47 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
         })
         .unwrap_or_else(|| rsx!(div {}));
 
+    let deleted_badge = cx.props.meta.and_then(|meta| {
+        let deleted = meta.deleted_at?.format("%d/%m/%y %H:%M").to_string();
+        let last_seen = meta
+            .last_seen
+            .map(|seen| format!(", last seen {}", seen.format("%d/%m/%y %H:%M")))
+            .unwrap_or_default();
+        let label = if meta.deleted_via_twitvault {
+            "Deleted with TwitVault"
+        } else {
+            "Deleted"
+        };
+        Some(rsx!(span {
+            class: "badge bg-danger",
+            title: "Deleted {deleted}{last_seen}",
+            "{label}"
+        }))
+    });
+
+    let unavailable_badge = cx.props.meta.and_then(|meta| {
+        let since = meta.unavailable_since?.format("%d/%m/%y %H:%M").to_string();
+        Some(rsx!(span {
+            class: "badge bg-warning text-dark",
+            title: "Can't be seen on Twitter since {since}, e.g. because the author is protected or suspended",
+            "Unavailable"
+        }))
+    });
+
+    let collection_badges = cx
+        .props
+        .collections
+        .iter()
+        .filter(|collection| Some(**collection) != cx.props.listed_in)
+        // Threads get a link instead
+        .filter(|collection| **collection != Collection::Threads)
+        .map(|collection| {
+            rsx!(span {
+                class: "badge bg-secondary",
+                "{collection}"
+            })
+        });
+
     let tweet_info = rsx!(
         div {
             class: "card-title d-flex flex-row justify-content-between align-items-center",
@@ -199,13 +254,16 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
             }
             span {
                 onclick: move |_| column2.set(ColumnState::Profile(user.id)),
-                "@{user.screen_name}"
+                "{handle}"
             }
             span {
                 class: "text-muted me-auto",
                 style: "font-size: 12px",
                 "{date}"
             }
+            collection_badges
+            deleted_badge
+            unavailable_badge
             action_dropdown
         }
     );
@@ -220,6 +278,18 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
         )
     });
 
+    let in_thread = cx.props.collections.contains(&Collection::Threads)
+        && cx.props.listed_in != Some(Collection::Threads);
+    let thread_link = in_thread.then(|| {
+        rsx!(
+            span {
+                class: "text-primary",
+                onclick: move |_| column2.set(ColumnState::Thread(tweet.id)),
+                "Thread"
+            }
+        )
+    });
+
     let tweet_actions = rsx!(div {
         style: "margin-bottom: 8px;",
         small {
@@ -235,6 +305,8 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
             " "
             tweet_responses
             " "
+            thread_link
+            " "
             }
     });
 
@@ -248,6 +320,9 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                     media: cx.props.media.clone(),
                     user: cx.props.user
                     responses: None
+                    meta: None,
+                    collections: &[],
+                    listed_in: None,
                     config: cx.props.config
                 }
             })
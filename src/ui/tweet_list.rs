@@ -5,7 +5,8 @@ use dioxus::prelude::*;
 use egg_mode::user::TwitterUser;
 
 use crate::config::Config;
-use crate::storage::MediaResolver;
+use crate::storage::{MediaResolver, TweetId, TweetMeta};
+use crate::tweet_index::{Collection, TweetIndex};
 
 use egg_mode::tweet::Tweet;
 
@@ -18,8 +19,12 @@ pub struct TweetListProps<'a> {
     media: MediaResolver<'a>,
     user: &'a TwitterUser,
     responses: &'a HashMap<u64, Vec<Tweet>>,
+    meta: &'a HashMap<TweetId, TweetMeta>,
+    index: &'a TweetIndex,
+    /// The collection `data` belongs to
+    collection: Collection,
     label: String,
-    config: &'a Config,
+    config: Option<&'a Config>,
 }
 
 pub fn TweetListComponent<'a>(cx: Scope<'a, TweetListProps>) -> Element<'a> {
@@ -39,6 +44,9 @@ pub fn TweetListComponent<'a>(cx: Scope<'a, TweetListProps>) -> Element<'a> {
             media: cx.props.media.clone(),
             user: cx.props.user
             responses: responses,
+            meta: cx.props.meta.get(&tweet.id),
+            collections: cx.props.index.collections(tweet.id),
+            listed_in: Some(cx.props.collection),
             config: cx.props.config
         }))
     });
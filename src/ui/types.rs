@@ -13,6 +13,10 @@ pub enum LoadingState {
     Setup(Config),
     Loading(Config),
     Loaded(StorageWrapper, Config),
+    /// Import a Twitter archive. Works with and without an existing storage
+    Importing,
+    /// The storage is encrypted and has to be unlocked first
+    Locked,
 }
 
 impl PartialEq for LoadingState {
@@ -42,6 +46,11 @@ impl StorageWrapper {
         }
     }
 
+    /// A copy of the storage, e.g. to import into it
+    pub fn storage(&self) -> Storage {
+        self.data.as_ref().clone()
+    }
+
     pub fn data(&self) -> &Data {
         self.data.data()
     }
@@ -0,0 +1,115 @@
+#![allow(non_snake_case)]
+
+use std::path::PathBuf;
+
+use dioxus::events::*;
+use dioxus::prelude::*;
+
+use crate::config::Config;
+use crate::crypto::Vault;
+use crate::storage::Storage;
+
+use super::helpers::{Box, NextButton, Spinner};
+use super::types::{LoadingState, StorageWrapper};
+
+#[derive(Clone)]
+enum UnlockResult {
+    Unlocked(Vault, Option<Storage>, Option<Config>),
+    Error(String),
+}
+
+#[inline_props]
+pub fn UnlockComponent(
+    cx: Scope,
+    storage: UseState<Option<StorageWrapper>>,
+    config: UseState<Option<Config>>,
+    vault: UseState<Option<Vault>>,
+    storage_path: PathBuf,
+    loading_state: UseState<LoadingState>,
+) -> Element {
+    let passphrase: &UseState<Option<String>> = use_state(&cx, || None);
+
+    let unlocked = use_future(&cx, passphrase, |passphrase| {
+        let storage_path = storage_path.clone();
+        async move {
+            let passphrase = passphrase.get().clone()?;
+            // Deriving the key takes a moment
+            let result = tokio::task::spawn_blocking(move || {
+                let vault = Vault::unlock(&storage_path, &passphrase)?;
+                // Only a missing storage continues with the login, a storage that
+                // can't be opened would be overwritten by the crawl
+                let storage = if Storage::exists(&storage_path) {
+                    Some(Storage::open(&storage_path, Some(vault.clone()))?)
+                } else {
+                    None
+                };
+                let config = Config::open(Some(storage_path), Some(vault.clone())).ok();
+                Ok::<_, eyre::Report>((vault, storage, config))
+            })
+            .await;
+            Some(match result {
+                Ok(Ok((vault, storage, config))) => UnlockResult::Unlocked(vault, storage, config),
+                Ok(Err(e)) => UnlockResult::Error(e.to_string()),
+                Err(e) => UnlockResult::Error(e.to_string()),
+            })
+        }
+    });
+
+    let ui = match (passphrase.get(), unlocked.value()) {
+        (None, _) => rsx!(form {
+            onsubmit: |evt: FormEvent| {
+                let entered = evt.values["passphrase"].to_string();
+                if !entered.is_empty() {
+                    passphrase.set(Some(entered));
+                }
+            },
+            prevent_default: "onsubmit",
+
+            div {
+                class: "vstack gap-3",
+                p {
+                    class: "lead",
+                    "This archive is encrypted. Please enter your passphrase to open it."
+                }
+                input {
+                    class: "form-control",
+                    "type": "password",
+                    id: "passphrase",
+                    name: "passphrase"
+                }
+                NextButton {
+                    title: "Unlock",
+                    kind: "submit",
+                    onclick: move |_| { },
+                }
+            }
+        }),
+        (Some(_), Some(Some(UnlockResult::Error(e)))) => rsx!(div {
+            class: "alert alert-danger",
+            "{e}"
+            NextButton {
+                title: "Try Again",
+                kind: "button",
+                onclick: move |_| passphrase.set(None),
+            }
+        }),
+        (Some(_), Some(Some(UnlockResult::Unlocked(unlocked_vault, opened, opened_config)))) => {
+            vault.set(Some(unlocked_vault.clone()));
+            config.set(opened_config.clone());
+            // Without data (e.g. because the first crawl didn't finish), continue with the login
+            if let Some(opened) = opened {
+                storage.set(Some(StorageWrapper::new(opened.clone())));
+            }
+            loading_state.set(LoadingState::default());
+            rsx!(span {})
+        }
+        (Some(_), _) => rsx!(Spinner {
+            title: "Unlocking".to_string()
+        }),
+    };
+
+    cx.render(rsx!(Box {
+        title: "Unlock TwitVault",
+        ui
+    }))
+}
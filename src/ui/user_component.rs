@@ -14,7 +14,7 @@ use super::tweet_component::TweetComponent;
 pub struct AuthorProps<'a> {
     profile: &'a TwitterUser,
     media: MediaResolver<'a>,
-    config: &'a Config,
+    config: Option<&'a Config>,
 }
 
 pub fn AuthorComponent<'a>(cx: Scope<'a, AuthorProps>) -> Element<'a> {
@@ -70,9 +70,10 @@ pub fn AuthorComponent<'a>(cx: Scope<'a, AuthorProps>) -> Element<'a> {
                 "Link: {domain}"
             })
         });
+    let author_link = crate::helpers::profile_url(author);
     let twitter_button = rsx!(a {
         class: "btn btn-secondary btn-sm",
-        href: "https://twitter.com/{author.screen_name}",
+        href: "{author_link}",
         "On Twitter"
     });
     let quoted = author
@@ -85,6 +86,9 @@ pub fn AuthorComponent<'a>(cx: Scope<'a, AuthorProps>) -> Element<'a> {
                     media: cx.props.media.clone(),
                     user: cx.props.profile
                     responses: None,
+                    meta: None,
+                    collections: &[],
+                    listed_in: None,
                     config: cx.props.config
                 }
             })
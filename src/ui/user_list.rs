@@ -16,7 +16,7 @@ pub struct AuthorListProps<'a> {
     media: MediaResolver<'a>,
     profiles: &'a HashMap<u64, TwitterUser>,
     label: String,
-    config: &'a Config,
+    config: Option<&'a Config>,
 }
 
 pub fn AuthorListComponent<'a>(cx: Scope<'a, AuthorListProps>) -> Element<'a> {